@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use cgmath::{vec3, InnerSpace, Vector3};
+
+use crate::{environment::EnvironmentMap, Color};
+
+// What a ray sees when it escapes the scene.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    // nothing, all light has to come from emissive materials
+    None,
+    Constant(Color),
+    // blends linearly along the ray's y, from `horizon` looking straight down
+    // to `zenith` straight up, so the horizon itself is an even mix of both
+    Gradient { horizon: Color, zenith: Color },
+    // an HDR image, which is also sampled as a light
+    Environment(Arc<EnvironmentMap>),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Gradient {
+            horizon: vec3(1., 1., 1.),
+            zenith: vec3(0.5, 0.7, 1.),
+        }
+    }
+}
+
+impl Background {
+    pub fn value(&self, dir: Vector3<f64>) -> Color {
+        match self {
+            Background::None => vec3(0., 0., 0.),
+            Background::Constant(color) => *color,
+            Background::Gradient { horizon, zenith } => {
+                let t = 0.5 * (dir.normalize().y + 1.);
+                (1. - t) * horizon + t * zenith
+            }
+            Background::Environment(map) => map.value(dir),
+        }
+    }
+
+    // whether the scene's lights sample it
+    pub fn is_light(&self) -> bool {
+        matches!(self, Background::Environment(_))
+    }
+}
@@ -0,0 +1,140 @@
+#![allow(clippy::needless_return)]
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use cgmath::{vec3, ElementWise, InnerSpace, Vector3};
+use ray_tracer::{
+    rng::Rng,
+    scene::description::{
+        ApertureDescription, BackgroundDescription, CameraDescription, IorDescription,
+        MaterialDescription, ObjectDescription, Projection, RenderSettings, SceneDescription,
+        TextureOrColor,
+    },
+    vector_additon::VectorAdditions,
+};
+
+fn random_scene(rng: &mut Rng) -> SceneDescription {
+    let mut materials = BTreeMap::new();
+    let mut objects = Vec::new();
+    let mut sphere = |name: String, center: [f64; 3], radius: f64, material| {
+        materials.insert(name.clone(), material);
+        objects.push(ObjectDescription::Sphere {
+            center,
+            radius,
+            material: name,
+        });
+    };
+
+    sphere(
+        "ground".to_string(),
+        [0., -1000., 0.],
+        1000.,
+        MaterialDescription::Lambertian {
+            albedo: TextureOrColor::Color([0.5, 0.5, 0.5]),
+        },
+    );
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng.uniform();
+            let center = vec3(
+                a as f64 + 0.9 * rng.uniform(),
+                0.2,
+                b as f64 + 0.9 * rng.uniform(),
+            );
+            if (center - vec3(4., 0.2, 0.)).magnitude() > 0.9 {
+                let material = if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo: Vector3<f64> = Vector3::random(0. ..1., rng)
+                        .mul_element_wise(Vector3::random(0. ..1., rng));
+                    MaterialDescription::Lambertian {
+                        albedo: TextureOrColor::Color(albedo.into()),
+                    }
+                } else if choose_mat < 0.95 {
+                    // metal
+                    MaterialDescription::Metal {
+                        albedo: TextureOrColor::Color(Vector3::random(0. ..0.5, rng).into()),
+                        fuzz: rng.range(0. ..0.5),
+                    }
+                } else {
+                    // glass
+                    MaterialDescription::Dielectric {
+                        ir: IorDescription::Constant(1.5),
+                    }
+                };
+                sphere(format!("sphere_{}_{}", a, b), center.into(), 0.2, material);
+            }
+        }
+    }
+
+    sphere(
+        "material1".to_string(),
+        [0., 1., 0.],
+        1.,
+        MaterialDescription::Dielectric {
+            ir: IorDescription::Constant(1.5),
+        },
+    );
+    sphere(
+        "material2".to_string(),
+        [-4., 1., 0.],
+        1.,
+        MaterialDescription::Lambertian {
+            albedo: TextureOrColor::Color([0.4, 0.2, 0.1]),
+        },
+    );
+    sphere(
+        "material3".to_string(),
+        [4., 1., 0.],
+        1.,
+        MaterialDescription::Metal {
+            albedo: TextureOrColor::Color([0.7, 0.6, 0.5]),
+            fuzz: 0.,
+        },
+    );
+
+    return SceneDescription {
+        render: RenderSettings::default(),
+        background: BackgroundDescription::default(),
+        fog: None,
+        camera: CameraDescription {
+            lookfrom: [13., 2., 3.],
+            lookat: [0., 0., 0.],
+            vup: [0., 1., 0.],
+            projection: Projection::Perspective,
+            vfov: 20.,
+            aperture: 0.1,
+            aperture_shape: ApertureDescription::Circle,
+            cats_eye: 0.,
+            focus_dist: Some(10.),
+            tilt: 0.,
+            swing: 0.,
+            shift: [0., 0.],
+            shutter: None,
+            animation: None,
+        },
+        textures: BTreeMap::new(),
+        materials,
+        prototypes: BTreeMap::new(),
+        objects,
+    };
+}
+
+// Writes a copy of the classic random spheres scene, to the path given as the
+// first argument or to stdout. The optional second argument seeds the layout.
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().filter(|path| path != "-");
+    let seed = match args.next() {
+        Some(seed) => seed
+            .parse()
+            .with_context(|| format!("invalid seed `{}`", seed))?,
+        None => 0,
+    };
+    let json = random_scene(&mut Rng::new(seed)).to_json()?;
+    match path {
+        Some(path) => std::fs::write(path, json)?,
+        None => println!("{}", json),
+    }
+    return Ok(());
+}
@@ -1,108 +1,143 @@
-use anyhow::Result;
-use cgmath::{vec3, Deg, ElementWise, EuclideanSpace, InnerSpace, Point3, Vector3};
+#![allow(clippy::needless_return)]
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
 use ray_tracer::{
-    camera::Camera,
-    image::PPMImageWriter,
-    material::{Dielectric, Lambertian, Metal},
-    random,
-    ray::hittable::{HittableList, Sphere},
-    vector_additon::VectorAdditions,
+    image,
+    render::{self, checkpoint_path, frame_path, is_finished, parse_frames, render_pass, Film},
+    scene::{description::SceneDescription, Scene},
 };
-use rayon::prelude::*;
 
-fn random_scene() -> HittableList {
-    let mut world = HittableList::default();
-    let ground_material = Lambertian::new(vec3(0.5, 0.5, 0.5));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0., -1000., 0.),
-        1000.,
-        ground_material,
-    )));
+const USAGE: &str =
+    "usage: ray-tracer <scene.json> [output.(ppm|png|hdr|exr)] [--frames <first>-<last>]";
 
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random(0. ..1.);
-            let center = Point3::new(
-                a as f64 + 0.9 * random(0. ..1.),
-                0.2,
-                b as f64 + 0.9 * random(0. ..1.),
-            );
-            if (center.to_vec() - vec3(4., 0.2, 0.)).magnitude() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo: Vector3<f64> =
-                        Vector3::random(0. ..1.).mul_element_wise(Vector3::random(0. ..1.));
-                    world.add(Box::new(Sphere::new(center, 0.2, Lambertian::new(albedo))));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Vector3::random(0. ..0.5);
-                    let fuzz = random(0. ..0.5);
-                    world.add(Box::new(Sphere::new(center, 0.2, Metal::new(albedo, fuzz))));
-                } else {
-                    // glass
-                    world.add(Box::new(Sphere::new(center, 0.2, Dielectric::new(1.5))));
-                }
+fn average_count(film: &Film) -> f64 {
+    return film.total_count() as f64 / (film.width() * film.height()) as f64;
+}
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let mut positional = Vec::new();
+    let mut frames = None;
+    while let Some(arg) = args.next() {
+        if arg == "--frames" {
+            let Some(range) = args.next() else {
+                bail!(USAGE);
+            };
+            frames = Some(parse_frames(&range)?);
+        } else {
+            positional.push(arg);
+        }
+    }
+    let (scene_path, output) = match positional.as_slice() {
+        [scene_path] => (scene_path, None),
+        [scene_path, output] => (scene_path, Some(output.clone())),
+        _ => bail!(USAGE),
+    };
+
+    let Some((first, last)) = frames else {
+        let scene = Scene::load(scene_path)?;
+        // a still of an animation is its frame 0
+        let still = |path: String| {
+            if path.contains('#') {
+                return frame_path(&path, 0);
             }
+            return path;
+        };
+        let output = still(output.unwrap_or_else(|| scene.settings.output.clone()));
+        let heatmap = scene
+            .settings
+            .adaptive
+            .as_ref()
+            .and_then(|a| a.heatmap.clone())
+            .map(still);
+        return render(&scene, &output, heatmap.as_deref());
+    };
+    let settings = SceneDescription::load(scene_path)?.render;
+    let output = output.unwrap_or(settings.output);
+    let heatmap = settings.adaptive.and_then(|a| a.heatmap);
+    for frame in first..=last {
+        let output = frame_path(&output, frame);
+        if is_finished(&output) {
+            eprintln!("skipping frame {}, {} exists", frame, output);
+            continue;
         }
+        eprintln!("rendering frame {} to {}", frame, output);
+        let scene = Scene::load_frame(scene_path, frame as f64)?;
+        let heatmap = heatmap.as_ref().map(|heatmap| frame_path(heatmap, frame));
+        render(&scene, &output, heatmap.as_deref())?;
     }
-    let material1 = Dielectric::new(1.5);
-    world.add(Box::new(Sphere::new(
-        Point3::new(0., 1., 0.),
-        1.,
-        material1,
-    )));
-    let material2 = Lambertian::new(vec3(0.4, 0.2, 0.1));
-    world.add(Box::new(Sphere::new(
-        Point3::new(-4., 1., 0.),
-        1.,
-        material2,
-    )));
-    let material3 = Metal::new(vec3(0.7, 0.6, 0.5), 0.);
-    world.add(Box::new(Sphere::new(
-        Point3::new(4., 1., 0.),
-        1.,
-        material3,
-    )));
-    return world;
+    return Ok(());
 }
 
-fn main() -> Result<()> {
-    const ASPECT_RATIO: f64 = 3. / 2.;
-    const IMAGE_WIDTH: u32 = 1200;
-    const SAMPLES_PER_PIXEL: u32 = 500;
-    const MAX_DEPTH: i32 = 50;
-
-    let image = PPMImageWriter::new("image.ppm", IMAGE_WIDTH, ASPECT_RATIO, SAMPLES_PER_PIXEL)?;
+fn render(scene: &Scene, output: &str, heatmap: Option<&str>) -> Result<()> {
+    let settings = &scene.settings;
+    // check the extension before spending hours rendering
+    if settings.output_format.is_none() {
+        image::ImageFormat::from_path(output)?;
+    }
+    if let Some(heatmap) = heatmap {
+        image::ImageFormat::from_path(heatmap)?;
+    }
 
-    // World
-    let world = random_scene();
-    // Camera
-    let lookfrom = Point3::new(13., 2., 3.);
-    let lookat = Point3::new(0., 0., 0.);
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vec3(0., 1., 0.),
-        Deg(20.).into(),
-        ASPECT_RATIO,
-        0.1,
-        10.,
-    );
+    let checkpoint = checkpoint_path(output);
+    let mut film = if Path::new(&checkpoint).exists() {
+        let film = Film::resume(&checkpoint, scene)?;
+        eprintln!(
+            "resuming from {} at {:.1} samples per pixel",
+            checkpoint,
+            average_count(&film)
+        );
+        film
+    } else {
+        Film::for_scene(scene)
+    };
 
-    image.write(|j, i, w| {
-        (0..w.samples_per_pixel)
-            .into_par_iter()
-            .fold(
-                || vec3(0., 0., 0.),
-                |acc, _| {
-                    let u = (i as f64 + random(0. ..1.)) / (w.image_width - 1) as f64;
-                    let v = (j as f64 + random(0. ..1.)) / (w.image_height - 1) as f64;
-                    let color = cam.get_ray(u, v).color(&world, MAX_DEPTH);
-                    acc + color
-                },
-            )
-            .sum()
-    })?;
+    let start = Instant::now();
+    let deadline = settings
+        .time_limit
+        .map(|limit| start + Duration::from_secs_f64(limit));
+    let interval = Duration::from_secs_f64(settings.checkpoint_interval);
+    let mut last_checkpoint = start;
+    let finished = loop {
+        if render_pass(scene, &mut film, deadline) {
+            break true;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            eprintln!(
+                "\ntime limit reached at {:.1} samples per pixel",
+                average_count(&film)
+            );
+            break false;
+        }
+        if last_checkpoint.elapsed() >= interval {
+            film.save(&checkpoint)?;
+            image::save(&render::image(scene, &film), output, settings.output_format)?;
+            last_checkpoint = Instant::now();
+        }
+    };
 
+    image::save(&render::image(scene, &film), output, settings.output_format)?;
+    if let Some(heatmap) = heatmap {
+        image::save(&film.heatmap(settings.samples_per_pixel), heatmap, None)?;
+    }
+    for &aov in settings.aovs.iter() {
+        // floats keep negative normals and large IDs intact
+        let path = Path::new(output).with_extension(format!("{}.exr", aov.name()));
+        if let Some(buffer) = film.aov(aov) {
+            image::save(&buffer, path, None)?;
+        }
+    }
+    if finished {
+        if Path::new(&checkpoint).exists() {
+            std::fs::remove_file(&checkpoint)?;
+        }
+    } else {
+        film.save(&checkpoint)?;
+        eprintln!("run again to continue from {}", checkpoint);
+    }
     return Ok(());
 }
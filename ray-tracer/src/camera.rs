@@ -1,8 +1,114 @@
+use std::f64::consts::PI;
+
 use cgmath::{vec3, Angle, InnerSpace, Point3, Rad, Vector3};
 
-use crate::{ray::Ray, vector_additon::VectorAdditions};
+use crate::{
+    distribution::Distribution2D, image::Framebuffer, ray::Ray, render::luminance,
+    sampler::Sampler, vector_additon::VectorAdditions,
+};
+
+pub trait Camera: Send + Sync {
+    // the ray through (s, t) on the image, both from 0 to 1 with t going up,
+    // or None where the camera sees nothing, like outside a fisheye's circle
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray>;
+}
+
+// u points right, v up and w backwards, away from lookat
+fn basis(
+    lookfrom: Point3<f64>,
+    lookat: Point3<f64>,
+    vup: Vector3<f64>,
+) -> (Vector3<f64>, Vector3<f64>, Vector3<f64>) {
+    let w = (lookfrom - lookat).normalize();
+    let u = vup.cross(w).normalize();
+    let v = w.cross(u);
+    return (u, v, w);
+}
+
+// rays are spread evenly over the time the shutter is open
+#[derive(Debug, Clone, Copy, Default)]
+struct Shutter {
+    open: f64,
+    close: f64,
+}
+
+impl Shutter {
+    fn time(&self, sampler: &mut dyn Sampler) -> f64 {
+        if self.close > self.open {
+            return self.open + (self.close - self.open) * sampler.get_1d();
+        }
+        return self.open;
+    }
+}
+
+// The shape of the lens opening, which is the shape of out of focus
+// highlights.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Aperture {
+    Circle,
+    // a regular polygon in the circle, with a corner `rotation` counter
+    // clockwise from the right
+    Polygon {
+        blades: u32,
+        rotation: Rad<f64>,
+    },
+    // light gets through the square around the circle in proportion to the
+    // brightness of an image fitted into it
+    Mask {
+        distribution: Distribution2D,
+        scale: [f64; 2],
+    },
+}
+
+impl Aperture {
+    pub fn mask(image: &Framebuffer) -> Self {
+        let (width, height) = (image.width() as f64, image.height() as f64);
+        let weights: Vec<f64> = image
+            .pixels()
+            .iter()
+            .map(|&c| luminance(c).max(0.))
+            .collect();
+        return Aperture::Mask {
+            distribution: Distribution2D::new(
+                &weights,
+                image.width() as usize,
+                image.height() as usize,
+            ),
+            scale: [width / width.max(height), height / width.max(height)],
+        };
+    }
+
+    // a point on the unit lens
+    fn sample(&self, u: [f64; 2]) -> Vector3<f64> {
+        match self {
+            Aperture::Circle => Vector3::sample_unit_disk(u),
+            Aperture::Polygon { blades, rotation } => {
+                // a uniform point in one of the triangles between the
+                // centre and two neighbouring corners
+                let n = *blades as f64;
+                let i = (u[0] * n).floor().min(n - 1.);
+                let (a, b) = (u[0] * n - i, u[1].sqrt());
+                let corner = |k: f64| {
+                    let angle = rotation.0 + 2. * PI * k / n;
+                    vec3(angle.cos(), angle.sin(), 0.)
+                };
+                b * ((1. - a) * corner(i) + a * corner(i + 1.))
+            }
+            Aperture::Mask {
+                distribution,
+                scale,
+            } => {
+                // the image's top row is up
+                let ([x, y], _) = distribution.sample(u);
+                vec3((2. * x - 1.) * scale[0], (1. - 2. * y) * scale[1], 0.)
+            }
+        }
+    }
+}
 
-pub struct Camera {
+// A thin lens camera, everything on the focus plane is sharp. The plane is
+// focus_dist in front of the camera, facing it unless tilted.
+pub struct PerspectiveCamera {
     origin: Point3<f64>,
     lower_left_corner: Point3<f64>,
     horizontal: Vector3<f64>,
@@ -10,10 +116,17 @@ pub struct Camera {
     u: Vector3<f64>,
     v: Vector3<f64>,
     w: Vector3<f64>,
+    aspect_ratio: f64,
+    focus_dist: f64,
     lens_radius: f64,
+    aperture: Aperture,
+    cats_eye: f64,
+    // the tilted focus plane's normal
+    focus_normal: Option<Vector3<f64>>,
+    shutter: Shutter,
 }
 
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(
         lookfrom: Point3<f64>,
         lookat: Point3<f64>,
@@ -27,9 +140,7 @@ impl Camera {
         let viewport_height = 2. * h;
         let viewport_width = aspect_ratio * viewport_height;
 
-        let w = (lookfrom - lookat).normalize();
-        let u = vup.cross(w).normalize();
-        let v = w.cross(u);
+        let (u, v, w) = basis(lookfrom, lookat, vup);
 
         let horizontal = focus_dist * viewport_width * u;
         let vertical = focus_dist * viewport_height * v;
@@ -44,16 +155,424 @@ impl Camera {
             u,
             v,
             w,
+            aspect_ratio,
+            focus_dist,
             lens_radius,
+            aperture: Aperture::Circle,
+            cats_eye: 0.,
+            focus_normal: None,
+            shutter: Shutter::default(),
         };
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vector3::random_in_unit_disk();
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter = Shutter { open, close };
+        return self;
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        return self;
+    }
+
+    // Mechanical vignetting: towards the edges of the image the lens is
+    // also cut off by a second circle, moved off centre by `cats_eye` times
+    // the lens radius at the corners. The lens sees less light there and
+    // out of focus highlights turn into cat's eyes.
+    pub fn with_cats_eye(mut self, cats_eye: f64) -> Self {
+        self.cats_eye = cats_eye;
+        return self;
+    }
+
+    // Turns the focus plane `tilt` around the horizontal axis, so its top
+    // leans away, and then `swing` around the vertical one, so its right
+    // side does. Like a tilt-shift lens, this can keep a whole receding
+    // floor sharp or, against it, only a thin band of it.
+    pub fn with_tilt(mut self, tilt: Rad<f64>, swing: Rad<f64>) -> Self {
+        self.focus_normal = if tilt.0 == 0. && swing.0 == 0. {
+            None
+        } else {
+            Some(tilt.cos() * (swing.cos() * self.w + swing.sin() * self.u) + tilt.sin() * self.v)
+        };
+        return self;
+    }
+
+    // Moves the image across the view without turning the camera, by
+    // fractions of its width and height, so verticals stay parallel.
+    pub fn with_shift(mut self, x: f64, y: f64) -> Self {
+        self.lower_left_corner += x * self.horizontal + y * self.vertical;
+        return self;
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let lens = self.aperture.sample(sampler.get_2d());
+        let time = self.shutter.time(sampler);
+        if self.cats_eye > 0. {
+            // 1 in the corners
+            let diagonal = (self.aspect_ratio * self.aspect_ratio + 1.).sqrt();
+            let centre = vec3((2. * s - 1.) * self.aspect_ratio, 2. * t - 1., 0.) / diagonal;
+            if (lens - self.cats_eye * centre).magnitude2() > 1. {
+                return None;
+            }
+        }
+        let rd = self.lens_radius * lens;
         let offset = self.u * rd.x + self.v * rd.y;
-        return Ray::new(
+        let towards =
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin;
+        let Some(normal) = self.focus_normal else {
+            return Some(Ray::with_time(self.origin + offset, towards - offset, time));
+        };
+        // where the ray through the centre of the lens meets the focus plane,
+        // which goes through the point focus_dist straight ahead
+        let facing = normal.dot(towards);
+        if facing >= 0. {
+            // the plane is behind, this part of the image is focused at infinity
+            return Some(Ray::with_time(self.origin + offset, towards, time));
+        }
+        let along = -self.focus_dist * normal.dot(self.w) / facing;
+        return Some(Ray::with_time(
             self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+            along * towards - offset,
+            time,
+        ));
+    }
+}
+
+// Parallel rays from a `height` by `height * aspect_ratio` rectangle centred
+// on lookfrom, so sizes don't shrink with distance.
+pub struct OrthographicCamera {
+    lower_left_corner: Point3<f64>,
+    horizontal: Vector3<f64>,
+    vertical: Vector3<f64>,
+    direction: Vector3<f64>,
+    shutter: Shutter,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        lookfrom: Point3<f64>,
+        lookat: Point3<f64>,
+        vup: Vector3<f64>,
+        height: f64,
+        aspect_ratio: f64,
+    ) -> Self {
+        let (u, v, w) = basis(lookfrom, lookat, vup);
+        let horizontal = height * aspect_ratio * u;
+        let vertical = height * v;
+        return Self {
+            lower_left_corner: lookfrom - horizontal / 2. - vertical / 2.,
+            horizontal,
+            vertical,
+            direction: -w,
+            shutter: Shutter::default(),
+        };
+    }
+
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter = Shutter { open, close };
+        return self;
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let time = self.shutter.time(sampler);
+        return Some(Ray::with_time(
+            self.lower_left_corner + s * self.horizontal + t * self.vertical,
+            self.direction,
+            time,
+        ));
+    }
+}
+
+// An equidistant fisheye: the angle from the view direction grows linearly
+// with the distance from the image centre, reaching fov / 2 on the largest
+// circle that fits the image. The fov can go up to 360 degrees.
+pub struct FisheyeCamera {
+    origin: Point3<f64>,
+    u: Vector3<f64>,
+    v: Vector3<f64>,
+    w: Vector3<f64>,
+    fov: Rad<f64>,
+    aspect_ratio: f64,
+    shutter: Shutter,
+}
+
+impl FisheyeCamera {
+    pub fn new(
+        lookfrom: Point3<f64>,
+        lookat: Point3<f64>,
+        vup: Vector3<f64>,
+        fov: Rad<f64>,
+        aspect_ratio: f64,
+    ) -> Self {
+        let (u, v, w) = basis(lookfrom, lookat, vup);
+        return Self {
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            fov,
+            aspect_ratio,
+            shutter: Shutter::default(),
+        };
+    }
+
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter = Shutter { open, close };
+        return self;
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let time = self.shutter.time(sampler);
+        // in units of the circle's radius
+        let (mut x, mut y) = (2. * s - 1., 2. * t - 1.);
+        if self.aspect_ratio >= 1. {
+            x *= self.aspect_ratio;
+        } else {
+            y /= self.aspect_ratio;
+        }
+        let r = (x * x + y * y).sqrt();
+        if r > 1. {
+            return None;
+        }
+        let theta = r * self.fov.0 / 2.;
+        let phi = y.atan2(x);
+        let direction =
+            theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w;
+        return Some(Ray::with_time(self.origin, direction, time));
+    }
+}
+
+// A full 360 by 180 degree panorama in latitude and longitude, with vup
+// along the top row and lookat's direction along the horizon in the middle,
+// so the horizon stays level however far up or down lookat is. Its images
+// are meant to be twice as wide as they are tall.
+pub struct EquirectangularCamera {
+    origin: Point3<f64>,
+    u: Vector3<f64>,
+    v: Vector3<f64>,
+    w: Vector3<f64>,
+    shutter: Shutter,
+}
+
+impl EquirectangularCamera {
+    pub fn new(lookfrom: Point3<f64>, lookat: Point3<f64>, vup: Vector3<f64>) -> Self {
+        // unlike basis() the pole is vup itself, and w the part of the view
+        // direction square to it
+        let v = vup.normalize();
+        let back = lookfrom - lookat;
+        let w = (back - back.dot(v) * v).normalize();
+        let u = v.cross(w);
+        return Self {
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            shutter: Shutter::default(),
+        };
+    }
+
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter = Shutter { open, close };
+        return self;
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let time = self.shutter.time(sampler);
+        let longitude = (2. * s - 1.) * PI;
+        let latitude = (t - 0.5) * PI;
+        let direction = latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w)
+            + latitude.sin() * self.v;
+        return Some(Ray::with_time(self.origin, direction, time));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{vec3, Deg};
+
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    fn close(a: Vector3<f64>, b: Vector3<f64>) -> bool {
+        return (a.normalize() - b).magnitude() < 1e-9;
+    }
+
+    #[test]
+    fn test_projections() {
+        // all looking down -z from the origin
+        let (from, at, up) = (
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., -1.),
+            vec3(0., 1., 0.),
         );
+        let mut sampler = IndependentSampler::new(0);
+        let mut ray = |camera: &dyn Camera, s, t| camera.get_ray(s, t, &mut sampler);
+
+        let perspective = PerspectiveCamera::new(from, at, up, Deg(90.).into(), 2., 0., 1.);
+        let r = ray(&perspective, 1., 0.5).unwrap();
+        assert!(close(r.direction(), vec3(2., 0., -1.).normalize()));
+
+        let ortho = OrthographicCamera::new(from, at, up, 2., 2.);
+        for (s, t) in [(0., 0.), (0.5, 0.5), (1., 0.75)] {
+            let r = ray(&ortho, s, t).unwrap();
+            assert!(close(r.direction(), vec3(0., 0., -1.)));
+            let expected = Point3::new(4. * s - 2., 2. * t - 1., 0.);
+            assert!((r.origin() - expected).magnitude() < 1e-9);
+        }
+
+        // 180 degrees, so the edge of the circle looks sideways
+        let fisheye = FisheyeCamera::new(from, at, up, Deg(180.).into(), 2.);
+        assert!(close(
+            ray(&fisheye, 0.5, 0.5).unwrap().direction(),
+            vec3(0., 0., -1.)
+        ));
+        assert!(close(
+            ray(&fisheye, 0.5, 1.).unwrap().direction(),
+            vec3(0., 1., 0.)
+        ));
+        assert!(close(
+            ray(&fisheye, 0.75, 0.5).unwrap().direction(),
+            vec3(1., 0., 0.)
+        ));
+        // halfway out is halfway round
+        let half = ray(&fisheye, 0.5, 0.75).unwrap().direction();
+        assert!(close(half, vec3(0., 1., -1.).normalize()));
+        assert!(ray(&fisheye, 0.95, 0.5).is_none());
+        assert!(ray(&fisheye, 0.7, 0.95).is_none());
+        // all the way round to straight behind
+        let full = FisheyeCamera::new(from, at, up, Deg(360.).into(), 1.);
+        assert!(close(
+            ray(&full, 1., 0.5).unwrap().direction(),
+            vec3(0., 0., 1.)
+        ));
+
+        let panorama = EquirectangularCamera::new(from, at, up);
+        for (s, t, expected) in [
+            (0.5, 0.5, vec3(0., 0., -1.)),
+            (0.75, 0.5, vec3(1., 0., 0.)),
+            (0.25, 0.5, vec3(-1., 0., 0.)),
+            (0., 0.5, vec3(0., 0., 1.)),
+            (0.3, 1., vec3(0., 1., 0.)),
+            (0.5, 0.25, vec3(0., -1., -1.).normalize()),
+        ] {
+            let r = ray(&panorama, s, t).unwrap();
+            assert!(
+                close(r.direction(), expected),
+                "{} {} {:?}",
+                s,
+                t,
+                r.direction()
+            );
+            assert_eq!(r.origin(), from);
+        }
+        // looking down at the origin from above keeps the horizon level
+        let tilted =
+            EquirectangularCamera::new(Point3::new(13., 2., 3.), Point3::new(0., 0., 0.), up);
+        let ahead = vec3(-13., 0., -3.).normalize();
+        for (s, t, expected) in [
+            (0.5, 0.5, ahead),
+            (0.3, 1., vec3(0., 1., 0.)),
+            (0.5, 0.75, (ahead + vec3(0., 1., 0.)).normalize()),
+            (0.75, 0.5, ahead.cross(vec3(0., 1., 0.))),
+        ] {
+            let r = ray(&tilted, s, t).unwrap();
+            assert!(
+                close(r.direction(), expected),
+                "{} {} {:?}",
+                s,
+                t,
+                r.direction()
+            );
+        }
+    }
+
+    #[test]
+    fn test_lens() {
+        let (from, at, up) = (
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., -1.),
+            vec3(0., 1., 0.),
+        );
+        let camera = || PerspectiveCamera::new(from, at, up, Deg(60.).into(), 1.5, 2., 4.);
+        let mut sampler = IndependentSampler::new(1);
+        let n = 10_000;
+
+        // a hexagon with a corner pointing up, reaching its corners
+        let hexagon = camera().with_aperture(Aperture::Polygon {
+            blades: 6,
+            rotation: Deg(90.).into(),
+        });
+        let mut furthest: f64 = 0.;
+        for _ in 0..n {
+            let lens = hexagon.get_ray(0.3, 0.6, &mut sampler).unwrap().origin() - from;
+            for k in 0..6 {
+                let edge = Rad(PI / 2. + PI * (2 * k + 1) as f64 / 6.);
+                let across = lens.x * edge.cos() + lens.y * edge.sin();
+                assert!(across <= (PI / 6.).cos() + 1e-9, "{:?}", lens);
+            }
+            furthest = furthest.max(lens.magnitude());
+        }
+        assert!(furthest > 0.98);
+
+        // only the left half of the mask lets light through
+        let mut pixels = vec![vec3(0., 0., 0.); 8];
+        pixels[0] = vec3(1., 1., 1.);
+        pixels[4] = vec3(1., 1., 1.);
+        let mask = Aperture::mask(&Framebuffer::from_pixels(4, 2, pixels).unwrap());
+        let masked = camera().with_aperture(mask);
+        for _ in 0..1000 {
+            let lens = masked.get_ray(0.5, 0.5, &mut sampler).unwrap().origin() - from;
+            assert!(
+                (-1. ..=-0.5).contains(&lens.x) && lens.y.abs() <= 0.5,
+                "{:?}",
+                lens
+            );
+        }
+
+        // the middle sees the whole lens, in the corners with a cat's eye of
+        // 1 only the overlap of two unit circles a radius apart gets through
+        let cats_eye = camera().with_cats_eye(1.);
+        assert!((0..1000).all(|_| cats_eye.get_ray(0.5, 0.5, &mut sampler).is_some()));
+        let through = (0..n)
+            .filter(|_| cats_eye.get_ray(1., 0., &mut sampler).is_some())
+            .count() as f64
+            / n as f64;
+        let overlap = 2. * (PI / 3. - 3f64.sqrt() / 4.) / PI;
+        assert!(
+            (through - overlap).abs() < 0.02,
+            "{} vs {}",
+            through,
+            overlap
+        );
+
+        // all rays through one point of the image meet on the tilted focus
+        // plane, whose top leans away
+        let tilted = camera().with_tilt(Deg(30.).into(), Deg(10.).into());
+        let normal = tilted.focus_normal.unwrap();
+        let centre = Point3::new(0., 0., -4.);
+        let mut focus = |s, t| {
+            let meets = |r: Ray| {
+                let along = (centre - r.origin()).dot(normal) / r.direction().dot(normal);
+                r.at(along)
+            };
+            let p = meets(tilted.get_ray(s, t, &mut sampler).unwrap());
+            for _ in 0..10 {
+                let q = meets(tilted.get_ray(s, t, &mut sampler).unwrap());
+                assert!((p - q).magnitude() < 1e-9, "{:?} vs {:?}", p, q);
+            }
+            p
+        };
+        assert!((focus(0.5, 0.5) - centre).magnitude() < 1e-9);
+        assert!(focus(0.5, 1.).z < -4. && focus(0.5, 0.).z > -4.);
+        assert!(focus(1., 0.5).z < -4. && focus(0., 0.5).z > -4.);
+        focus(0.1, 0.9);
     }
 }
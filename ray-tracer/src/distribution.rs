@@ -0,0 +1,135 @@
+// Piecewise constant distributions over [0, 1) and [0, 1)^2, for importance
+// sampling tabulated functions like an environment map's brightness.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    // the mean of func, which is its integral over [0, 1)
+    integral: f64,
+}
+
+impl Distribution1D {
+    // `func` must not be negative; one that is 0 everywhere samples uniformly
+    pub fn new(mut func: Vec<f64>) -> Self {
+        assert!(!func.is_empty());
+        let n = func.len() as f64;
+        let mut integral = func.iter().sum::<f64>() / n;
+        if integral <= 0. || !integral.is_finite() {
+            func.iter_mut().for_each(|f| *f = 1.);
+            integral = 1.;
+        }
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        let mut sum = 0.;
+        cdf.push(0.);
+        for f in func.iter() {
+            sum += f / n;
+            cdf.push(sum / integral);
+        }
+        *cdf.last_mut().unwrap() = 1.;
+        return Self {
+            func,
+            cdf,
+            integral,
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // Maps `u` to a point in [0, 1), returning it with its pdf and the piece
+    // it fell in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.func.len();
+        let i = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0. {
+            ((u - self.cdf[i]) / width).clamp(0., 1.)
+        } else {
+            0.
+        };
+        let x = ((i as f64 + du) / n as f64).min(1. - f64::EPSILON);
+        return (x, self.func[i] / self.integral, i);
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        let n = self.func.len();
+        let i = ((x * n as f64) as usize).min(n - 1);
+        return self.func[i] / self.integral;
+    }
+}
+
+// A distribution over a grid of `width` x `height` pieces, sampled by picking
+// a row from the marginal and then a column within it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    // `func` row by row
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(func.len(), width * height);
+        let rows: Vec<Distribution1D> = func
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+        return Self { rows, marginal };
+    }
+
+    // a point [x, y] in the unit square and its pdf
+    pub fn sample(&self, u: [f64; 2]) -> ([f64; 2], f64) {
+        let (y, pdf_y, row) = self.marginal.sample(u[1]);
+        let (x, pdf_x, _) = self.rows[row].sample(u[0]);
+        return ([x, y], pdf_x * pdf_y);
+    }
+
+    pub fn pdf(&self, p: [f64; 2]) -> f64 {
+        let h = self.rows.len();
+        let row = ((p[1] * h as f64) as usize).min(h - 1);
+        return self.marginal.pdf(p[1]) * self.rows[row].pdf(p[0]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rng::Rng;
+
+    #[test]
+    fn test_samples_follow_the_function() {
+        let func = [0., 1., 3., 0., 4., 2.];
+        let dist = Distribution2D::new(&func, 3, 2);
+        let mut rng = Rng::new(4);
+        let mut counts = [0; 6];
+        let n = 100_000;
+        for _ in 0..n {
+            let (p, pdf) = dist.sample([rng.uniform(), rng.uniform()]);
+            assert!((0. ..1.).contains(&p[0]) && (0. ..1.).contains(&p[1]));
+            assert!((pdf - dist.pdf(p)).abs() < 1e-12);
+            counts[(p[1] * 2.) as usize * 3 + (p[0] * 3.) as usize] += 1;
+        }
+        for (count, f) in counts.iter().zip(func) {
+            let expected = f / 10. * n as f64;
+            assert!((*count as f64 - expected).abs() < 0.02 * n as f64);
+        }
+        assert_eq!(counts[0], 0);
+        assert_eq!(counts[3], 0);
+
+        // all zero falls back to uniform
+        let flat = Distribution1D::new(vec![0.; 4]);
+        let (x, pdf, i) = flat.sample(0.3);
+        assert!((x - 0.3).abs() < 1e-12 && pdf == 1. && i == 1);
+    }
+}
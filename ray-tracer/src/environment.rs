@@ -0,0 +1,179 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use cgmath::{vec3, Deg, InnerSpace, Matrix, Matrix3, Point3, Vector3};
+
+use crate::{
+    distribution::Distribution2D,
+    image::Framebuffer,
+    ray::{
+        aabb::Aabb,
+        hittable::{HitRecord, Hittable},
+        Ray,
+    },
+    render::luminance,
+    sampler::Sampler,
+    Color,
+};
+
+// An equirectangular (latitude-longitude) image of the light arriving from
+// every direction. The image's centre is straight down -z, its top row +y
+// and u grows towards +x.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    intensity: f64,
+    // from the map's frame to the world and back
+    rotation: Matrix3<f64>,
+    inverse: Matrix3<f64>,
+    // over the pixels, by brightness times the solid angle they cover
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    // `rotation` turns the map counter clockwise around +y, in degrees
+    pub fn new(image: Framebuffer, rotation: f64, intensity: f64) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let weights: Vec<f64> = image
+            .pixels()
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| {
+                let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+                luminance(c).max(0.) * theta.sin()
+            })
+            .collect();
+        let rotation = Matrix3::from_angle_y(Deg(rotation));
+        return Self {
+            distribution: Distribution2D::new(&weights, width, height),
+            image,
+            intensity,
+            rotation,
+            inverse: rotation.transpose(),
+        };
+    }
+
+    // the image coordinates in [0, 1) the world direction `dir` looks at
+    fn uv(&self, dir: Vector3<f64>) -> [f64; 2] {
+        let d = self.inverse * dir.normalize();
+        let u = 0.5 + d.x.atan2(-d.z) / (2. * PI);
+        let v = d.y.clamp(-1., 1.).acos() / PI;
+        return [u.rem_euclid(1.), v.min(1. - f64::EPSILON)];
+    }
+
+    fn direction(&self, [u, v]: [f64; 2]) -> Vector3<f64> {
+        let (theta, phi) = (v * PI, (u - 0.5) * 2. * PI);
+        let d = vec3(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+        return self.rotation * d;
+    }
+
+    pub fn value(&self, dir: Vector3<f64>) -> Color {
+        let [u, v] = self.uv(dir);
+        let x = ((u * self.image.width() as f64) as u32).min(self.image.width() - 1);
+        let y = ((v * self.image.height() as f64) as u32).min(self.image.height() - 1);
+        return self.intensity * self.image.get(x, y);
+    }
+
+    // solid angle pdf of sample returning `dir`
+    pub fn pdf(&self, dir: Vector3<f64>) -> f64 {
+        let uv = self.uv(dir);
+        let sin_theta = (uv[1] * PI).sin();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        return self.distribution.pdf(uv) / (2. * PI * PI * sin_theta);
+    }
+
+    // a direction picked in proportion to the brightness seen along it
+    pub fn sample(&self, u: [f64; 2]) -> Vector3<f64> {
+        let (uv, _) = self.distribution.sample(u);
+        return self.direction(uv);
+    }
+}
+
+// Lets the path integrator sample an environment map like any other light.
+// It is never hit, rays that escape the scene see it as the background.
+pub struct EnvironmentLight {
+    map: Arc<EnvironmentMap>,
+}
+
+impl EnvironmentLight {
+    pub fn new(map: Arc<EnvironmentMap>) -> Self {
+        Self { map }
+    }
+}
+
+impl Hittable for EnvironmentLight {
+    fn hit(&self, _r: &Ray, _t_min: f64, _t_max: f64) -> Option<HitRecord<'_>> {
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    fn pdf_value(&self, _origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        self.map.pdf(dir)
+    }
+
+    fn random_direction(&self, _origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        self.map.sample(sampler.get_2d())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{rng::Rng, vector_additon::VectorAdditions};
+
+    // dim sky with a small bright sun in one pixel
+    fn sunny(rotation: f64) -> EnvironmentMap {
+        let mut image = Framebuffer::new(32, 16);
+        for p in image.pixels_mut() {
+            *p = vec3(0.2, 0.3, 0.5);
+        }
+        image.set(20, 4, vec3(5000., 5000., 4000.));
+        return EnvironmentMap::new(image, rotation, 2.);
+    }
+
+    #[test]
+    fn test_environment_pdf() {
+        let map = sunny(30.);
+        let mut rng = Rng::new(9);
+        let n = 400_000;
+        let total: f64 = (0..n)
+            .map(|_| 4. * PI * map.pdf(Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()])))
+            .sum::<f64>()
+            / n as f64;
+        assert!((total - 1.).abs() < 0.05, "{}", total);
+
+        // most samples head for the sun
+        let sun = map.value(map.direction([20.5 / 32., 4.5 / 16.]));
+        let mut towards_sun = 0;
+        for _ in 0..1000 {
+            let dir = map.sample([rng.uniform(), rng.uniform()]);
+            assert!((dir.magnitude() - 1.).abs() < 1e-9);
+            if map.value(dir) == sun {
+                towards_sun += 1;
+            }
+        }
+        assert!(towards_sun > 900, "{}", towards_sun);
+        assert_eq!(sun, vec3(10000., 10000., 8000.));
+    }
+
+    #[test]
+    fn test_rotation() {
+        // the middle of the map is -z, a quarter turn moves it to -x
+        let mut image = Framebuffer::new(4, 2);
+        image.set(2, 0, vec3(1., 0., 0.));
+        let map = EnvironmentMap::new(image.clone(), 0., 1.);
+        assert_eq!(map.value(vec3(0., 0.5, -1.)), vec3(1., 0., 0.));
+        assert_eq!(map.value(vec3(0.1, 0.5, 1.)), vec3(0., 0., 0.));
+        let turned = EnvironmentMap::new(image, 90., 1.);
+        assert_eq!(turned.value(vec3(-1., 0.5, -0.1)), vec3(1., 0., 0.));
+        assert_eq!(turned.value(vec3(0.1, 0.5, -1.)), vec3(0., 0., 0.));
+    }
+}
@@ -0,0 +1,21 @@
+use std::path::Path;
+
+use anyhow::Result;
+use image::{ImageBuffer, Rgb};
+
+use super::{Framebuffer, ImageSink};
+
+// OpenEXR with 32 bit float channels, values are written linear and unclamped
+pub struct ExrSink;
+
+impl ImageSink for ExrSink {
+    fn write(&self, framebuffer: &Framebuffer, path: &Path) -> Result<()> {
+        let img: ImageBuffer<Rgb<f32>, Vec<f32>> =
+            ImageBuffer::from_fn(framebuffer.width(), framebuffer.height(), |x, y| {
+                let c = framebuffer.get(x, y);
+                Rgb([c.x as f32, c.y as f32, c.z as f32])
+            });
+        img.save_with_format(path, image::ImageFormat::OpenExr)?;
+        Ok(())
+    }
+}
@@ -1,120 +1,308 @@
-use anyhow::Result;
-use rayon::prelude::*;
-use std::{
-    fs::{File, OpenOptions},
-    io::Write,
-    sync::atomic::AtomicU32,
-};
+mod exr;
+mod png;
+mod ppm;
+mod radiance;
+
+use anyhow::{bail, Context, Result};
+use cgmath::vec3;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 use crate::Color;
 
-pub struct PPMImageWriter {
-    file: File,
-    pub image_width: u32,
-    pub image_height: u32,
-    pub samples_per_pixel: u32,
-    pub aspect_ratio: f64,
+pub use self::{exr::ExrSink, png::PngSink, ppm::PpmSink, radiance::HdrSink};
+
+// A linear float image, stored top row first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
 }
 
-impl PPMImageWriter {
-    pub fn new(
-        file_name: &str,
-        image_width: u32,
-        aspect_ratio: f64,
-        samples_per_pixel: u32,
-    ) -> Result<Self> {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(file_name)?;
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![vec3(0., 0., 0.); (width * height) as usize],
+        }
+    }
 
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Color>) -> Result<Self> {
+        if pixels.len() != (width * height) as usize {
+            bail!(
+                "expected {} pixels for a {}x{} image, got {}",
+                width * height,
+                width,
+                height,
+                pixels.len()
+            );
+        }
         return Ok(Self {
-            file,
-            image_height: (image_width as f64 / aspect_ratio) as u32,
-            image_width,
-            aspect_ratio,
-            samples_per_pixel,
+            width,
+            height,
+            pixels,
         });
     }
 
-    fn write_color(&mut self, color: Color) -> Result<()> {
-        let scale = 1. / self.samples_per_pixel as f64;
-        let r = (256. * ((scale * color.x).sqrt().clamp(0., 0.999))) as u32;
-        let g = (256. * ((scale * color.y).sqrt().clamp(0., 0.999))) as u32;
-        let b = (256. * ((scale * color.z).sqrt().clamp(0., 0.999))) as u32;
-
-        writeln!(self.file, "{} {} {}", r, g, b)?;
-        Ok(())
-    }
-
-    pub fn write<F>(mut self, closure: F) -> Result<()>
-    where
-        F: Fn(u32, u32, &Self) -> Color + Send + Sync,
-    {
-        writeln!(
-            self.file,
-            "P3\n{} {}\n255",
-            self.image_width, self.image_height
-        )?;
-
-        let counter: AtomicU32 = AtomicU32::new(0);
-        eprintln!(
-            "width: {}, height: {}, total: {}",
-            self.image_width,
-            self.image_height,
-            self.image_height * self.image_width
-        );
-
-        let colors: Vec<Color> = (0..(self.image_width * self.image_height))
-            .into_par_iter()
-            .map(|idx| {
-                let j = self.image_height - (idx / self.image_width);
-                let i = idx % self.image_width;
-                let res = closure(j, i, &self);
-                let prev = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                eprint!(
-                    "\r{:.2}%",
-                    (((prev + 1) as f64 / (self.image_width * self.image_height) as f64) * 100.)
-                );
-                std::io::stderr().flush().unwrap();
-                res
-            })
-            .collect();
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // x counts columns from the left, y rows from the top
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferFunction {
+    Linear,
+    Gamma(f64),
+    Srgb,
+}
+
+impl TransferFunction {
+    pub fn apply(&self, x: f64) -> f64 {
+        let x = x.max(0.);
+        match self {
+            TransferFunction::Linear => x,
+            TransferFunction::Gamma(gamma) => x.powf(1. / gamma),
+            TransferFunction::Srgb => {
+                if x <= 0.0031308 {
+                    12.92 * x
+                } else {
+                    1.055 * x.powf(1. / 2.4) - 0.055
+                }
+            }
+        }
+    }
+
+    // quantizes to an integer in 0..=max after applying the curve
+    pub fn encode(&self, x: f64, max: u32) -> u32 {
+        let max = max as f64;
+        return (self.apply(x).clamp(0., 1.) * max + 0.5).min(max) as u32;
+    }
+
+    // the inverse of apply, takes an encoded value in [0, 1] back to linear
+    pub fn invert(&self, y: f64) -> f64 {
+        let y = y.max(0.);
+        match self {
+            TransferFunction::Linear => y,
+            TransferFunction::Gamma(gamma) => y.powf(*gamma),
+            TransferFunction::Srgb => {
+                if y <= 0.04045 {
+                    y / 12.92
+                } else {
+                    ((y + 0.055) / 1.055).powf(2.4)
+                }
+            }
+        }
+    }
+}
 
-        for color in colors.into_iter() {
-            self.write_color(color)?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    PpmAscii,
+    PpmBinary,
+    Png8,
+    Png16,
+    Hdr,
+    Exr,
+}
+
+impl ImageFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        return match ext.as_deref() {
+            Some("ppm") => Ok(ImageFormat::PpmAscii),
+            Some("png") => Ok(ImageFormat::Png8),
+            Some("hdr") => Ok(ImageFormat::Hdr),
+            Some("exr") => Ok(ImageFormat::Exr),
+            _ => bail!(
+                "can't tell the image format of {}, use .ppm, .png, .hdr or .exr",
+                path.display()
+            ),
+        };
+    }
+
+    pub fn sink(&self) -> Box<dyn ImageSink> {
+        match self {
+            ImageFormat::PpmAscii => Box::new(PpmSink::ascii()),
+            ImageFormat::PpmBinary => Box::new(PpmSink::binary()),
+            ImageFormat::Png8 => Box::new(PngSink::eight_bit()),
+            ImageFormat::Png16 => Box::new(PngSink::sixteen_bit()),
+            ImageFormat::Hdr => Box::new(HdrSink),
+            ImageFormat::Exr => Box::new(ExrSink),
         }
-        // for j in (0..self.image_height).rev() {
-        //     for i in 0..self.image_width {
-        //         eprint!("\rScanlines remaing {} ", j);
-        //         std::io::stderr().flush()?;
-        //         self.write_color(closure(j, i, &self))?;
-        //     }
-        // }
-        eprintln!("\nDone. ");
-        Ok(())
     }
 }
 
+pub trait ImageSink {
+    fn write(&self, framebuffer: &Framebuffer, path: &Path) -> Result<()>;
+}
+
+// Writes `framebuffer` to `path`, picking the format from the extension unless one is given.
+pub fn save<P: AsRef<Path>>(
+    framebuffer: &Framebuffer,
+    path: P,
+    format: Option<ImageFormat>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let format = match format {
+        Some(format) => format,
+        None => ImageFormat::from_path(path)?,
+    };
+    return format
+        .sink()
+        .write(framebuffer, path)
+        .with_context(|| format!("failed to write {}", path.display()));
+}
+
+// Reads an image into linear floats. 8 and 16 bit formats are assumed to be
+// sRGB encoded, float formats (.hdr, .exr) are taken as they are.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Framebuffer> {
+    let path = path.as_ref();
+    let context = || format!("failed to read {}", path.display());
+    if ImageFormat::from_path(path).ok() == Some(ImageFormat::Hdr) {
+        return radiance::read(path).with_context(context);
+    }
+    let img = ::image::open(path).with_context(context)?;
+    let linear = matches!(
+        img,
+        ::image::DynamicImage::ImageRgb32F(_) | ::image::DynamicImage::ImageRgba32F(_)
+    );
+    let img = img.into_rgb32f();
+    let pixels = img
+        .pixels()
+        .map(|p| {
+            let c = vec3(p[0] as f64, p[1] as f64, p[2] as f64);
+            if linear {
+                c
+            } else {
+                c.map(|x| TransferFunction::Srgb.invert(x))
+            }
+        })
+        .collect();
+    return Framebuffer::from_pixels(img.width(), img.height(), pixels);
+}
+
 #[cfg(test)]
 mod test {
     use cgmath::vec3;
 
     use super::*;
 
+    fn gradient() -> Framebuffer {
+        // red across, green up from the bottom row
+        let pixels = (0..32)
+            .flat_map(|y| {
+                (0..64).map(move |x| {
+                    vec3(
+                        x as f64 / 63.,
+                        (31 - y) as f64 / 31.,
+                        // something well outside of [0, 1] for the float formats
+                        4.,
+                    )
+                })
+            })
+            .collect();
+        return Framebuffer::from_pixels(64, 32, pixels).unwrap();
+    }
+
+    fn decode(path: &Path) -> ::image::Rgb32FImage {
+        if path.extension().unwrap() == "hdr" {
+            // the generic loader tone maps radiance files down to 8 bits
+            let file = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+            let decoder = ::image::codecs::hdr::HdrDecoder::new(file).unwrap();
+            let meta = decoder.metadata();
+            let data = decoder.read_image_hdr().unwrap();
+            return ::image::ImageBuffer::from_fn(meta.width, meta.height, |x, y| {
+                data[(y * meta.width + x) as usize]
+            });
+        }
+        ::image::open(path).unwrap().into_rgb32f()
+    }
+
     #[test]
     fn test_writer() {
-        let writer = PPMImageWriter::new("./image.ppm", 256, 1., 100).unwrap();
-        assert!(writer
-            .write(|j, i, writer| {
-                return vec3(
-                    (i as f64) / (writer.image_width - 1) as f64,
-                    (j as f64) / (writer.image_height - 1) as f64,
-                    0.25,
-                );
-            })
-            .map(|_| true)
-            .unwrap_or(false));
+        let dir = std::env::temp_dir().join("ray-tracer-image-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let fb = gradient();
+
+        for (name, format) in [
+            ("ascii.ppm", None),
+            ("binary.ppm", Some(ImageFormat::PpmBinary)),
+            ("eight.png", None),
+            ("sixteen.png", Some(ImageFormat::Png16)),
+        ] {
+            let path = dir.join(name);
+            save(&fb, &path, format).unwrap();
+            let img = decode(&path);
+            assert_eq!(img.dimensions(), (64, 32), "{}", name);
+            // bottom right is full red, the bottom row has no green
+            let p = img.get_pixel(63, 31);
+            assert!((p[0] - 1.).abs() < 1e-3 && p[1] < 1e-3, "{} {:?}", name, p);
+            // the blue channel is clipped
+            assert!((p[2] - 1.).abs() < 1e-3, "{} {:?}", name, p);
+        }
+
+        for name in ["float.hdr", "float.exr"] {
+            let path = dir.join(name);
+            save(&fb, &path, None).unwrap();
+            let img = decode(&path);
+            assert_eq!(img.dimensions(), (64, 32), "{}", name);
+            // linear values survive, including ones above 1
+            let p = img.get_pixel(32, 0);
+            assert!((p[0] - 32. / 63.).abs() < 1e-2, "{} {:?}", name, p);
+            assert!((p[1] - 1.).abs() < 1e-2, "{} {:?}", name, p);
+            assert!((p[2] - 4.).abs() < 1e-1, "{} {:?}", name, p);
+        }
+
+        assert!(save(&fb, dir.join("image.bmp"), None).is_err());
+
+        // loading undoes the transfer function of the 8 bit formats
+        for name in ["sixteen.png", "float.hdr", "float.exr"] {
+            let loaded = load(dir.join(name)).unwrap();
+            let (expected, got) = (fb.get(32, 0), loaded.get(32, 0));
+            assert!((got.x - expected.x).abs() < 1e-2, "{} {:?}", name, got);
+            assert!((got.y - expected.y).abs() < 1e-2, "{} {:?}", name, got);
+        }
+    }
+
+    #[test]
+    fn test_transfer_functions() {
+        assert_eq!(TransferFunction::Srgb.encode(0., 255), 0);
+        assert_eq!(TransferFunction::Srgb.encode(1., 255), 255);
+        assert_eq!(TransferFunction::Srgb.encode(0.5, 255), 188);
+        assert_eq!(TransferFunction::Gamma(2.).encode(0.25, 255), 128);
+        assert_eq!(TransferFunction::Linear.encode(2., 65535), 65535);
+        assert_eq!(TransferFunction::Linear.encode(-1., 255), 0);
+        for x in [0., 0.002, 0.2, 0.9] {
+            let y = TransferFunction::Srgb.apply(x);
+            assert!((TransferFunction::Srgb.invert(y) - x).abs() < 1e-12);
+        }
     }
 }
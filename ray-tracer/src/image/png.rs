@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use anyhow::Result;
+use image::{ImageBuffer, Rgb};
+
+use super::{Framebuffer, ImageSink, TransferFunction};
+
+pub struct PngSink {
+    sixteen_bit: bool,
+    transfer: TransferFunction,
+}
+
+impl PngSink {
+    pub fn eight_bit() -> Self {
+        Self {
+            sixteen_bit: false,
+            transfer: TransferFunction::Srgb,
+        }
+    }
+
+    pub fn sixteen_bit() -> Self {
+        Self {
+            sixteen_bit: true,
+            transfer: TransferFunction::Srgb,
+        }
+    }
+
+    pub fn with_transfer(mut self, transfer: TransferFunction) -> Self {
+        self.transfer = transfer;
+        return self;
+    }
+}
+
+impl ImageSink for PngSink {
+    fn write(&self, framebuffer: &Framebuffer, path: &Path) -> Result<()> {
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+        if self.sixteen_bit {
+            let img = ImageBuffer::from_fn(width, height, |x, y| {
+                let c = framebuffer.get(x, y);
+                Rgb([c.x, c.y, c.z].map(|v| self.transfer.encode(v, u16::MAX as u32) as u16))
+            });
+            img.save_with_format(path, image::ImageFormat::Png)?;
+        } else {
+            let img = ImageBuffer::from_fn(width, height, |x, y| {
+                let c = framebuffer.get(x, y);
+                Rgb([c.x, c.y, c.z].map(|v| self.transfer.encode(v, u8::MAX as u32) as u8))
+            });
+            img.save_with_format(path, image::ImageFormat::Png)?;
+        }
+        Ok(())
+    }
+}
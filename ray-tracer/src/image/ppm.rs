@@ -0,0 +1,62 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+
+use super::{Framebuffer, ImageSink, TransferFunction};
+
+pub struct PpmSink {
+    binary: bool,
+    transfer: TransferFunction,
+}
+
+impl PpmSink {
+    // plain text P3
+    pub fn ascii() -> Self {
+        Self {
+            binary: false,
+            transfer: TransferFunction::Srgb,
+        }
+    }
+
+    // raw P6
+    pub fn binary() -> Self {
+        Self {
+            binary: true,
+            transfer: TransferFunction::Srgb,
+        }
+    }
+
+    pub fn with_transfer(mut self, transfer: TransferFunction) -> Self {
+        self.transfer = transfer;
+        return self;
+    }
+}
+
+impl ImageSink for PpmSink {
+    fn write(&self, framebuffer: &Framebuffer, path: &Path) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(
+            file,
+            "{}\n{} {}\n255",
+            if self.binary { "P6" } else { "P3" },
+            framebuffer.width(),
+            framebuffer.height()
+        )?;
+        for color in framebuffer.pixels().iter() {
+            let r = self.transfer.encode(color.x, 255);
+            let g = self.transfer.encode(color.y, 255);
+            let b = self.transfer.encode(color.z, 255);
+            if self.binary {
+                file.write_all(&[r as u8, g as u8, b as u8])?;
+            } else {
+                writeln!(file, "{} {} {}", r, g, b)?;
+            }
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
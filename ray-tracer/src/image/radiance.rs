@@ -0,0 +1,45 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use anyhow::Result;
+use cgmath::vec3;
+use image::{
+    codecs::hdr::{HdrDecoder, HdrEncoder},
+    Rgb,
+};
+
+use super::{Framebuffer, ImageSink};
+
+// Radiance RGBE, values are written linear and unclamped
+pub struct HdrSink;
+
+impl ImageSink for HdrSink {
+    fn write(&self, framebuffer: &Framebuffer, path: &Path) -> Result<()> {
+        let data: Vec<Rgb<f32>> = framebuffer
+            .pixels()
+            .iter()
+            .map(|c| Rgb([c.x as f32, c.y as f32, c.z as f32].map(|v| v.max(0.))))
+            .collect();
+        HdrEncoder::new(BufWriter::new(File::create(path)?)).encode(
+            &data,
+            framebuffer.width() as usize,
+            framebuffer.height() as usize,
+        )?;
+        Ok(())
+    }
+}
+
+// image::open tone maps radiance files down to 8 bits, so they are decoded here
+pub fn read(path: &Path) -> Result<Framebuffer> {
+    let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+    let meta = decoder.metadata();
+    let pixels = decoder
+        .read_image_hdr()?
+        .iter()
+        .map(|p| vec3(p[0] as f64, p[1] as f64, p[2] as f64))
+        .collect();
+    return Framebuffer::from_pixels(meta.width, meta.height, pixels);
+}
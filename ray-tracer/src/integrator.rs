@@ -0,0 +1,434 @@
+use cgmath::{vec3, ElementWise};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ray::{hittable::Hittable, Ray},
+    render::AovSample,
+    sampler::Sampler,
+    scene::Scene,
+    Color,
+};
+
+// paths shorter than this are never cut short by russian roulette
+const RR_START_DEPTH: i32 = 3;
+
+pub trait Integrator: Send + Sync {
+    // estimates the radiance arriving along `r`, filling `aov` in from what
+    // `r` hits first
+    fn color(
+        &self,
+        r: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        aov: Option<&mut AovSample>,
+    ) -> Color;
+}
+
+// Pure path tracing with a fixed depth, the original Ray::color.
+pub struct NaiveIntegrator {
+    pub max_depth: i32,
+}
+
+impl Integrator for NaiveIntegrator {
+    fn color(
+        &self,
+        r: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        aov: Option<&mut AovSample>,
+    ) -> Color {
+        return r.color(
+            scene.world.as_ref(),
+            &scene.background,
+            self.max_depth,
+            sampler,
+            aov,
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MisHeuristic {
+    Balance,
+    #[default]
+    Power,
+}
+
+impl MisHeuristic {
+    // weight for a sample taken with pdf `a` when `b` could also have produced it
+    pub fn weight(&self, a: f64, b: f64) -> f64 {
+        let (a, b) = match self {
+            MisHeuristic::Balance => (a, b),
+            MisHeuristic::Power => (a * a, b * b),
+        };
+        if a + b == 0. {
+            return 0.;
+        }
+        return a / (a + b);
+    }
+}
+
+// Path tracing with next event estimation: every diffuse bounce also samples
+// the scene's lights directly and the two strategies are combined with
+// multiple importance sampling. Paths end by russian roulette; `max_depth`
+// is only a safety net.
+pub struct PathIntegrator {
+    pub max_depth: i32,
+    pub heuristic: MisHeuristic,
+}
+
+impl Integrator for PathIntegrator {
+    fn color(
+        &self,
+        r: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        mut aov: Option<&mut AovSample>,
+    ) -> Color {
+        let mut radiance = vec3(0., 0., 0.);
+        let mut throughput = vec3(1., 1., 1.);
+        let mut ray = *r;
+        // pdf of the bounce that produced `ray`, None for camera rays and specular bounces
+        let mut prev_pdf: Option<f64> = None;
+        let sample_lights = !scene.lights.is_empty();
+
+        for depth in 0..self.max_depth {
+            let Some(rec) = scene.world.hit(&ray, 0.001, f64::INFINITY) else {
+                // an environment map is one of the lights
+                let weight = match prev_pdf {
+                    Some(bsdf_pdf) if scene.background.is_light() => {
+                        let light_pdf = scene.lights.pdf_value(ray.origin(), ray.direction());
+                        self.heuristic.weight(bsdf_pdf, light_pdf)
+                    }
+                    _ => 1.,
+                };
+                let background = scene.background.value(ray.direction());
+                radiance += ray.spectral(weight * throughput.mul_element_wise(background));
+                break;
+            };
+            if let Some(aov) = aov.take() {
+                *aov = AovSample::from_hit(&ray, &rec);
+            }
+
+            let emitted = rec.mat_ptr.emitted(&rec);
+            if emitted != vec3(0., 0., 0.) {
+                let weight = match prev_pdf {
+                    Some(bsdf_pdf) if sample_lights => {
+                        let light_pdf = scene.lights.pdf_value(ray.origin(), ray.direction());
+                        self.heuristic.weight(bsdf_pdf, light_pdf)
+                    }
+                    _ => 1.,
+                };
+                radiance += ray.spectral(weight * throughput.mul_element_wise(emitted));
+            }
+
+            let Some(srec) = rec.mat_ptr.scatter(&ray, &rec, sampler) else {
+                break;
+            };
+
+            if srec.pdf.is_some() && sample_lights {
+                let dir = scene.lights.random_direction(rec.p, sampler);
+                let light_pdf = scene.lights.pdf_value(rec.p, dir);
+                let f = rec.mat_ptr.eval(&ray, &rec, dir);
+                if light_pdf > 0. && f != vec3(0., 0., 0.) {
+                    let light_ray = ray.spawn(rec.p, dir);
+                    let le = match scene.world.hit(&light_ray, 0.001, f64::INFINITY) {
+                        Some(light_rec) => light_rec.mat_ptr.emitted(&light_rec),
+                        None if scene.background.is_light() => scene.background.value(dir),
+                        None => vec3(0., 0., 0.),
+                    };
+                    let bsdf_pdf = rec.mat_ptr.pdf(&ray, &rec, dir);
+                    let weight = self.heuristic.weight(light_pdf, bsdf_pdf);
+                    radiance += ray.spectral(
+                        (weight / light_pdf) * throughput.mul_element_wise(f).mul_element_wise(le),
+                    );
+                }
+            }
+
+            throughput = throughput.mul_element_wise(srec.attenuation);
+            prev_pdf = srec.pdf;
+            ray = srec.scattered;
+
+            if depth + 1 >= RR_START_DEPTH {
+                let survive = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+                if survive <= 0. || sampler.get_1d() > survive {
+                    break;
+                }
+                throughput /= survive;
+            }
+        }
+        return radiance;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use cgmath::{vec3, Deg, Point3};
+
+    use super::*;
+    use crate::{
+        background::Background,
+        camera::PerspectiveCamera,
+        environment::{EnvironmentLight, EnvironmentMap},
+        image::Framebuffer,
+        material::{Dielectric, DiffuseLight, Lambertian},
+        ray::{
+            hittable::{Hittable, HittableList, Sphere},
+            quad::Quad,
+        },
+        sampler::IndependentSampler,
+        scene::description::RenderSettings,
+        spectrum::{sample_wavelength, Ior},
+    };
+
+    // a diffuse floor under a small spherical light, nothing else
+    fn scene() -> Scene {
+        let light = || {
+            Box::new(Sphere::new(
+                Point3::new(0., 4., 0.),
+                1.,
+                DiffuseLight::new(vec3(4., 4., 4.)),
+            ))
+        };
+        let mut world = HittableList::default();
+        world.add(Box::new(Quad::new(
+            Point3::new(-50., 0., -50.),
+            vec3(0., 0., 100.),
+            vec3(100., 0., 0.),
+            Lambertian::new(vec3(0.5, 0.5, 0.5)),
+        )));
+        world.add(light());
+        return Scene {
+            world: Box::new(world),
+            lights: HittableList::new(light()),
+            camera: Box::new(PerspectiveCamera::new(
+                Point3::new(0., 1., 5.),
+                Point3::new(0., 0., 0.),
+                vec3(0., 1., 0.),
+                Deg(40.).into(),
+                1.,
+                0.,
+                1.,
+            )),
+            background: Background::None,
+            settings: RenderSettings::default(),
+            fingerprint: 0,
+        };
+    }
+
+    fn estimate(integrator: &dyn Integrator, scene: &Scene, n: usize) -> (f64, f64) {
+        // looks straight down at the point under the light
+        let r = Ray::new(Point3::new(0., 2., 0.), vec3(0., -1., 0.));
+        let mut sampler = IndependentSampler::new(1);
+        let samples: Vec<f64> = (0..n)
+            .map(|_| integrator.color(&r, scene, &mut sampler, None).x)
+            .collect();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let variance =
+            samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / (n - 1) as f64;
+        return (mean, (variance / n as f64).sqrt());
+    }
+
+    #[test]
+    fn test_path_integrator_matches_naive() {
+        let scene = scene();
+        // a lambertian point lit by a sphere of radiance Le subtending an angle
+        // a from the normal reflects albedo * Le * sin^2(a)
+        let expected = 0.5 * 4. * (1. / 4f64).powi(2);
+
+        let (naive, naive_err) = estimate(&NaiveIntegrator { max_depth: 50 }, &scene, 200_000);
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power] {
+            let path = PathIntegrator {
+                max_depth: 50,
+                heuristic,
+            };
+            let (mean, err) = estimate(&path, &scene, 50_000);
+            let combined = (err * err + naive_err * naive_err).sqrt();
+            assert!(
+                (mean - naive).abs() < 5. * combined,
+                "{:?}: {} +- {} vs naive {} +- {}",
+                heuristic,
+                mean,
+                err,
+                naive,
+                naive_err
+            );
+            assert!(
+                (mean - expected).abs() < 5. * err,
+                "{} vs {}",
+                mean,
+                expected
+            );
+            // light sampling should be far less noisy than hoping to hit the light
+            assert!(err < naive_err, "{} vs {}", err, naive_err);
+        }
+        assert!((naive - expected).abs() < 5. * naive_err);
+    }
+
+    #[test]
+    fn test_environment_light() {
+        // the floor lit only by a sky with a small bright sun
+        let mut image = Framebuffer::new(64, 32);
+        for p in image.pixels_mut() {
+            *p = vec3(0.1, 0.1, 0.1);
+        }
+        image.set(40, 6, vec3(2000., 2000., 2000.));
+        let map = Arc::new(EnvironmentMap::new(image, 0., 1.));
+        let mut scene = scene();
+        let mut world = HittableList::default();
+        world.add(Box::new(Quad::new(
+            Point3::new(-50., 0., -50.),
+            vec3(0., 0., 100.),
+            vec3(100., 0., 0.),
+            Lambertian::new(vec3(0.5, 0.5, 0.5)),
+        )));
+        scene.world = Box::new(world);
+        scene.lights = HittableList::new(Box::new(EnvironmentLight::new(map.clone())));
+        scene.background = Background::Environment(map);
+
+        let (naive, naive_err) = estimate(&NaiveIntegrator { max_depth: 50 }, &scene, 200_000);
+        let path = PathIntegrator {
+            max_depth: 50,
+            heuristic: MisHeuristic::Power,
+        };
+        let (mean, err) = estimate(&path, &scene, 20_000);
+        let combined = (err * err + naive_err * naive_err).sqrt();
+        assert!(
+            (mean - naive).abs() < 5. * combined,
+            "{} +- {} vs naive {} +- {}",
+            mean,
+            err,
+            naive,
+            naive_err
+        );
+        assert!(err < naive_err / 5., "{} vs {}", err, naive_err);
+    }
+
+    #[test]
+    fn test_light_pdfs_integrate_to_one() {
+        let scene = scene();
+        let origin = Point3::new(0.3, 0., 0.2);
+        let n = 20_000;
+        // the sphere's cone samples all hit it with a constant pdf of one over
+        // the solid angle it covers
+        let d2 = 0.3f64 * 0.3 + 4. * 4. + 0.2 * 0.2;
+        let solid_angle = 2. * std::f64::consts::PI * (1. - (1. - 1. / d2).sqrt());
+        let mut sampler = IndependentSampler::new(2);
+        let mut misses = 0;
+        for _ in 0..n {
+            let dir = scene.lights.random_direction(origin, &mut sampler);
+            let pdf = scene.lights.pdf_value(origin, dir);
+            if pdf == 0. {
+                misses += 1;
+                continue;
+            }
+            assert!((pdf * solid_angle - 1.).abs() < 1e-9);
+        }
+        assert!(misses < n / 1000, "{} samples missed the sphere", misses);
+
+        let quad = Quad::new(
+            Point3::new(-1., 3., -1.),
+            vec3(2., 0., 0.),
+            vec3(0., 0., 2.),
+            DiffuseLight::new(vec3(1., 1., 1.)),
+        );
+        // the quad's area pdf must also integrate to its solid angle, so compare
+        // against a brute force estimate from uniform hemisphere directions
+        let by_light: f64 = (0..n)
+            .map(|_| 1. / quad.pdf_value(origin, quad.random_direction(origin, &mut sampler)))
+            .sum::<f64>()
+            / n as f64;
+        let hits = (0..n)
+            .filter(|_| {
+                let dir = loop {
+                    let [x, z] = sampler.get_2d();
+                    let d = vec3(2. * x - 1., sampler.get_1d(), 2. * z - 1.);
+                    if cgmath::InnerSpace::magnitude2(d) <= 1. {
+                        break d;
+                    }
+                };
+                quad.hit(&Ray::new(origin, dir), 0.001, f64::INFINITY)
+                    .is_some()
+            })
+            .count();
+        let by_hemisphere = 2. * std::f64::consts::PI * hits as f64 / n as f64;
+        assert!(
+            (by_light - by_hemisphere).abs() < 0.1 * by_hemisphere,
+            "{} vs {}",
+            by_light,
+            by_hemisphere
+        );
+    }
+
+    #[test]
+    fn test_spectral_matches_rgb() {
+        // looking through a glass ball at a coloured floor; the glass barely
+        // disperses, so rendering it spectrally has to give the same colour
+        // on average even though every path through it is dispersed
+        let with_glass = |ir| {
+            let mut scene = scene();
+            let mut world = HittableList::default();
+            world.add(Box::new(Quad::new(
+                Point3::new(-50., 0., -50.),
+                vec3(0., 0., 100.),
+                vec3(100., 0., 0.),
+                Lambertian::new(vec3(0.7, 0.4, 0.1)),
+            )));
+            world.add(Box::new(Sphere::new(
+                Point3::new(0.1, 1., 0.),
+                0.5,
+                Dielectric::dispersive(ir),
+            )));
+            world.add(Box::new(Sphere::new(
+                Point3::new(0., 4., 0.),
+                1.,
+                DiffuseLight::new(vec3(4., 4., 4.)),
+            )));
+            scene.world = Box::new(world);
+            scene
+        };
+        let rgb = with_glass(Ior::Constant(1.5));
+        let spectral = with_glass(Ior::Cauchy { a: 1.5, b: 1e-9 });
+        let r = Ray::new(Point3::new(0., 2., 0.), vec3(0., -1., 0.));
+        let mut sampler = IndependentSampler::new(3);
+        let n = 100_000;
+        let mut estimate = |integrator: &dyn Integrator, scene: &Scene, spectral: bool| {
+            let (mut sum, mut sum2) = (vec3(0., 0., 0.), vec3(0., 0., 0.));
+            for _ in 0..n {
+                let r = if spectral {
+                    r.with_wavelength(sample_wavelength(sampler.get_1d()))
+                } else {
+                    r
+                };
+                let c = integrator.color(&r, scene, &mut sampler, None);
+                sum += c;
+                sum2 += c.mul_element_wise(c);
+            }
+            let mean = sum / n as f64;
+            let variance = sum2 / n as f64 - mean.mul_element_wise(mean);
+            (mean, variance / n as f64)
+        };
+        let path = PathIntegrator {
+            max_depth: 50,
+            heuristic: MisHeuristic::Power,
+        };
+        let naive = NaiveIntegrator { max_depth: 50 };
+        for integrator in [&path as &dyn Integrator, &naive] {
+            let (expected, expected_var) = estimate(integrator, &rgb, false);
+            let (mean, var) = estimate(integrator, &spectral, true);
+            for i in 0..3 {
+                let err = (var[i] + expected_var[i]).sqrt();
+                assert!(
+                    (mean[i] - expected[i]).abs() < 5. * err,
+                    "{:?} vs {:?}",
+                    mean,
+                    expected
+                );
+            }
+            assert!(expected.x > 2. * expected.z);
+        }
+    }
+}
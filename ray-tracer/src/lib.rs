@@ -1,17 +1,23 @@
+#![allow(clippy::needless_return)]
 use cgmath::Vector3;
-use rand::{
-    distributions::uniform::{SampleRange, SampleUniform},
-    thread_rng, Rng,
-};
 
+pub mod background;
 pub mod camera;
+pub mod distribution;
+pub mod environment;
 pub mod image;
+pub mod integrator;
 pub mod material;
+pub mod microfacet;
+pub mod obj;
+pub mod onb;
 pub mod ray;
+pub mod render;
+pub mod rng;
+pub mod sampler;
+pub mod scene;
+pub mod spectrum;
+pub mod texture;
 pub mod vector_additon;
 
 pub type Color = Vector3<f64>;
-
-pub fn random<T: SampleUniform, R: SampleRange<T>>(range: R) -> T {
-    thread_rng().gen_range(range)
-}
@@ -1,44 +1,206 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{f64::consts::PI, sync::Arc};
 
 use cgmath::{vec3, InnerSpace, Vector3};
 
 use crate::{
-    random,
+    microfacet::{
+        fresnel_conductor, fresnel_dielectric, fresnel_schlick, gtr1, reflect, refract,
+        sample_gtr1, Ggx, MIN_ALPHA,
+    },
+    onb::Onb,
     ray::{hittable::HitRecord, Ray},
+    sampler::Sampler,
+    spectrum::{Ior, LAMBDA_D},
+    texture::{SolidColor, Texture},
     vector_additon::VectorAdditions,
     Color,
 };
 
+pub struct ScatterRecord {
+    // bsdf * cos / pdf for the scattered direction
+    pub attenuation: Color,
+    pub scattered: Ray,
+    // solid angle pdf of the scattered direction, None for specular lobes
+    // that light sampling can't help with
+    pub pdf: Option<f64>,
+}
+
+impl ScatterRecord {
+    pub fn specular(attenuation: Color, scattered: Ray) -> Self {
+        Self {
+            attenuation,
+            scattered,
+            pdf: None,
+        }
+    }
+
+    // `wi` sampled with `pdf` from a material whose bsdf * cos there is `f`,
+    // all in `frame`
+    fn sampled(
+        r_in: &Ray,
+        rec: &HitRecord,
+        frame: &Onb,
+        wi: Vector3<f64>,
+        f: Color,
+        pdf: f64,
+    ) -> Option<Self> {
+        if pdf <= 0. || !pdf.is_finite() {
+            return None;
+        }
+        return Some(Self {
+            attenuation: f / pdf,
+            scattered: r_in.spawn(rec.p, frame.local(wi)),
+            pdf: Some(pdf),
+        });
+    }
+}
+
+// The frame around the normal the microfacet materials work in, and the
+// direction back along `r_in` in it.
+fn shading_frame(r_in: &Ray, rec: &HitRecord) -> (Onb, Vector3<f64>) {
+    let frame = Onb::from_w(rec.normal);
+    let wo = frame.to_local(-r_in.direction().normalize());
+    return (frame, wo);
+}
+
 pub trait Material: Send + Sync {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord>;
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        vec3(0., 0., 0.)
+    }
+    // bsdf * cos for light arriving from `wi`, only needed by materials whose
+    // scatter returns a pdf
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: Vector3<f64>) -> Color {
+        vec3(0., 0., 0.)
+    }
+    // the pdf scatter would have picked `wi` with
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _wi: Vector3<f64>) -> f64 {
+        0.
+    }
+    // the surface colour without lighting, for the albedo AOV
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        vec3(0., 0., 0.)
+    }
+    // the material's number in the scene for the material ID AOV, 0 for none
+    fn id(&self) -> u32 {
+        0
+    }
+    // true for phase functions, which are hit inside media rather than on a
+    // surface with a normal
+    fn is_volumetric(&self) -> bool {
+        false
+    }
+}
+
+// Gives `material` a number for the material ID AOV.
+pub struct TaggedMaterial {
+    material: Box<dyn Material>,
+    id: u32,
+}
+
+impl TaggedMaterial {
+    pub fn new(material: Box<dyn Material>, id: u32) -> Box<Self> {
+        return Box::new(Self { material, id });
+    }
+}
+
+impl Material for TaggedMaterial {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        self.material.scatter(r_in, rec, sampler)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.material.emitted(rec)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> Color {
+        self.material.eval(r_in, rec, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> f64 {
+        self.material.pdf(r_in, rec, wi)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn is_volumetric(&self) -> bool {
+        self.material.is_volumetric()
+    }
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Box<Self> {
+        return Self::textured(SolidColor::new(albedo));
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Box<Self> {
         return Box::new(Self { albedo });
     }
 }
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + Vector3::random_in_unit_sphere().normalize();
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        // normal + a random unit vector is cosine distributed around the normal
+        let mut scatter_direction = rec.normal + Vector3::sample_unit_sphere(sampler.get_2d());
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
-        return Some((self.albedo, Ray::new(rec.p, scatter_direction)));
+        return Some(ScatterRecord {
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            pdf: Some(self.pdf(r_in, rec, scatter_direction)),
+            scattered: r_in.spawn(rec.p, scatter_direction),
+        });
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> Color {
+        let cosine = rec.normal.dot(wi.normalize()).max(0.);
+        return self.albedo.value(rec.u, rec.v, rec.p) * cosine / PI;
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> f64 {
+        return rec.normal.dot(wi.normalize()).max(0.) / PI;
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        return self.albedo.value(rec.u, rec.v, rec.p);
     }
 }
 
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Box<Self> {
+        return Self::textured(SolidColor::new(albedo), fuzz);
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: f64) -> Box<Self> {
         return Box::new(Self {
             albedo,
             fuzz: if fuzz < 1. { fuzz } else { 1. },
@@ -46,25 +208,43 @@ impl Metal {
     }
 }
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let reflected = r_in.direction().normalize().reflect(rec.normal);
-        let scattered = Ray::new(
+        let scattered = r_in.spawn(
             rec.p,
-            reflected + (self.fuzz * Vector3::random_in_unit_sphere()),
+            reflected + (self.fuzz * Vector3::sample_unit_ball(sampler.get_2d(), sampler.get_1d())),
         );
         if scattered.direction().dot(rec.normal) > 0. {
-            return Some((self.albedo, scattered));
+            return Some(ScatterRecord::specular(
+                self.albedo.value(rec.u, rec.v, rec.p),
+                scattered,
+            ));
         }
         return None;
     }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        return self.albedo.value(rec.u, rec.v, rec.p);
+    }
 }
 
 pub struct Dielectric {
-    ir: f64,
+    ir: Ior,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Box<Self> {
+        return Self::dispersive(Ior::Constant(ir));
+    }
+
+    // Rendered spectrally, light of each wavelength bends by its own index
+    // of refraction. Otherwise it's the index at the d-line.
+    pub fn dispersive(ir: Ior) -> Box<Self> {
         return Box::new(Self { ir });
     }
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
@@ -75,23 +255,528 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let attenuation = vec3(1., 1., 1.);
-        let refraction_ratio = if rec.front_face {
-            1. / self.ir
-        } else {
-            self.ir
-        };
+        let ir = self.ir.at(r_in.wavelength().unwrap_or(LAMBDA_D));
+        let refraction_ratio = if rec.front_face { 1. / ir } else { ir };
         let unit_direction = r_in.direction().normalize();
         let cos_theta = (-unit_direction).dot(rec.normal).min(1.);
         let sin_theta = (1. - cos_theta * cos_theta).sqrt();
         let cannot_reflect = refraction_ratio * sin_theta > 1.;
-        let direction =
-            if cannot_reflect || Self::reflectance(cos_theta, refraction_ratio) > random(0. ..1.) {
-                unit_direction.reflect(rec.normal)
-            } else {
-                unit_direction.refract(rec.normal, refraction_ratio)
+        let direction = if cannot_reflect
+            || Self::reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
+        {
+            unit_direction.reflect(rec.normal)
+        } else {
+            unit_direction.refract(rec.normal, refraction_ratio)
+        };
+        let mut scattered = r_in.spawn(rec.p, direction);
+        if self.ir.is_dispersive() {
+            scattered = scattered.disperse();
+        }
+        return Some(ScatterRecord::specular(attenuation, scattered));
+    }
+
+    // clear glass, as denoisers expect
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        return vec3(1., 1., 1.);
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Box<Self> {
+        return Box::new(Self { emit });
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        self.emit
+    }
+}
+
+// Phase function of a participating medium, scatters equally in every direction.
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Box<Self> {
+        return Self::textured(SolidColor::new(albedo));
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Box<Self> {
+        return Box::new(Self { albedo });
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        return Some(ScatterRecord {
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            scattered: r_in.spawn(rec.p, Vector3::sample_unit_sphere(sampler.get_2d())),
+            pdf: Some(1. / (4. * PI)),
+        });
+    }
+
+    // there is no cosine term inside a medium
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, _wi: Vector3<f64>) -> Color {
+        return self.albedo.value(rec.u, rec.v, rec.p) / (4. * PI);
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _wi: Vector3<f64>) -> f64 {
+        return 1. / (4. * PI);
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        return self.albedo.value(rec.u, rec.v, rec.p);
+    }
+
+    fn is_volumetric(&self) -> bool {
+        true
+    }
+}
+
+// A metal with complex index of refraction eta + ik, rough with GGX
+// microfacets or a perfect mirror at roughness 0.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: Option<Ggx>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Box<Self> {
+        let distribution = Ggx::from_roughness(roughness);
+        return Box::new(Self {
+            eta,
+            k,
+            distribution: (roughness * roughness >= MIN_ALPHA).then_some(distribution),
+        });
+    }
+
+    fn eval_local(&self, ggx: &Ggx, wo: Vector3<f64>, wi: Vector3<f64>) -> Color {
+        if wo.z <= 0. || wi.z <= 0. {
+            return vec3(0., 0., 0.);
+        }
+        let h = (wo + wi).normalize();
+        let f = fresnel_conductor(wo.dot(h), self.eta, self.k);
+        return f * ggx.d(h) * ggx.g(wo, wi) / (4. * wo.z);
+    }
+
+    fn pdf_local(ggx: &Ggx, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        if wo.z <= 0. || wi.z <= 0. {
+            return 0.;
+        }
+        let h = (wo + wi).normalize();
+        return ggx.visible_normal_pdf(wo, h) / (4. * wo.dot(h));
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let (frame, wo) = shading_frame(r_in, rec);
+        if wo.z <= 0. {
+            return None;
+        }
+        let Some(ggx) = &self.distribution else {
+            let reflected = r_in.direction().normalize().reflect(rec.normal);
+            return Some(ScatterRecord::specular(
+                fresnel_conductor(wo.z, self.eta, self.k),
+                r_in.spawn(rec.p, reflected),
+            ));
+        };
+        let h = ggx.sample_visible_normal(wo, sampler.get_2d());
+        let wi = reflect(wo, h);
+        let (f, pdf) = (self.eval_local(ggx, wo, wi), Self::pdf_local(ggx, wo, wi));
+        return ScatterRecord::sampled(r_in, rec, &frame, wi, f, pdf);
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> Color {
+        let Some(ggx) = &self.distribution else {
+            return vec3(0., 0., 0.);
+        };
+        let (frame, wo) = shading_frame(r_in, rec);
+        return self.eval_local(ggx, wo, frame.to_local(wi.normalize()));
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> f64 {
+        let Some(ggx) = &self.distribution else {
+            return 0.;
+        };
+        let (frame, wo) = shading_frame(r_in, rec);
+        return Self::pdf_local(ggx, wo, frame.to_local(wi.normalize()));
+    }
+
+    // the colour seen head on
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        return fresnel_conductor(1., self.eta, self.k);
+    }
+}
+
+// Frosted glass: a dielectric interface with GGX microfacets after Walter et
+// al. Like Dielectric, refracted radiance isn't scaled by the change in
+// solid angle, which cancels out for closed objects.
+pub struct RoughDielectric {
+    ir: f64,
+    distribution: Ggx,
+}
+
+impl RoughDielectric {
+    pub fn new(ir: f64, roughness: f64) -> Box<Self> {
+        return Box::new(Self {
+            ir,
+            distribution: Ggx::from_roughness(roughness),
+        });
+    }
+
+    // index of refraction across the surface over the one on the ray's side
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        return if rec.front_face {
+            self.ir
+        } else {
+            1. / self.ir
+        };
+    }
+
+    // the microfacet normal, on wo's side, turning wo into wi
+    fn half_vector(wo: Vector3<f64>, wi: Vector3<f64>, eta: f64) -> Option<Vector3<f64>> {
+        let h = if wi.z > 0. { wo + wi } else { wo + eta * wi };
+        if h.magnitude2() == 0. {
+            return None;
+        }
+        let h = h.normalize();
+        let h = if h.z < 0. { -h } else { h };
+        if wo.dot(h) <= 0. || (wi.z < 0.) != (wi.dot(h) < 0.) {
+            return None;
+        }
+        return Some(h);
+    }
+
+    // bsdf * cos and the pdf of sampling it
+    fn eval_local(&self, wo: Vector3<f64>, wi: Vector3<f64>, eta: f64) -> (f64, f64) {
+        let ggx = &self.distribution;
+        if wo.z <= 0. || wi.z == 0. {
+            return (0., 0.);
+        }
+        let Some(h) = Self::half_vector(wo, wi, eta) else {
+            return (0., 0.);
+        };
+        let (oh, ih) = (wo.dot(h), wi.dot(h));
+        let fresnel = fresnel_dielectric(oh, eta);
+        let d = ggx.d(h);
+        let g = ggx.g(wo, wi);
+        let pdf_h = ggx.visible_normal_pdf(wo, h);
+        if wi.z > 0. {
+            return (fresnel * d * g / (4. * wo.z), fresnel * pdf_h / (4. * oh));
+        }
+        let denom = oh + eta * ih;
+        let jacobian = eta * eta * ih.abs() / (denom * denom);
+        return (
+            (1. - fresnel) * d * g * jacobian * oh / wo.z,
+            (1. - fresnel) * pdf_h * jacobian,
+        );
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let (frame, wo) = shading_frame(r_in, rec);
+        if wo.z <= 0. {
+            return None;
+        }
+        let eta = self.eta(rec);
+        let h = self
+            .distribution
+            .sample_visible_normal(wo, sampler.get_2d());
+        let wi = if sampler.get_1d() < fresnel_dielectric(wo.dot(h), eta) {
+            reflect(wo, h)
+        } else {
+            refract(wo, h, eta)?
+        };
+        let (f, pdf) = self.eval_local(wo, wi, eta);
+        return ScatterRecord::sampled(r_in, rec, &frame, wi, vec3(f, f, f), pdf);
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> Color {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let (f, _) = self.eval_local(wo, frame.to_local(wi.normalize()), self.eta(rec));
+        return vec3(f, f, f);
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> f64 {
+        let (frame, wo) = shading_frame(r_in, rec);
+        return self
+            .eval_local(wo, frame.to_local(wi.normalize()), self.eta(rec))
+            .1;
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        return vec3(1., 1., 1.);
+    }
+}
+
+// Burley's principled BSDF from Disney: a retro-reflective diffuse base, a
+// GGX specular lobe that goes from dielectric to metal with `metallic`, and a
+// GTR1 clearcoat layer on top. All parameters are 0 to 1.
+pub struct Principled {
+    base_color: Arc<dyn Texture>,
+    metallic: f64,
+    roughness: f64,
+    // scales the dielectric reflectance at normal incidence, 0.5 is 4%
+    specular: f64,
+    clearcoat: f64,
+    clearcoat_gloss: f64,
+    distribution: Ggx,
+}
+
+impl Principled {
+    pub fn new(
+        base_color: Arc<dyn Texture>,
+        metallic: f64,
+        roughness: f64,
+        specular: f64,
+        clearcoat: f64,
+        clearcoat_gloss: f64,
+    ) -> Box<Self> {
+        return Box::new(Self {
+            base_color,
+            metallic,
+            roughness,
+            specular,
+            clearcoat,
+            clearcoat_gloss,
+            distribution: Ggx::from_roughness(roughness),
+        });
+    }
+
+    fn clearcoat_alpha(&self) -> f64 {
+        return 0.1 + (0.001 - 0.1) * self.clearcoat_gloss;
+    }
+
+    // how often scatter picks the diffuse, specular and clearcoat lobes
+    fn lobe_weights(&self) -> [f64; 3] {
+        let weights = [1. - self.metallic, 1., 0.25 * self.clearcoat];
+        let total: f64 = weights.iter().sum();
+        return weights.map(|w| w / total);
+    }
+
+    fn eval_local(&self, base: Color, wo: Vector3<f64>, wi: Vector3<f64>) -> Color {
+        if wo.z <= 0. || wi.z <= 0. {
+            return vec3(0., 0., 0.);
+        }
+        let h = (wo + wi).normalize();
+        let cos_d = wi.dot(h);
+
+        let fd90 = 0.5 + 2. * self.roughness * cos_d * cos_d;
+        let retro = |cos: f64| 1. + (fd90 - 1.) * (1. - cos).powi(5);
+        let diffuse = (1. - self.metallic) * base / PI * retro(wo.z) * retro(wi.z);
+
+        let dielectric = 0.08 * self.specular;
+        let f0 =
+            vec3(dielectric, dielectric, dielectric) * (1. - self.metallic) + base * self.metallic;
+        let ggx = &self.distribution;
+        let specular = fresnel_schlick(f0, cos_d) * ggx.d(h) * ggx.g(wo, wi) / (4. * wo.z * wi.z);
+
+        let coat_fresnel = 0.04 + 0.96 * (1. - cos_d).powi(5);
+        let coat_g = Ggx { alpha: 0.25 }.g(wo, wi);
+        let coat = 0.25 * self.clearcoat * coat_fresnel * gtr1(h, self.clearcoat_alpha()) * coat_g
+            / (4. * wo.z * wi.z);
+
+        return (diffuse + specular + vec3(coat, coat, coat)) * wi.z;
+    }
+
+    fn pdf_local(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        if wo.z <= 0. || wi.z <= 0. {
+            return 0.;
+        }
+        let h = (wo + wi).normalize();
+        let [diffuse, specular, coat] = self.lobe_weights();
+        return diffuse * wi.z / PI
+            + specular * self.distribution.visible_normal_pdf(wo, h) / (4. * wo.dot(h))
+            + coat * gtr1(h, self.clearcoat_alpha()) * h.z / (4. * wo.dot(h));
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let (frame, wo) = shading_frame(r_in, rec);
+        if wo.z <= 0. {
+            return None;
+        }
+        let [diffuse, specular, _] = self.lobe_weights();
+        let lobe = sampler.get_1d();
+        let u = sampler.get_2d();
+        let wi = if lobe < diffuse {
+            vec3(0., 0., 1.) + Vector3::sample_unit_sphere(u)
+        } else if lobe < diffuse + specular {
+            reflect(wo, self.distribution.sample_visible_normal(wo, u))
+        } else {
+            reflect(wo, sample_gtr1(self.clearcoat_alpha(), u))
+        };
+        if wi.z <= 0. {
+            return None;
+        }
+        let wi = wi.normalize();
+        let base = self.base_color.value(rec.u, rec.v, rec.p);
+        let (f, pdf) = (self.eval_local(base, wo, wi), self.pdf_local(wo, wi));
+        return ScatterRecord::sampled(r_in, rec, &frame, wi, f, pdf);
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> Color {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let base = self.base_color.value(rec.u, rec.v, rec.p);
+        return self.eval_local(base, wo, frame.to_local(wi.normalize()));
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<f64>) -> f64 {
+        let (frame, wo) = shading_frame(r_in, rec);
+        return self.pdf_local(wo, frame.to_local(wi.normalize()));
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        return self.base_color.value(rec.u, rec.v, rec.p);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{Point3, Vector3};
+
+    use super::*;
+    use crate::{rng::Rng, sampler::IndependentSampler};
+
+    // Checks scatter against eval and pdf: each sample's weight is eval / pdf
+    // there, and the mean weight, the reflectance, matches eval integrated
+    // over the sphere by uniform sampling.
+    fn check_sampling(material: &dyn Material, incoming: Vector3<f64>, front_face: bool) {
+        let r = Ray::new(Point3::new(0., 0., 1.), incoming);
+        let outward = if front_face {
+            vec3(0., 0., 1.)
+        } else {
+            vec3(0., 0., -1.)
+        };
+        let rec = HitRecord::new(1., Point3::new(0., 0., 0.), outward, &r, material);
+        let mut sampler = IndependentSampler::new(5);
+        let n = 200_000;
+        let mut sampled = vec3(0., 0., 0.);
+        for _ in 0..n {
+            let Some(srec) = material.scatter(&r, &rec, &mut sampler) else {
+                continue;
             };
-        return Some((attenuation, Ray::new(rec.p, direction)));
+            let wi = srec.scattered.direction();
+            let pdf = material.pdf(&r, &rec, wi);
+            assert!((srec.pdf.unwrap() - pdf).abs() <= 1e-6 * pdf);
+            let expected = material.eval(&r, &rec, wi) / pdf;
+            assert!((srec.attenuation - expected).magnitude() <= 1e-6 * expected.magnitude());
+            sampled += srec.attenuation;
+        }
+        sampled /= n as f64;
+
+        let mut rng = Rng::new(6);
+        let mut uniform = vec3(0., 0., 0.);
+        for _ in 0..n {
+            let wi = Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]);
+            uniform += 4. * PI * material.eval(&r, &rec, wi);
+        }
+        uniform /= n as f64;
+        assert!(
+            (sampled - uniform).magnitude() < 0.03 * uniform.magnitude().max(0.1),
+            "{:?} vs {:?}",
+            sampled,
+            uniform
+        );
+    }
+
+    #[test]
+    fn test_conductor() {
+        let gold = Conductor::new(vec3(0.143, 0.374, 1.442), vec3(3.983, 2.385, 1.603), 0.5);
+        check_sampling(gold.as_ref(), vec3(0.5, 0.2, -1.), true);
+        check_sampling(gold.as_ref(), vec3(1., 0., -0.3), true);
+    }
+
+    #[test]
+    fn test_rough_dielectric() {
+        let glass = RoughDielectric::new(1.5, 0.4);
+        check_sampling(glass.as_ref(), vec3(0.5, 0.2, -1.), true);
+        check_sampling(glass.as_ref(), vec3(0.3, 0., -1.), false);
+    }
+
+    #[test]
+    fn test_principled() {
+        let base = SolidColor::new(vec3(0.8, 0.3, 0.2));
+        let plastic = Principled::new(base.clone(), 0., 0.4, 0.5, 1., 0.);
+        check_sampling(plastic.as_ref(), vec3(0.5, 0.2, -1.), true);
+        let metal = Principled::new(base, 1., 0.5, 0.5, 0., 1.);
+        check_sampling(metal.as_ref(), vec3(1., 0., -0.5), true);
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let glass = Dielectric::dispersive(Ior::Cauchy { a: 1.5, b: 0.02 });
+        let r = Ray::new(Point3::new(0., 0., 1.), vec3(1., 0., -1.));
+        let rec = HitRecord::new(
+            1.,
+            Point3::new(0., 0., 0.),
+            vec3(0., 0., 1.),
+            &r,
+            glass.as_ref(),
+        );
+        let mut sampler = IndependentSampler::new(1);
+        // the sideways part of the refracted direction, by wavelength
+        let mut bend = |r: Ray| loop {
+            let srec = glass.scatter(&r, &rec, &mut sampler).unwrap();
+            let d = srec.scattered.direction().normalize();
+            if d.z < 0. {
+                return (d.x, srec.scattered.is_dispersed());
+            }
+        };
+        let (blue, dispersed) = bend(r.with_wavelength(400.));
+        assert!(dispersed);
+        let (red, _) = bend(r.with_wavelength(700.));
+        // blue bends more, and RGB rays see the d-line index undispersed
+        assert!(blue < red, "{} vs {}", blue, red);
+        let (d_line, dispersed) = bend(r);
+        assert!(!dispersed);
+        let n = 1.5 + 0.02 / (LAMBDA_D / 1000.).powi(2);
+        assert!((d_line - std::f64::consts::FRAC_1_SQRT_2 / n).abs() < 1e-9);
     }
 }
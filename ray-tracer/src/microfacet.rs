@@ -0,0 +1,222 @@
+use std::f64::consts::PI;
+
+use cgmath::{vec3, ElementWise, InnerSpace, Vector3};
+
+use crate::Color;
+
+// Everything here works in a local frame with the normal along z, so cos(theta)
+// of a unit vector is its z.
+
+// below this the distribution is too sharp to evaluate, materials treat it as
+// a perfect mirror instead
+pub const MIN_ALPHA: f64 = 1e-3;
+
+// The GGX (Trowbridge-Reitz) distribution of microfacet normals with Smith's
+// height correlated shadowing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ggx {
+    pub alpha: f64,
+}
+
+impl Ggx {
+    // the perceptually linear roughness of the principled model, alpha = r^2
+    pub fn from_roughness(roughness: f64) -> Self {
+        return Self {
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+        };
+    }
+
+    pub fn d(&self, h: Vector3<f64>) -> f64 {
+        if h.z <= 0. {
+            return 0.;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.z * h.z * (a2 - 1.) + 1.;
+        return a2 / (PI * t * t);
+    }
+
+    fn lambda(&self, w: Vector3<f64>) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 == 0. {
+            return f64::INFINITY;
+        }
+        let tan2 = (1. - cos2).max(0.) / cos2;
+        return ((1. + self.alpha * self.alpha * tan2).sqrt() - 1.) / 2.;
+    }
+
+    // the fraction of microfacets visible from `w`
+    pub fn g1(&self, w: Vector3<f64>) -> f64 {
+        return 1. / (1. + self.lambda(w));
+    }
+
+    // the fraction visible from both `wo` and `wi`
+    pub fn g(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        return 1. / (1. + self.lambda(wo) + self.lambda(wi));
+    }
+
+    // Heitz's sampling of the normals visible from `wo`, which must be above
+    // the surface. Never gives a normal facing away from wo.
+    pub fn sample_visible_normal(&self, wo: Vector3<f64>, u: [f64; 2]) -> Vector3<f64> {
+        // stretch to the hemisphere configuration
+        let vh = vec3(self.alpha * wo.x, self.alpha * wo.y, wo.z).normalize();
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0. {
+            vec3(-vh.y, vh.x, 0.) / len2.sqrt()
+        } else {
+            vec3(1., 0., 0.)
+        };
+        let t2 = vh.cross(t1);
+        let r = u[0].sqrt();
+        let phi = 2. * PI * u[1];
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + vh.z);
+        let p2 = (1. - s) * (1. - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * vh;
+        return vec3(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1e-6)).normalize();
+    }
+
+    // the pdf of sample_visible_normal picking `h`
+    pub fn visible_normal_pdf(&self, wo: Vector3<f64>, h: Vector3<f64>) -> f64 {
+        if wo.z <= 0. {
+            return 0.;
+        }
+        return self.g1(wo) * wo.dot(h).max(0.) * self.d(h) / wo.z;
+    }
+}
+
+// Burley's GTR1 distribution, a long tailed lobe for the clearcoat.
+pub fn gtr1(h: Vector3<f64>, alpha: f64) -> f64 {
+    if h.z <= 0. {
+        return 0.;
+    }
+    let a2 = alpha * alpha;
+    let t = 1. + (a2 - 1.) * h.z * h.z;
+    return (a2 - 1.) / (PI * a2.ln() * t);
+}
+
+// the inverse of gtr1's cdf, the pdf of the normal is gtr1(h) * h.z
+pub fn sample_gtr1(alpha: f64, u: [f64; 2]) -> Vector3<f64> {
+    let a2 = alpha * alpha;
+    let cos2 = (1. - a2.powf(1. - u[0])) / (1. - a2);
+    let cos = cos2.max(0.).sqrt();
+    let sin = (1. - cos2).max(0.).sqrt();
+    let phi = 2. * PI * u[1];
+    return vec3(sin * phi.cos(), sin * phi.sin(), cos);
+}
+
+// `wo` mirrored about `h`
+pub fn reflect(wo: Vector3<f64>, h: Vector3<f64>) -> Vector3<f64> {
+    return 2. * wo.dot(h) * h - wo;
+}
+
+// `wo` refracted through a surface with normal `h` on its side, where `eta` is
+// the index of refraction on the far side over the one on wo's side. None on
+// total internal reflection.
+pub fn refract(wo: Vector3<f64>, h: Vector3<f64>, eta: f64) -> Option<Vector3<f64>> {
+    let cos_i = wo.dot(h);
+    let sin2_t = (1. - cos_i * cos_i).max(0.) / (eta * eta);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    return Some(-wo / eta + (cos_i / eta - cos_t) * h);
+}
+
+// The exact unpolarised Fresnel reflectance of a dielectric interface, with
+// `eta` as in refract.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0., 1.);
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    return (parallel * parallel + perpendicular * perpendicular) / 2.;
+}
+
+// The Fresnel reflectance of a conductor with complex index of refraction
+// eta + ik per channel, from air.
+pub fn fresnel_conductor(cos_i: f64, eta: Color, k: Color) -> Color {
+    let cos_i = cos_i.clamp(0., 1.);
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1. - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2. * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        return (rs + rp) / 2.;
+    };
+    return vec3(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    );
+}
+
+pub fn fresnel_schlick(f0: Color, cos_i: f64) -> Color {
+    let white = vec3(1., 1., 1.);
+    return f0 + (white - f0).mul_element_wise(white * (1. - cos_i.clamp(0., 1.)).powi(5));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{rng::Rng, vector_additon::VectorAdditions};
+
+    #[test]
+    fn test_visible_normals_match_their_pdf() {
+        // the pdf integrates to one over the sphere of normals
+        let mut rng = Rng::new(3);
+        for alpha in [0.2, 0.5, 0.9] {
+            let ggx = Ggx { alpha };
+            let wo = vec3(0.6, 0.1, 0.5).normalize();
+            let n = 200_000;
+            let total: f64 = (0..n)
+                .map(|_| {
+                    let h = Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]);
+                    4. * PI * ggx.visible_normal_pdf(wo, h)
+                })
+                .sum::<f64>()
+                / n as f64;
+            assert!((total - 1.).abs() < 0.05, "{}: {}", alpha, total);
+
+            for _ in 0..1000 {
+                let h = ggx.sample_visible_normal(wo, [rng.uniform(), rng.uniform()]);
+                assert!(h.z > 0. && wo.dot(h) >= 0.);
+                assert!((h.magnitude() - 1.).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fresnel() {
+        // glass at normal incidence reflects 4%, and everything past the
+        // critical angle from inside
+        assert!((fresnel_dielectric(1., 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.1, 1. / 1.5), 1.);
+        assert!((fresnel_dielectric(0., 1.5) - 1.).abs() < 1e-12);
+        // a conductor with k = 0 is a dielectric
+        let f = fresnel_conductor(0.7, vec3(1.5, 1.5, 1.5), vec3(0., 0., 0.));
+        assert!((f.x - fresnel_dielectric(0.7, 1.5)).abs() < 1e-12);
+        // gold is yellow
+        let gold = fresnel_conductor(1., vec3(0.143, 0.374, 1.442), vec3(3.983, 2.385, 1.603));
+        assert!(gold.x > 0.9 && gold.z < 0.5, "{:?}", gold);
+
+        let h = vec3(0.1, 0.2, 0.9).normalize();
+        let wo = vec3(-0.3, 0.4, 0.8).normalize();
+        let wi = refract(wo, h, 1.5).unwrap();
+        // Snell's law
+        let sin = |w: Vector3<f64>| w.cross(h).magnitude();
+        assert!((sin(wo) - 1.5 * sin(wi)).abs() < 1e-12);
+        assert!(wi.dot(h) < 0.);
+        assert!((reflect(wo, h).dot(h) - wo.dot(h)).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{vec2, vec3, Point3, Vector3};
+
+use crate::{
+    material::{Dielectric, Lambertian, Material, Metal},
+    ray::triangle::{MeshData, MeshFace, TriangleMesh},
+    Color,
+};
+
+// The subset of an MTL entry that maps onto our materials.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub shininess: f64,
+    pub ior: f64,
+    pub dissolve: f64,
+    pub illum: u32,
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: vec3(0.8, 0.8, 0.8),
+            specular: vec3(0., 0., 0.),
+            shininess: 0.,
+            ior: 1.5,
+            dissolve: 1.,
+            illum: 2,
+        }
+    }
+
+    // illum 4, 6, 7 and translucent entries become glass, illum 3 or a
+    // dominant specular colour becomes metal, everything else is diffuse
+    pub fn to_material(&self) -> Box<dyn Material> {
+        let max = |c: Color| c.x.max(c.y).max(c.z);
+        if self.dissolve < 1. || matches!(self.illum, 4 | 6 | 7) {
+            return Dielectric::new(self.ior);
+        }
+        if self.illum == 3 || (max(self.specular) > 0. && max(self.specular) >= max(self.diffuse)) {
+            // map the phong exponent onto fuzz, sharper highlights mean less fuzz
+            let fuzz = (2. / (self.shininess + 2.)).sqrt();
+            return Metal::new(self.specular, fuzz);
+        }
+        return Lambertian::new(self.diffuse);
+    }
+}
+
+pub fn parse_mtl<R: BufRead>(reader: R) -> Result<Vec<MtlMaterial>> {
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        if keyword.starts_with('#') {
+            continue;
+        }
+        let args: Vec<&str> = tokens.collect();
+        let context = || format!("mtl line {}: `{}`", i + 1, line);
+
+        if keyword == "newmtl" {
+            let Some(name) = args.first() else {
+                bail!("{}: newmtl without a name", context());
+            };
+            materials.push(MtlMaterial::new(name));
+            continue;
+        }
+        let Some(current) = materials.last_mut() else {
+            // statements before the first newmtl have nothing to apply to
+            continue;
+        };
+        match keyword {
+            "Kd" => current.diffuse = parse_vec3(&args).with_context(context)?,
+            "Ks" => current.specular = parse_vec3(&args).with_context(context)?,
+            "Ns" => current.shininess = parse_f64(&args, 0).with_context(context)?,
+            "Ni" => current.ior = parse_f64(&args, 0).with_context(context)?,
+            "d" => current.dissolve = parse_f64(&args, 0).with_context(context)?,
+            "Tr" => current.dissolve = 1. - parse_f64(&args, 0).with_context(context)?,
+            "illum" => {
+                current.illum = args
+                    .first()
+                    .context("missing illumination model")
+                    .and_then(|s| Ok(s.parse()?))
+                    .with_context(context)?
+            }
+            _ => {}
+        }
+    }
+    return Ok(materials);
+}
+
+fn parse_f64(args: &[&str], idx: usize) -> Result<f64> {
+    let s = args
+        .get(idx)
+        .with_context(|| format!("expected at least {} values", idx + 1))?;
+    return s
+        .parse()
+        .with_context(|| format!("`{}` is not a number", s));
+}
+
+fn parse_vec3(args: &[&str]) -> Result<Vector3<f64>> {
+    return Ok(vec3(
+        parse_f64(args, 0)?,
+        parse_f64(args, 1)?,
+        parse_f64(args, 2)?,
+    ));
+}
+
+// OBJ indices are 1 based and may be negative, counting back from the end
+fn resolve_index(s: &str, len: usize) -> Result<usize> {
+    let idx: i64 = s
+        .parse()
+        .with_context(|| format!("`{}` is not an index", s))?;
+    let resolved = if idx < 0 { len as i64 + idx } else { idx - 1 };
+    if idx == 0 || resolved < 0 || resolved >= len as i64 {
+        bail!("index {} is out of range (have {})", idx, len);
+    }
+    return Ok(resolved as usize);
+}
+
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Parses an OBJ stream. `load_mtl` is called with each `mtllib` name and
+// returns the materials defined in it.
+pub fn parse_obj<R, F>(reader: R, mut load_mtl: F) -> Result<MeshData>
+where
+    R: BufRead,
+    F: FnMut(&str) -> Result<Vec<MtlMaterial>>,
+{
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut faces = Vec::new();
+
+    let mut library: HashMap<String, MtlMaterial> = HashMap::new();
+    // index 0 is the fallback for faces before any usemtl
+    let mut used: Vec<MtlMaterial> = vec![MtlMaterial::new("default")];
+    let mut used_index: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+        let context = || format!("obj line {}: `{}`", i + 1, line);
+
+        match keyword {
+            "v" => {
+                let p = parse_vec3(&args).with_context(context)?;
+                positions.push(Point3::new(p.x, p.y, p.z));
+            }
+            "vn" => normals.push(parse_vec3(&args).with_context(context)?),
+            "vt" => {
+                let u = parse_f64(&args, 0).with_context(context)?;
+                let v = if args.len() > 1 {
+                    parse_f64(&args, 1).with_context(context)?
+                } else {
+                    0.
+                };
+                uvs.push(vec2(u, v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    bail!("{}: a face needs at least 3 vertices", context());
+                }
+                let mut vertices = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let mut parts = arg.split('/');
+                    let position = resolve_index(parts.next().unwrap_or(""), positions.len())
+                        .with_context(context)?;
+                    let uv = match parts.next() {
+                        Some(s) if !s.is_empty() => {
+                            Some(resolve_index(s, uvs.len()).with_context(context)?)
+                        }
+                        _ => None,
+                    };
+                    let normal = match parts.next() {
+                        Some(s) if !s.is_empty() => {
+                            Some(resolve_index(s, normals.len()).with_context(context)?)
+                        }
+                        _ => None,
+                    };
+                    vertices.push(FaceVertex {
+                        position,
+                        uv,
+                        normal,
+                    });
+                }
+                // polygons are triangulated as a fan around their first vertex
+                for k in 1..vertices.len() - 1 {
+                    let tri = [&vertices[0], &vertices[k], &vertices[k + 1]];
+                    let all = |f: &dyn Fn(&FaceVertex) -> Option<usize>| {
+                        let [a, b, c] = tri.map(f);
+                        Some([a?, b?, c?])
+                    };
+                    faces.push(MeshFace {
+                        vertices: tri.map(|v| v.position),
+                        normals: all(&|v| v.normal),
+                        uvs: all(&|v| v.uv),
+                        material: current_material,
+                    });
+                }
+            }
+            "mtllib" => {
+                for name in args.iter() {
+                    for mtl in load_mtl(name).with_context(context)? {
+                        library.insert(mtl.name.clone(), mtl);
+                    }
+                }
+            }
+            "usemtl" => {
+                let Some(name) = args.first() else {
+                    bail!("{}: usemtl without a name", context());
+                };
+                current_material = match used_index.get(*name) {
+                    Some(&idx) => idx,
+                    None => {
+                        let Some(mtl) = library.get(*name) else {
+                            bail!("{}: material `{}` is not defined", context(), name);
+                        };
+                        used.push(mtl.clone());
+                        used_index.insert(name.to_string(), used.len() - 1);
+                        used.len() - 1
+                    }
+                };
+            }
+            _ => {}
+        }
+    }
+
+    return Ok(MeshData {
+        positions,
+        normals,
+        uvs,
+        faces,
+        materials: used.iter().map(|m| m.to_material()).collect(),
+    });
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<TriangleMesh> {
+    let path = path.as_ref();
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let data = parse_obj(BufReader::new(file), |name| {
+        let mtl_path = dir.join(name);
+        let file = File::open(&mtl_path)
+            .with_context(|| format!("failed to open {}", mtl_path.display()))?;
+        parse_mtl(BufReader::new(file))
+    })
+    .with_context(|| format!("failed to load {}", path.display()))?;
+    return TriangleMesh::new(data);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MTL: &str = "
+# two materials
+newmtl red
+Kd 0.8 0.1 0.1
+newmtl glass
+Ni 1.45
+d 0.2
+";
+
+    const OBJ: &str = "
+mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+usemtl red
+f -4 -3 -2
+usemtl glass
+f 1//1 3//1 4//1
+";
+
+    #[test]
+    fn test_parse_obj() {
+        let mut requested = Vec::new();
+        let data = parse_obj(OBJ.as_bytes(), |name| {
+            requested.push(name.to_string());
+            parse_mtl(MTL.as_bytes())
+        })
+        .unwrap();
+
+        assert_eq!(requested, vec!["scene.mtl"]);
+        assert_eq!(data.positions.len(), 4);
+        // the quad is split in two, plus one triangle per usemtl block
+        assert_eq!(data.faces.len(), 4);
+        assert_eq!(data.materials.len(), 3);
+        assert_eq!(
+            data.faces[1],
+            MeshFace {
+                vertices: [0, 2, 3],
+                normals: Some([0, 0, 0]),
+                uvs: Some([0, 2, 3]),
+                material: 0,
+            }
+        );
+        assert_eq!(data.faces[2].vertices, [0, 1, 2]);
+        assert_eq!(data.faces[2].material, 1);
+        assert_eq!(data.faces[3].uvs, None);
+        assert_eq!(data.faces[3].material, 2);
+        assert!(TriangleMesh::new(data).is_ok());
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let err = parse_obj("v 0 0 0\nf 1 2 3\n".as_bytes(), |_| Ok(vec![]))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("obj line 2"));
+        let err = parse_obj("usemtl missing\n".as_bytes(), |_| Ok(vec![]))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("missing"));
+    }
+
+    #[test]
+    fn test_mtl_mapping() {
+        let materials = parse_mtl(MTL.as_bytes()).unwrap();
+        assert_eq!(materials[0].diffuse, vec3(0.8, 0.1, 0.1));
+        assert_eq!(materials[1].ior, 1.45);
+        assert_eq!(materials[1].dissolve, 0.2);
+    }
+}
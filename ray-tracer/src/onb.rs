@@ -0,0 +1,32 @@
+use cgmath::{vec3, InnerSpace, Vector3};
+
+// An orthonormal basis built around w, for sampling directions relative to a normal.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vector3<f64>,
+    pub v: Vector3<f64>,
+    pub w: Vector3<f64>,
+}
+
+impl Onb {
+    pub fn from_w(n: Vector3<f64>) -> Self {
+        let w = n.normalize();
+        let a = if w.x.abs() > 0.9 {
+            vec3(0., 1., 0.)
+        } else {
+            vec3(1., 0., 0.)
+        };
+        let v = w.cross(a).normalize();
+        let u = w.cross(v);
+        return Self { u, v, w };
+    }
+
+    pub fn local(&self, a: Vector3<f64>) -> Vector3<f64> {
+        return a.x * self.u + a.y * self.v + a.z * self.w;
+    }
+
+    // the inverse of local
+    pub fn to_local(&self, a: Vector3<f64>) -> Vector3<f64> {
+        return vec3(a.dot(self.u), a.dot(self.v), a.dot(self.w));
+    }
+}
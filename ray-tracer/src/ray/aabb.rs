@@ -0,0 +1,84 @@
+use cgmath::{EuclideanSpace, Point3, Vector3};
+
+use super::Ray;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub minimum: Point3<f64>,
+    pub maximum: Point3<f64>,
+}
+
+impl Aabb {
+    pub fn new(minimum: Point3<f64>, maximum: Point3<f64>) -> Self {
+        Self { minimum, maximum }
+    }
+
+    // builds a box from two arbitrary corners, sorting each axis
+    pub fn from_points(a: Point3<f64>, b: Point3<f64>) -> Self {
+        Self {
+            minimum: Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            maximum: Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
+        return Aabb::new(
+            Point3::new(
+                box0.minimum.x.min(box1.minimum.x),
+                box0.minimum.y.min(box1.minimum.y),
+                box0.minimum.z.min(box1.minimum.z),
+            ),
+            Point3::new(
+                box0.maximum.x.max(box1.maximum.x),
+                box0.maximum.y.max(box1.maximum.y),
+                box0.maximum.z.max(box1.maximum.z),
+            ),
+        );
+    }
+
+    pub fn extend(&self, p: Point3<f64>) -> Aabb {
+        return Aabb::surrounding_box(self, &Aabb::new(p, p));
+    }
+
+    pub fn hit(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for a in 0..3 {
+            let inv_d = 1. / r.dir[a];
+            let mut t0 = (self.minimum[a] - r.orig[a]) * inv_d;
+            let mut t1 = (self.maximum[a] - r.orig[a]) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // written so a NaN from a 0 * inf slab test never shrinks the interval
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    pub fn centroid(&self) -> Point3<f64> {
+        return self.minimum.midpoint(self.maximum);
+    }
+
+    pub fn diagonal(&self) -> Vector3<f64> {
+        return self.maximum - self.minimum;
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.diagonal();
+        return 2. * (d.x * d.y + d.y * d.z + d.z * d.x);
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.diagonal();
+        if d.x > d.y && d.x > d.z {
+            return 0;
+        }
+        if d.y > d.z {
+            return 1;
+        }
+        return 2;
+    }
+}
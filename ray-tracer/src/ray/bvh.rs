@@ -0,0 +1,303 @@
+use anyhow::{bail, Result};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable, HittableList},
+    Ray,
+};
+
+const SAH_BUCKETS: usize = 12;
+const SAH_TRAVERSAL_COST: f64 = 0.125;
+const MAX_LEAF_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitHeuristic {
+    // split the centroid bounds in half along the longest axis
+    Midpoint,
+    // bucketed surface area heuristic
+    #[default]
+    Sah,
+}
+
+enum BvhKind {
+    Leaf(Vec<Box<dyn Hittable>>),
+    Branch {
+        axis: usize,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+pub struct BvhNode {
+    bbox: Aabb,
+    kind: BvhKind,
+}
+
+struct Primitive {
+    object: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList, heuristic: SplitHeuristic) -> Result<Self> {
+        if list.is_empty() {
+            bail!("cannot build a BVH from an empty list");
+        }
+        let mut primitives = Vec::with_capacity(list.len());
+        for (i, object) in list.into_objects().into_iter().enumerate() {
+            let Some(bbox) = object.bounding_box() else {
+                bail!(
+                    "object {} has no bounding box and cannot be put in a BVH",
+                    i
+                );
+            };
+            primitives.push(Primitive { object, bbox });
+        }
+        return Ok(Self::build(primitives, heuristic));
+    }
+
+    fn leaf(primitives: Vec<Primitive>) -> Self {
+        let bbox = primitives
+            .iter()
+            .skip(1)
+            .fold(primitives[0].bbox, |acc, p| {
+                Aabb::surrounding_box(&acc, &p.bbox)
+            });
+        return Self {
+            bbox,
+            kind: BvhKind::Leaf(primitives.into_iter().map(|p| p.object).collect()),
+        };
+    }
+
+    fn build(mut primitives: Vec<Primitive>, heuristic: SplitHeuristic) -> Self {
+        if primitives.len() == 1 {
+            return Self::leaf(primitives);
+        }
+
+        let centroid_bounds = primitives.iter().skip(1).fold(
+            Aabb::new(primitives[0].bbox.centroid(), primitives[0].bbox.centroid()),
+            |acc, p| acc.extend(p.bbox.centroid()),
+        );
+        let axis = centroid_bounds.longest_axis();
+        let extent = centroid_bounds.diagonal()[axis];
+        if extent <= 0. {
+            // every centroid coincides, nothing to split on
+            if primitives.len() <= MAX_LEAF_SIZE {
+                return Self::leaf(primitives);
+            }
+            let right = primitives.split_off(primitives.len() / 2);
+            return Self::branch(axis, primitives, right, heuristic);
+        }
+
+        let split = match heuristic {
+            SplitHeuristic::Midpoint => {
+                let mid = centroid_bounds.centroid()[axis];
+                Some(Self::partition(&mut primitives, |p| {
+                    p.bbox.centroid()[axis] < mid
+                }))
+            }
+            SplitHeuristic::Sah => Self::sah_split(&mut primitives, axis, &centroid_bounds),
+        };
+
+        let mid = match split {
+            Some(mid) if mid != 0 && mid != primitives.len() => mid,
+            Some(_) => {
+                // degenerate partition, fall back to splitting at the median centroid
+                primitives
+                    .sort_by(|a, b| a.bbox.centroid()[axis].total_cmp(&b.bbox.centroid()[axis]));
+                primitives.len() / 2
+            }
+            None => return Self::leaf(primitives),
+        };
+
+        let right = primitives.split_off(mid);
+        return Self::branch(axis, primitives, right, heuristic);
+    }
+
+    fn branch(
+        axis: usize,
+        left: Vec<Primitive>,
+        right: Vec<Primitive>,
+        heuristic: SplitHeuristic,
+    ) -> Self {
+        let left = Box::new(Self::build(left, heuristic));
+        let right = Box::new(Self::build(right, heuristic));
+        return Self {
+            bbox: Aabb::surrounding_box(&left.bbox, &right.bbox),
+            kind: BvhKind::Branch { axis, left, right },
+        };
+    }
+
+    // moves every primitive matching `pred` to the front and returns how many there were
+    fn partition<F: Fn(&Primitive) -> bool>(primitives: &mut [Primitive], pred: F) -> usize {
+        let mut first = 0;
+        for i in 0..primitives.len() {
+            if pred(&primitives[i]) {
+                primitives.swap(first, i);
+                first += 1;
+            }
+        }
+        return first;
+    }
+
+    // returns None when keeping the primitives in a single leaf is cheaper than any split
+    fn sah_split(
+        primitives: &mut [Primitive],
+        axis: usize,
+        centroid_bounds: &Aabb,
+    ) -> Option<usize> {
+        let min = centroid_bounds.minimum[axis];
+        let extent = centroid_bounds.diagonal()[axis];
+        let bucket_of = |p: &Primitive| {
+            let b = (SAH_BUCKETS as f64 * (p.bbox.centroid()[axis] - min) / extent) as usize;
+            b.min(SAH_BUCKETS - 1)
+        };
+
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut bounds: [Option<Aabb>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+        for p in primitives.iter() {
+            let b = bucket_of(p);
+            counts[b] += 1;
+            bounds[b] = Some(match bounds[b] {
+                Some(bbox) => Aabb::surrounding_box(&bbox, &p.bbox),
+                None => p.bbox,
+            });
+        }
+
+        let union = |range: &[Option<Aabb>]| {
+            range.iter().flatten().fold(None, |acc: Option<Aabb>, b| {
+                Some(acc.map_or(*b, |acc| Aabb::surrounding_box(&acc, b)))
+            })
+        };
+        let total_area = union(&bounds).map_or(0., |b| b.surface_area());
+
+        let mut best: Option<(usize, f64)> = None;
+        for split in 1..SAH_BUCKETS {
+            let left_count: usize = counts[..split].iter().sum();
+            let right_count: usize = counts[split..].iter().sum();
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let left_area = union(&bounds[..split]).map_or(0., |b| b.surface_area());
+            let right_area = union(&bounds[split..]).map_or(0., |b| b.surface_area());
+            let cost = if total_area > 0. {
+                SAH_TRAVERSAL_COST
+                    + (left_count as f64 * left_area + right_count as f64 * right_area) / total_area
+            } else {
+                SAH_TRAVERSAL_COST + left_count.max(right_count) as f64
+            };
+            if best.is_none_or(|(_, c)| cost < c) {
+                best = Some((split, cost));
+            }
+        }
+
+        let leaf_cost = primitives.len() as f64;
+        return match best {
+            Some((_, cost)) if primitives.len() <= MAX_LEAF_SIZE && cost >= leaf_cost => None,
+            Some((split, _)) => Some(Self::partition(primitives, |p| bucket_of(p) < split)),
+            None if primitives.len() <= MAX_LEAF_SIZE => None,
+            None => Some(0),
+        };
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+        match &self.kind {
+            BvhKind::Leaf(objects) => {
+                let mut closest: Option<HitRecord> = None;
+                for object in objects.iter() {
+                    let t = closest.as_ref().map_or(t_max, |rec| rec.t);
+                    if let Some(rec) = object.hit(r, t_min, t) {
+                        closest = Some(rec);
+                    }
+                }
+                return closest;
+            }
+            BvhKind::Branch { axis, left, right } => {
+                // visit the child nearer to the ray origin first so the far one is usually culled
+                let (first, second) = if r.dir[*axis] < 0. {
+                    (right, left)
+                } else {
+                    (left, right)
+                };
+                let hit_first = first.hit(r, t_min, t_max);
+                let t = hit_first.as_ref().map_or(t_max, |rec| rec.t);
+                return second.hit(r, t_min, t).or(hit_first);
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{vec3, EuclideanSpace, Point3, Vector3};
+
+    use super::*;
+    use crate::{
+        material::Lambertian, ray::hittable::Sphere, rng::Rng, vector_additon::VectorAdditions,
+    };
+
+    fn sphere_list(n: usize) -> HittableList {
+        let mut rng = Rng::new(n as u64);
+        let mut world = HittableList::default();
+        for _ in 0..n {
+            let center = Point3::from_vec(Vector3::random(-10. ..10., &mut rng));
+            world.add(Box::new(Sphere::new(
+                center,
+                rng.range(0.05..0.8),
+                Lambertian::new(vec3(0.5, 0.5, 0.5)),
+            )));
+        }
+        return world;
+    }
+
+    #[test]
+    fn test_bvh_matches_linear_list() {
+        let mut rng = Rng::new(0);
+        for heuristic in [SplitHeuristic::Midpoint, SplitHeuristic::Sah] {
+            let mut linear = HittableList::default();
+            let mut copy = HittableList::default();
+            for _ in 0..500 {
+                let center = Point3::from_vec(Vector3::random(-10. ..10., &mut rng));
+                let radius = rng.range(0.05..0.8);
+                linear.add(Box::new(Sphere::new(
+                    center,
+                    radius,
+                    Lambertian::new(vec3(0.5, 0.5, 0.5)),
+                )));
+                copy.add(Box::new(Sphere::new(
+                    center,
+                    radius,
+                    Lambertian::new(vec3(0.5, 0.5, 0.5)),
+                )));
+            }
+            let bvh = BvhNode::new(copy, heuristic).unwrap();
+
+            for _ in 0..2000 {
+                let origin = Point3::from_vec(Vector3::random(-15. ..15., &mut rng));
+                let dir = Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]);
+                let r = Ray::new(origin, dir);
+                let expected = linear.hit(&r, 0.001, f64::INFINITY).map(|rec| rec.t);
+                let actual = bvh.hit(&r, 0.001, f64::INFINITY).map(|rec| rec.t);
+                assert_eq!(expected, actual, "{:?}", heuristic);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bvh_bounds_contain_objects() {
+        let world = sphere_list(100);
+        let expected = world.bounding_box().unwrap();
+        let bvh = BvhNode::new(world, SplitHeuristic::Sah).unwrap();
+        assert_eq!(bvh.bounding_box(), Some(expected));
+        assert!(BvhNode::new(HittableList::default(), SplitHeuristic::Sah).is_err());
+    }
+}
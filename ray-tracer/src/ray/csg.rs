@@ -0,0 +1,256 @@
+use cgmath::Point3;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    Ray,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // the left solid with the right one cut away
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(&self, left: bool, right: bool) -> bool {
+        match self {
+            CsgOperation::Union => left || right,
+            CsgOperation::Intersection => left && right,
+            CsgOperation::Difference => left && !right,
+        }
+    }
+}
+
+// The solid combining two others. Both have to be closed, so that going
+// along a ray their surfaces alternate between entering and leaving them,
+// and the result's surface is wherever being inside it changes. Surfaces of
+// the right solid cut out by a difference keep its material.
+pub struct Csg {
+    operation: CsgOperation,
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+    bbox: Option<Aabb>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        let (a, b) = (left.bounding_box(), right.bounding_box());
+        let bbox = match operation {
+            CsgOperation::Union => a.zip(b).map(|(a, b)| Aabb::surrounding_box(&a, &b)),
+            CsgOperation::Intersection => match (a, b) {
+                (Some(a), Some(b)) => Some(overlap(&a, &b).unwrap_or(a)),
+                _ => a.or(b),
+            },
+            CsgOperation::Difference => a,
+        };
+        return Self {
+            operation,
+            left,
+            right,
+            bbox,
+        };
+    }
+}
+
+fn overlap(a: &Aabb, b: &Aabb) -> Option<Aabb> {
+    let min = Point3::new(
+        a.minimum.x.max(b.minimum.x),
+        a.minimum.y.max(b.minimum.y),
+        a.minimum.z.max(b.minimum.z),
+    );
+    let max = Point3::new(
+        a.maximum.x.min(b.maximum.x),
+        a.maximum.y.min(b.maximum.y),
+        a.maximum.z.min(b.maximum.z),
+    );
+    if (0..3).any(|i| min[i] > max[i]) {
+        return None;
+    }
+    return Some(Aabb::new(min, max));
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        return self.hits(r, t_min, t_max).into_iter().next();
+    }
+
+    fn hits(&self, r: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        // whether the ray starts inside either solid shows in whether its
+        // first crossing leaves it, so look past t_max
+        let left = self.left.hits(r, t_min, f64::INFINITY);
+        let right = self.right.hits(r, t_min, f64::INFINITY);
+        let starts_inside = |hits: &[HitRecord]| hits.first().is_some_and(|rec| !rec.front_face);
+        let mut in_left = starts_inside(&left);
+        let mut in_right = starts_inside(&right);
+        let mut inside = self.operation.inside(in_left, in_right);
+
+        let mut hits = Vec::new();
+        let mut left = left.into_iter().peekable();
+        let mut right = right.into_iter().peekable();
+        loop {
+            let from_left = match (left.peek(), right.peek()) {
+                (Some(a), Some(b)) => a.t <= b.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let mut rec = if from_left {
+                let rec = left.next().unwrap();
+                in_left = rec.front_face;
+                rec
+            } else {
+                let rec = right.next().unwrap();
+                in_right = rec.front_face;
+                rec
+            };
+            if rec.t >= t_max {
+                break;
+            }
+            let now_inside = self.operation.inside(in_left, in_right);
+            if now_inside != inside {
+                // the normal already faces the ray, only which side is out
+                // can change, as on the walls of a hole
+                rec.front_face = now_inside;
+                hits.push(rec);
+                inside = now_inside;
+            }
+        }
+        return hits;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{vec3, MetricSpace, Vector3};
+
+    use super::*;
+    use crate::{
+        material::{Lambertian, Material},
+        ray::{hittable::Sphere, quad::Cuboid, shapes::Cylinder},
+        rng::Rng,
+        vector_additon::VectorAdditions,
+    };
+
+    fn gray() -> Box<dyn Material> {
+        Lambertian::new(vec3(0.5, 0.5, 0.5))
+    }
+
+    fn sphere(x: f64, radius: f64) -> Box<dyn Hittable> {
+        Box::new(Sphere::new(Point3::new(x, 0., 0.), radius, gray()))
+    }
+
+    fn ts(hits: &[HitRecord]) -> Vec<(f64, bool)> {
+        hits.iter()
+            .map(|rec| ((rec.t * 1e6).round() / 1e6, rec.front_face))
+            .collect()
+    }
+
+    #[test]
+    fn test_operations_along_a_ray() {
+        // two unit spheres overlapping between x = 0 and 1, seen along x
+        let r = Ray::new(Point3::new(-5., 0., 0.), vec3(1., 0., 0.));
+        let csg = |operation| Csg::new(operation, sphere(0., 1.), sphere(1., 1.));
+
+        let union = csg(CsgOperation::Union);
+        assert_eq!(
+            ts(&union.hits(&r, 0.001, f64::INFINITY)),
+            [(4., true), (7., false)]
+        );
+        let both = csg(CsgOperation::Intersection);
+        assert_eq!(
+            ts(&both.hits(&r, 0.001, f64::INFINITY)),
+            [(5., true), (6., false)]
+        );
+        let difference = csg(CsgOperation::Difference);
+        let hits = difference.hits(&r, 0.001, f64::INFINITY);
+        assert_eq!(ts(&hits), [(4., true), (5., false)]);
+        // the cut is the inside of the right sphere, facing back at the ray
+        assert_eq!(hits[1].normal, vec3(-1., 0., 0.));
+
+        // starting inside the union, and stopping at t_max
+        let inside = Ray::new(Point3::new(0.5, 0., 0.), vec3(1., 0., 0.));
+        assert_eq!(
+            ts(&union.hits(&inside, 0.001, f64::INFINITY)),
+            [(1.5, false)]
+        );
+        assert!(union.hit(&inside, 0.001, 1.).is_none());
+        assert!(difference.hit(&inside, 0.001, f64::INFINITY).is_none());
+
+        // CSG nests, a sphere with a hole straight through it
+        let hole = Cylinder::new(
+            Point3::new(-3., 0., 0.),
+            Point3::new(3., 0., 0.),
+            0.5,
+            true,
+            gray(),
+        );
+        let bead = Csg::new(
+            CsgOperation::Difference,
+            Box::new(csg(CsgOperation::Union)),
+            Box::new(hole),
+        );
+        assert!(bead.hit(&r, 0.001, f64::INFINITY).is_none());
+        let down = Ray::new(Point3::new(-0.6, 5., 0.), vec3(0., -1., 0.));
+        assert_eq!(
+            ts(&bead.hits(&down, 0.001, f64::INFINITY)),
+            [(4.2, true), (4.5, false), (5.5, true), (5.8, false)]
+        );
+        let bbox = bead.bounding_box().unwrap();
+        assert_eq!((bbox.minimum.x, bbox.maximum.x), (-1., 2.));
+    }
+
+    #[test]
+    fn test_lens_surface() {
+        // a biconvex lens from two spheres, every hit on it is on one of
+        // them and inside the other
+        let (a, b) = (Point3::new(-1.5, 0., 0.), Point3::new(1.5, 0., 0.));
+        let lens = Csg::new(
+            CsgOperation::Intersection,
+            Box::new(Sphere::new(a, 2., gray())),
+            Box::new(Sphere::new(b, 2., gray())),
+        );
+        let cube = Cuboid::new(
+            Point3::new(-0.4, -0.4, -0.4),
+            Point3::new(0.4, 0.4, 0.4),
+            [gray(), gray(), gray(), gray(), gray(), gray()],
+        );
+        let cut = Csg::new(CsgOperation::Difference, Box::new(lens), Box::new(cube));
+        let mut rng = Rng::new(5);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let from = Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]);
+            let origin = Point3::new(0., 0., 0.) + 6. * from;
+            let r = Ray::new(
+                origin,
+                -from + 0.2 * Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]),
+            );
+            let all = cut.hits(&r, 0.001, f64::INFINITY);
+            assert!(all.len() % 2 == 0);
+            for (i, rec) in all.iter().enumerate() {
+                // entering and leaving alternate
+                assert_eq!(rec.front_face, i % 2 == 0);
+                let (da, db) = (rec.p.distance(a), rec.p.distance(b));
+                let in_cube = (0..3).all(|j| rec.p[j].abs() <= 0.4 + 1e-9);
+                let on_lens = ((da - 2.).abs() < 1e-9 && db <= 2. + 1e-9)
+                    || ((db - 2.).abs() < 1e-9 && da <= 2. + 1e-9);
+                let on_cube = (0..3).any(|j| (rec.p[j].abs() - 0.4).abs() < 1e-9);
+                assert!(
+                    on_lens && !in_cube || on_cube && da <= 2. && db <= 2.,
+                    "{:?}",
+                    rec
+                );
+            }
+            hits += all.len();
+        }
+        assert!(hits > 1000);
+    }
+}
@@ -1,24 +1,28 @@
-use std::{cell::RefCell, fmt::Debug, rc::Rc};
+use std::{f64::consts::PI, fmt::Debug};
 
-use cgmath::{vec3, InnerSpace, Point3, Vector3};
+use cgmath::{vec3, InnerSpace, MetricSpace, Point3, Vector3};
 
-use crate::material::Material;
+use crate::{material::Material, onb::Onb, sampler::Sampler};
 
-use super::Ray;
+use super::{aabb::Aabb, Ray};
 
 pub struct HitRecord<'a> {
     pub t: f64,
     pub p: Point3<f64>,
     pub normal: Vector3<f64>,
     pub front_face: bool,
+    pub u: f64,
+    pub v: f64,
     pub mat_ptr: &'a dyn Material,
+    // the scene object that was hit, for the object ID AOV, 0 for none
+    pub object_id: u32,
 }
 impl<'a> Debug for HitRecord<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Hit Record {{t: {:?}, p: {:?}, normal: {:?}, front_face: {:?}}}",
-            self.t, self.p, self.normal, self.front_face
+            "Hit Record {{t: {:?}, p: {:?}, normal: {:?}, front_face: {:?}, u: {:?}, v: {:?}}}",
+            self.t, self.p, self.normal, self.front_face, self.u, self.v
         )
     }
 }
@@ -36,7 +40,10 @@ impl<'a> HitRecord<'a> {
             p,
             normal: vec3(0., 0., 0.),
             front_face: false,
+            u: 0.,
+            v: 0.,
             mat_ptr,
+            object_id: 0,
         };
         hr.set_face_normal(r, outward_normal);
         return hr;
@@ -49,10 +56,51 @@ impl<'a> HitRecord<'a> {
             -outward_normal
         };
     }
+
+    pub fn with_uv(mut self, u: f64, v: f64) -> Self {
+        self.u = u;
+        self.v = v;
+        return self;
+    }
+
+    // replaces the normal with an interpolated one, keeping the side chosen by set_face_normal
+    pub fn set_shading_normal(&mut self, shading_normal: Vector3<f64>) {
+        let n = shading_normal.normalize();
+        self.normal = if n.dot(self.normal) < 0. { -n } else { n };
+    }
 }
 
+// relative distance between two hits that Hittable::hits tells apart
+const HITS_EPSILON: f64 = 1e-9;
+
 pub trait Hittable: Send + Sync {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // Every surface the ray crosses between t_min and t_max, nearest first,
+    // for CSG. Found by asking for the next hit past the last one, stepping
+    // a little past each so shapes solved numerically don't find it again.
+    fn hits(&self, r: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        let mut hits = Vec::new();
+        let mut t = t_min;
+        while let Some(rec) = self.hit(r, t, t_max) {
+            if rec.t <= t {
+                break;
+            }
+            t = rec.t + HITS_EPSILON * rec.t.abs().max(1.);
+            hits.push(rec);
+        }
+        return hits;
+    }
+
+    // Light sampling: the solid angle pdf of random_direction picking `dir`
+    // from `origin`. Shapes that can't be sampled leave these as they are.
+    fn pdf_value(&self, _origin: Point3<f64>, _dir: Vector3<f64>) -> f64 {
+        0.
+    }
+    fn random_direction(&self, _origin: Point3<f64>, _sampler: &mut dyn Sampler) -> Vector3<f64> {
+        vec3(1., 0., 0.)
+    }
 }
 
 #[derive(Default)]
@@ -61,7 +109,7 @@ pub struct HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let mut temp_record = None;
         let mut closest_so_far = t_max;
 
@@ -74,6 +122,37 @@ impl Hittable for HittableList {
 
         return temp_record;
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut objects = self.objects.iter();
+        let mut output_box = objects.next()?.bounding_box()?;
+        for object in objects {
+            output_box = Aabb::surrounding_box(&output_box, &object.bounding_box()?);
+        }
+        return Some(output_box);
+    }
+
+    // picks one of the objects uniformly
+    fn pdf_value(&self, origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        if self.objects.is_empty() {
+            return 0.;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|object| object.pdf_value(origin, dir))
+            .sum();
+        return sum / self.objects.len() as f64;
+    }
+
+    fn random_direction(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        if self.objects.is_empty() {
+            return vec3(1., 0., 0.);
+        }
+        let n = self.objects.len();
+        let idx = ((sampler.get_1d() * n as f64) as usize).min(n - 1);
+        return self.objects[idx].random_direction(origin, sampler);
+    }
 }
 
 impl HittableList {
@@ -90,6 +169,81 @@ impl HittableList {
     pub fn clear(&mut self) {
         self.objects.clear();
     }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable>> {
+        self.objects
+    }
+}
+
+// Gives `object` a number for the object ID AOV.
+pub struct Tagged {
+    object: Box<dyn Hittable>,
+    id: u32,
+}
+
+impl Tagged {
+    pub fn new(object: Box<dyn Hittable>, id: u32) -> Self {
+        Self { object, id }
+    }
+}
+
+impl Hittable for Tagged {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut rec = self.object.hit(r, t_min, t_max)?;
+        rec.object_id = self.id;
+        return Some(rec);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        self.object.pdf_value(origin, dir)
+    }
+
+    fn random_direction(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        self.object.random_direction(origin, sampler)
+    }
+}
+
+fn hit_sphere<'a>(
+    center: Point3<f64>,
+    radius: f64,
+    mat_ptr: &'a dyn Material,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    let oc = r.orig - center;
+    let a = r.dir.magnitude2();
+    let half_b = oc.dot(r.dir);
+    let c = oc.magnitude2() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let mut root = (-half_b - sqrtd) / a;
+    if root <= t_min || t_max <= root {
+        root = (-half_b + sqrtd) / a;
+        if root <= t_min || t_max <= root {
+            return None;
+        }
+    }
+    let t = root;
+    let p = r.at(t);
+    let outward_normal = (p - center) / radius;
+    let (u, v) = Sphere::uv(outward_normal);
+    return Some(HitRecord::new(t, p, outward_normal, r, mat_ptr).with_uv(u, v));
 }
 
 pub struct Sphere {
@@ -107,35 +261,181 @@ impl Sphere {
             mat_ptr,
         }
     }
+
+    // u goes around the y axis starting from -x, v from the bottom pole to the top
+    pub fn uv(outward_normal: Vector3<f64>) -> (f64, f64) {
+        let theta = (-outward_normal.y).clamp(-1., 1.).acos();
+        let phi = (-outward_normal.z).atan2(outward_normal.x) + PI;
+        return (phi / (2. * PI), theta / PI);
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = r.orig - self.center;
-        let a = r.dir.magnitude2();
-        let half_b = oc.dot(r.dir);
-        let c = oc.magnitude2() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0. {
-            return None;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        return hit_sphere(
+            self.center,
+            self.radius,
+            self.mat_ptr.as_ref(),
+            r,
+            t_min,
+            t_max,
+        );
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = vec3(self.radius, self.radius, self.radius);
+        return Some(Aabb::from_points(self.center - r, self.center + r));
+    }
+
+    // samples the cone of directions the sphere covers as seen from `origin`
+    fn pdf_value(&self, origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        let distance_squared = origin.distance2(self.center);
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared
+            || self
+                .hit(&Ray::new(origin, dir), 0.001, f64::INFINITY)
+                .is_none()
+        {
+            return 0.;
         }
-        let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        if root <= t_min || t_max <= root {
-            root = (-half_b + sqrtd) / a;
-            if root <= t_min || t_max <= root {
-                return None;
-            }
+        let cos_theta_max = (1. - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2. * PI * (1. - cos_theta_max);
+        return 1. / solid_angle;
+    }
+
+    fn random_direction(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let direction = self.center - origin;
+        let distance_squared = direction.magnitude2();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return direction;
         }
-        let t = root;
-        let p = r.at(t);
-        let outward_normal = (p - self.center) / self.radius;
-        return Some(HitRecord::new(
-            t,
-            p,
-            outward_normal,
-            r,
+        let [r1, r2] = sampler.get_2d();
+        let cos_theta_max = (1. - radius_squared / distance_squared).sqrt();
+        let z = 1. + r2 * (cos_theta_max - 1.);
+        let phi = 2. * PI * r1;
+        let sin_theta = (1. - z * z).sqrt();
+        let local = vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, z);
+        return Onb::from_w(direction).local(local);
+    }
+}
+
+// A sphere moving in a straight line from center0 at time0 to center1 at
+// time1. It holds still outside of that interval.
+pub struct MovingSphere {
+    pub center0: Point3<f64>,
+    pub center1: Point3<f64>,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub mat_ptr: Box<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point3<f64>,
+        center1: Point3<f64>,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        mat_ptr: Box<dyn Material>,
+    ) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            mat_ptr,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Point3<f64> {
+        if self.time1 <= self.time0 {
+            return self.center0;
+        }
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0., 1.);
+        return self.center0 + s * (self.center1 - self.center0);
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        return hit_sphere(
+            self.center(r.time()),
+            self.radius,
             self.mat_ptr.as_ref(),
-        ));
+            r,
+            t_min,
+            t_max,
+        );
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = vec3(self.radius, self.radius, self.radius);
+        let box0 = Aabb::from_points(self.center0 - r, self.center0 + r);
+        let box1 = Aabb::from_points(self.center1 - r, self.center1 + r);
+        return Some(Aabb::surrounding_box(&box0, &box1));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sphere_uv() {
+        let close = |(u, v): (f64, f64), (eu, ev): (f64, f64)| {
+            assert!(
+                (u - eu).abs() < 1e-12 && (v - ev).abs() < 1e-12,
+                "{:?}",
+                (u, v)
+            );
+        };
+        close(Sphere::uv(vec3(1., 0., 0.)), (0.5, 0.5));
+        close(Sphere::uv(vec3(0., 0., 1.)), (0.25, 0.5));
+        close(Sphere::uv(vec3(0., 0., -1.)), (0.75, 0.5));
+        assert_eq!(Sphere::uv(vec3(0., 1., 0.)).1, 1.);
+        assert_eq!(Sphere::uv(vec3(0., -1., 0.)).1, 0.);
+
+        // the hit record carries them
+        let sphere = Sphere::new(
+            Point3::new(0., 0., -3.),
+            2.,
+            crate::material::Lambertian::new(vec3(1., 1., 1.)),
+        );
+        let rec = sphere
+            .hit(
+                &Ray::new(Point3::new(0., 0., 0.), vec3(0., 0., -1.)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        close((rec.u, rec.v), (0.25, 0.5));
+    }
+
+    #[test]
+    fn test_moving_sphere_follows_ray_time() {
+        let sphere = MovingSphere::new(
+            Point3::new(0., 0., -3.),
+            Point3::new(2., 0., -3.),
+            0.,
+            1.,
+            0.5,
+            crate::material::Lambertian::new(vec3(1., 1., 1.)),
+        );
+        let at = |x: f64, time| {
+            let r = Ray::with_time(Point3::new(x, 0., 0.), vec3(0., 0., -1.), time);
+            sphere.hit(&r, 0.001, f64::INFINITY).is_some()
+        };
+        assert!(at(0., 0.) && !at(2., 0.));
+        assert!(!at(0., 1.) && at(2., 1.));
+        assert!(at(1., 0.5) && !at(0., 0.5));
+        // clamped outside the interval
+        assert!(at(2., 3.));
+
+        let bbox = sphere.bounding_box().unwrap();
+        assert_eq!(bbox.minimum, Point3::new(-0.5, -0.5, -3.5));
+        assert_eq!(bbox.maximum, Point3::new(2.5, 0.5, -2.5));
     }
 }
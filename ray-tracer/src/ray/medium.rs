@@ -0,0 +1,169 @@
+use cgmath::{vec3, InnerSpace};
+
+use crate::{
+    material::Material,
+    rng::{hash, Rng},
+};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    Ray,
+};
+
+// A homogeneous volume such as smoke or fog. Rays travelling through it
+// scatter after an exponentially distributed distance, off the
+// `phase_function` material (usually Isotropic). The boundary must be
+// closed and convex, and without one the medium fills all of space.
+pub struct ConstantMedium {
+    boundary: Option<Box<dyn Hittable>>,
+    density: f64,
+    phase_function: Box<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Box<dyn Hittable>,
+        density: f64,
+        phase_function: Box<dyn Material>,
+    ) -> Self {
+        Self {
+            boundary: Some(boundary),
+            density,
+            phase_function,
+        }
+    }
+
+    // fills everything, for fog over the whole scene
+    pub fn unbounded(density: f64, phase_function: Box<dyn Material>) -> Self {
+        Self {
+            boundary: None,
+            density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // the stretch of the ray inside the boundary, clipped to [t_min, t_max]
+        let (t_enter, t_exit) = match &self.boundary {
+            Some(boundary) => {
+                let enter = boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY)?;
+                let exit = boundary.hit(r, enter.t + 0.0001, f64::INFINITY)?;
+                (enter.t.max(t_min), exit.t.min(t_max))
+            }
+            None => (t_min, t_max),
+        };
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = r.direction().magnitude();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        // hit() has no generator to draw from, so the free path is seeded by
+        // the ray itself; every scattered ray differs, which keeps renders
+        // deterministic without correlating neighbouring paths
+        let seed = hash(&[
+            r.origin().x.to_bits(),
+            r.origin().y.to_bits(),
+            r.origin().z.to_bits(),
+            r.direction().x.to_bits(),
+            r.direction().y.to_bits(),
+            r.direction().z.to_bits(),
+            r.time().to_bits(),
+            t_enter.to_bits(),
+        ]);
+        let u = Rng::new(seed).uniform();
+        let hit_distance = -(1. - u).ln() / self.density;
+        if hit_distance > distance_inside {
+            return None;
+        }
+        let t = t_enter + hit_distance / ray_length;
+        // the normal means nothing inside a volume
+        return Some(HitRecord::new(
+            t,
+            r.at(t),
+            vec3(1., 0., 0.),
+            r,
+            self.phase_function.as_ref(),
+        ));
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.as_ref()?.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::Point3;
+
+    use super::*;
+    use crate::{material::Isotropic, ray::hittable::Sphere};
+
+    // fraction of `n` rays from `r` that get through `t_max` without
+    // scattering; the same ray always scatters the same way, so each copy is
+    // sent at a different time
+    fn transmittance(medium: &ConstantMedium, r: &Ray, t_max: f64, n: usize) -> f64 {
+        let passed = (0..n)
+            .map(|i| Ray::with_time(r.origin(), r.direction(), i as f64 / n as f64))
+            .filter(|r| medium.hit(r, 0.001, t_max).is_none())
+            .count();
+        return passed as f64 / n as f64;
+    }
+
+    #[test]
+    fn test_transmittance_follows_beers_law() {
+        let n = 20_000;
+        // 4 sigma of a binomial with p around 0.37
+        let tolerance = 4. * (0.25 / n as f64).sqrt();
+
+        let ball = ConstantMedium::new(
+            Box::new(Sphere::new(
+                Point3::new(0., 0., -5.),
+                1.,
+                Isotropic::new(vec3(1., 1., 1.)),
+            )),
+            0.5,
+            Isotropic::new(vec3(1., 1., 1.)),
+        );
+        // straight through the centre is 2 units of medium, whatever the
+        // length of the direction vector
+        let r = Ray::new(Point3::new(0., 0., 0.), vec3(0., 0., -3.));
+        let expected = (-0.5f64 * 2.).exp();
+        let got = transmittance(&ball, &r, f64::INFINITY, n);
+        assert!(
+            (got - expected).abs() < tolerance,
+            "{} vs {}",
+            got,
+            expected
+        );
+        // rays that miss the boundary are never scattered
+        let r = Ray::new(Point3::new(3., 0., 0.), vec3(0., 0., -1.));
+        assert_eq!(transmittance(&ball, &r, f64::INFINITY, 100), 1.);
+        // and a ray starting inside only sees the rest of the ball
+        let r = Ray::new(Point3::new(0., 0., -5.), vec3(0., 0., -1.));
+        let expected = (-0.5f64).exp();
+        let got = transmittance(&ball, &r, f64::INFINITY, n);
+        assert!(
+            (got - expected).abs() < tolerance,
+            "{} vs {}",
+            got,
+            expected
+        );
+
+        let fog = ConstantMedium::unbounded(0.1, Isotropic::new(vec3(1., 1., 1.)));
+        assert!(fog.bounding_box().is_none());
+        let r = Ray::new(Point3::new(0., 0., 0.), vec3(0., 2., 0.));
+        // t_max = 5 along a direction of length 2 is 10 units of fog
+        let expected = (-0.1f64 * 10.).exp();
+        let got = transmittance(&fog, &r, 5., n);
+        assert!(
+            (got - expected).abs() < tolerance,
+            "{} vs {}",
+            got,
+            expected
+        );
+    }
+}
@@ -1,15 +1,38 @@
+pub mod aabb;
+pub mod bvh;
+pub mod csg;
 pub mod hittable;
-
-use std::f64::INFINITY;
+pub mod medium;
+pub mod quad;
+pub mod sdf;
+pub mod shapes;
+pub mod transform;
+pub mod triangle;
 
 use cgmath::{vec3, ElementWise};
-use cgmath::{InnerSpace, Point3, Vector3};
+use cgmath::{Point3, Vector3};
+
+use crate::{
+    background::Background,
+    render::AovSample,
+    sampler::Sampler,
+    spectrum::{spectrum_value, wavelength_color},
+    Color,
+};
 
 use self::hittable::Hittable;
 
+#[derive(Debug, Clone, Copy)]
 pub struct Ray {
     orig: Point3<f64>,
     dir: Vector3<f64>,
+    // when during the shutter interval the ray was sent, for moving objects
+    time: f64,
+    // in nm, picked for each camera ray when rendering spectrally
+    wavelength: Option<f64>,
+    // whether the path has been through something dispersive, from then on
+    // it carries only light of its wavelength
+    dispersed: bool,
 }
 
 impl Default for Ray {
@@ -17,35 +40,156 @@ impl Default for Ray {
         Self {
             orig: Point3::new(0., 0., 0.),
             dir: vec3(0., 0., 0.),
+            time: 0.,
+            wavelength: None,
+            dispersed: false,
         }
     }
 }
 
 impl Ray {
     pub fn new(orig: Point3<f64>, dir: Vector3<f64>) -> Self {
-        Self { orig, dir }
+        Self::with_time(orig, dir, 0.)
+    }
+
+    pub fn with_time(orig: Point3<f64>, dir: Vector3<f64>, time: f64) -> Self {
+        Self {
+            orig,
+            dir,
+            time,
+            wavelength: None,
+            dispersed: false,
+        }
+    }
+
+    pub fn with_wavelength(mut self, wavelength: f64) -> Self {
+        self.wavelength = Some(wavelength);
+        return self;
+    }
+
+    // the next ray of the same path
+    pub fn spawn(&self, orig: Point3<f64>, dir: Vector3<f64>) -> Self {
+        Self { orig, dir, ..*self }
+    }
+
+    // Marks a ray leaving something dispersive. Only spectral rays can be.
+    pub fn disperse(mut self) -> Self {
+        self.dispersed = self.wavelength.is_some();
+        return self;
+    }
+
+    // Light of `color` reaching the camera along the ray's path. Once the
+    // path is dispersed it only stands for its wavelength, so that's all of
+    // the colour's spectrum that gets through.
+    pub fn spectral(&self, color: Color) -> Color {
+        match self.wavelength {
+            Some(lambda) if self.dispersed => {
+                wavelength_color(lambda) * spectrum_value(color, lambda)
+            }
+            _ => color,
+        }
     }
 
     pub fn at(&self, t: f64) -> Point3<f64> {
         return self.orig + (t * self.dir);
     }
 
-    pub fn color(&self, world: &dyn Hittable, depth: i32) -> Vector3<f64> {
+    pub fn color(
+        &self,
+        world: &dyn Hittable,
+        background: &Background,
+        depth: i32,
+        sampler: &mut dyn Sampler,
+        aov: Option<&mut AovSample>,
+    ) -> Vector3<f64> {
         if depth <= 0 {
             return vec3(0., 0., 0.);
         }
 
-        if let Some(rec) = world.hit(self, 0.001, INFINITY) {
-            if let Some((attenuation, scattered)) = rec.mat_ptr.scatter(self, &rec) {
-                return attenuation.mul_element_wise(scattered.color(world, depth - 1));
+        if let Some(rec) = world.hit(self, 0.001, f64::INFINITY) {
+            if let Some(aov) = aov {
+                *aov = AovSample::from_hit(self, &rec);
             }
-            return vec3(0., 0., 0.);
+            let emitted = rec.mat_ptr.emitted(&rec);
+            if let Some(srec) = rec.mat_ptr.scatter(self, &rec, sampler) {
+                let mut incoming = srec.attenuation.mul_element_wise(srec.scattered.color(
+                    world,
+                    background,
+                    depth - 1,
+                    sampler,
+                    None,
+                ));
+                // colours stay RGB down the path, they are only taken to the
+                // path's wavelength where it gets dispersed
+                if !self.dispersed {
+                    incoming = srec.scattered.spectral(incoming);
+                }
+                return emitted + incoming;
+            }
+            return emitted;
         }
-        let unit_direction = self.dir.normalize();
-        let t = 0.5 * (unit_direction.y + 1.);
-        return (1. - t) * vec3(1.0, 1.0, 1.0) + t * vec3(0.5, 0.7, 1.0);
+        return background.value(self.dir);
+    }
+    pub fn origin(&self) -> Point3<f64> {
+        self.orig
     }
     pub fn direction(&self) -> Vector3<f64> {
         self.dir
     }
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+    pub fn is_dispersed(&self) -> bool {
+        self.dispersed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::vec3;
+
+    use super::*;
+    use crate::{
+        material::{DiffuseLight, Lambertian},
+        ray::hittable::{HittableList, Sphere},
+        sampler::IndependentSampler,
+    };
+
+    #[test]
+    fn test_color_accumulates_emission() {
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0., 0., -2.),
+            1.,
+            DiffuseLight::new(vec3(4., 2., 1.)),
+        )));
+        let background = Background::Constant(vec3(0.25, 0.5, 0.75));
+        let mut sampler = IndependentSampler::new(0);
+
+        let r = Ray::new(Point3::new(0., 0., 0.), vec3(0., 0., -1.));
+        assert_eq!(
+            r.color(&world, &background, 10, &mut sampler, None),
+            vec3(4., 2., 1.)
+        );
+        let r = Ray::new(Point3::new(0., 0., 0.), vec3(0., 0., 1.));
+        assert_eq!(
+            r.color(&world, &background, 10, &mut sampler, None),
+            vec3(0.25, 0.5, 0.75)
+        );
+
+        // a diffuse sphere with no lights and no background stays black
+        let world = HittableList::new(Box::new(Sphere::new(
+            Point3::new(0., 0., -2.),
+            1.,
+            Lambertian::new(vec3(1., 1., 1.)),
+        )));
+        let r = Ray::new(Point3::new(0., 0., 0.), vec3(0., 0., -1.));
+        assert_eq!(
+            r.color(&world, &Background::None, 10, &mut sampler, None),
+            vec3(0., 0., 0.)
+        );
+    }
 }
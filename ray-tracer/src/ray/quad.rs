@@ -0,0 +1,228 @@
+use cgmath::{vec3, InnerSpace, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{material::Material, sampler::Sampler};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable, HittableList},
+    Ray,
+};
+
+const BOX_PADDING: f64 = 1e-4;
+
+// A parallelogram with one corner at q and edges u and v.
+pub struct Quad {
+    q: Point3<f64>,
+    u: Vector3<f64>,
+    v: Vector3<f64>,
+    normal: Vector3<f64>,
+    d: f64,
+    w: Vector3<f64>,
+    area: f64,
+    mat_ptr: Box<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(
+        q: Point3<f64>,
+        u: Vector3<f64>,
+        v: Vector3<f64>,
+        mat_ptr: Box<dyn Material>,
+    ) -> Self {
+        let n = u.cross(v);
+        let normal = n.normalize();
+        return Self {
+            q,
+            u,
+            v,
+            normal,
+            d: normal.dot(q - Point3::new(0., 0., 0.)),
+            w: n / n.magnitude2(),
+            area: n.magnitude(),
+            mat_ptr,
+        };
+    }
+
+    // The rectangle facing +`axis` at `offset` along it, spanning `min` to
+    // `max` in the two axes after it, cyclically (y and z for x, z and x for
+    // y, x and y for z).
+    pub fn axis_aligned(
+        axis: Axis,
+        min: [f64; 2],
+        max: [f64; 2],
+        offset: f64,
+        mat_ptr: Box<dyn Material>,
+    ) -> Self {
+        let a = axis as usize;
+        let (i, j) = ((a + 1) % 3, (a + 2) % 3);
+        let mut q = Point3::new(0., 0., 0.);
+        q[a] = offset;
+        q[i] = min[0];
+        q[j] = min[1];
+        let mut u = vec3(0., 0., 0.);
+        u[i] = max[0] - min[0];
+        let mut v = vec3(0., 0., 0.);
+        v[j] = max[1] - min[1];
+        return Self::new(q, u, v, mat_ptr);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Axis {
+    X = 0,
+    Y = 1,
+    Z = 2,
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denom = self.normal.dot(r.dir);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - self.normal.dot(r.orig - Point3::new(0., 0., 0.))) / denom;
+        if t <= t_min || t_max <= t {
+            return None;
+        }
+
+        let p = r.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0. ..=1.).contains(&alpha) || !(0. ..=1.).contains(&beta) {
+            return None;
+        }
+        return Some(
+            HitRecord::new(t, p, self.normal, r, self.mat_ptr.as_ref()).with_uv(alpha, beta),
+        );
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = Aabb::from_points(self.q, self.q + self.u + self.v)
+            .extend(self.q + self.u)
+            .extend(self.q + self.v);
+        let pad = Vector3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        return Some(Aabb::new(bbox.minimum - pad, bbox.maximum + pad));
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        let Some(rec) = self.hit(&Ray::new(origin, dir), 0.001, f64::INFINITY) else {
+            return 0.;
+        };
+        let distance_squared = rec.t * rec.t * dir.magnitude2();
+        let cosine = (dir.dot(self.normal) / dir.magnitude()).abs();
+        if cosine == 0. {
+            return 0.;
+        }
+        return distance_squared / (cosine * self.area);
+    }
+
+    fn random_direction(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let [a, b] = sampler.get_2d();
+        let p = self.q + a * self.u + b * self.v;
+        return p - origin;
+    }
+}
+
+// The axis aligned box between corners `a` and `b`, made of six quads facing
+// out. Each face needs its own material.
+pub struct Cuboid {
+    sides: HittableList,
+}
+
+impl Cuboid {
+    pub fn new(a: Point3<f64>, b: Point3<f64>, materials: [Box<dyn Material>; 6]) -> Self {
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+        let dx = vec3(max.x - min.x, 0., 0.);
+        let dy = vec3(0., max.y - min.y, 0.);
+        let dz = vec3(0., 0., max.z - min.z);
+
+        let [front, right, back, left, top, bottom] = materials;
+        let mut sides = HittableList::default();
+        sides.add(Box::new(Quad::new(
+            Point3::new(min.x, min.y, max.z),
+            dx,
+            dy,
+            front,
+        )));
+        sides.add(Box::new(Quad::new(
+            Point3::new(max.x, min.y, max.z),
+            -dz,
+            dy,
+            right,
+        )));
+        sides.add(Box::new(Quad::new(
+            Point3::new(max.x, min.y, min.z),
+            -dx,
+            dy,
+            back,
+        )));
+        sides.add(Box::new(Quad::new(min, dz, dy, left)));
+        sides.add(Box::new(Quad::new(
+            Point3::new(min.x, max.y, max.z),
+            dx,
+            -dz,
+            top,
+        )));
+        sides.add(Box::new(Quad::new(min, dx, dz, bottom)));
+        return Self { sides };
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.sides.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.sides.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        self.sides.pdf_value(origin, dir)
+    }
+
+    fn random_direction(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        self.sides.random_direction(origin, sampler)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_cuboid_faces_point_out() {
+        let gray = || Lambertian::new(vec3(0.5, 0.5, 0.5));
+        let cuboid = Cuboid::new(
+            Point3::new(1., 2., 3.),
+            Point3::new(-1., 0., 0.),
+            [gray(), gray(), gray(), gray(), gray(), gray()],
+        );
+        let center = Point3::new(0., 1., 1.5);
+        for dir in [
+            vec3(1., 0., 0.),
+            vec3(0., 1., 0.),
+            vec3(0., 0., 1.),
+            vec3(-1., 0., 0.),
+            vec3(0., -1., 0.),
+            vec3(0., 0., -1.),
+        ] {
+            let r = Ray::new(center + 10. * dir, -dir);
+            let rec = cuboid.hit(&r, 0.001, f64::INFINITY).unwrap();
+            assert!(rec.front_face, "{:?}", dir);
+            assert_eq!(rec.normal, dir);
+        }
+
+        let quad = Quad::axis_aligned(Axis::Y, [0., 1.], [2., 4.], 5., gray());
+        let r = Ray::new(Point3::new(1.5, 10., 0.5), vec3(0., -1., 0.));
+        let rec = quad.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.front_face);
+        assert_eq!(rec.p, Point3::new(1.5, 5., 0.5));
+        assert_eq!((rec.u, rec.v), (0.25, 0.5 / 3.));
+    }
+}
@@ -0,0 +1,674 @@
+use cgmath::{vec3, EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::material::Material;
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable, Sphere},
+    Ray,
+};
+
+// closer than this to the surface counts as on it, when no crossing is
+// found right behind it
+const SURFACE_EPSILON: f64 = 1e-4;
+// the smallest step, so marching always gets somewhere
+const MIN_STEP: f64 = 1e-5;
+const MAX_STEPS: usize = 1000;
+// offset for the differences the normal is taken from
+const GRADIENT_STEP: f64 = 1e-6;
+
+// A signed distance field: negative inside, positive outside, and never
+// more than `lipschitz()` times the true distance to the surface, so that
+// stepping by distance / lipschitz can't pass through it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SdfNode {
+    Sphere {
+        radius: f64,
+    },
+    // centred on the origin, with its edges rounded off by `rounding`
+    Box {
+        half_size: Vector3<f64>,
+        rounding: f64,
+    },
+    // around the y axis
+    Torus {
+        major: f64,
+        minor: f64,
+    },
+    // the points within `radius` of the segment from a to b
+    Capsule {
+        a: Point3<f64>,
+        b: Point3<f64>,
+        radius: f64,
+    },
+    // the power `power` Mandelbulb fractal, through its distance estimate
+    Mandelbulb {
+        power: f64,
+        iterations: u32,
+    },
+    Translate {
+        offset: Vector3<f64>,
+        node: Box<SdfNode>,
+    },
+    Scale {
+        factor: f64,
+        node: Box<SdfNode>,
+    },
+    // with `smoothness` > 0 the nodes blend into each other over about that
+    // distance, like metaballs
+    Union {
+        nodes: Vec<SdfNode>,
+        smoothness: f64,
+    },
+    Intersection {
+        nodes: Vec<SdfNode>,
+        smoothness: f64,
+    },
+    // `base` with `cut` taken out of it
+    Difference {
+        base: Box<SdfNode>,
+        cut: Box<SdfNode>,
+        smoothness: f64,
+    },
+    // 2 count + 1 copies along each axis, `spacing` apart (0 for no copies).
+    // The node has to fit in its cell of the grid.
+    Repeat {
+        spacing: Vector3<f64>,
+        count: [u32; 3],
+        node: Box<SdfNode>,
+    },
+    // turned around the y axis by `rate` radians per unit up it. Built with
+    // twist(), which works out how far from the axis the node reaches.
+    Twist {
+        rate: f64,
+        radius: f64,
+        node: Box<SdfNode>,
+    },
+}
+
+// Quilez's polynomial smooth minimum, which is never above the minimum and
+// at most k / 4 below it.
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0. {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.) / k;
+    return a.min(b) - h * h * k * 0.25;
+}
+
+fn smooth_max(a: f64, b: f64, k: f64) -> f64 {
+    return -smooth_min(-a, -b, k);
+}
+
+fn pad(bbox: &Aabb, amount: f64) -> Aabb {
+    let pad = vec3(amount, amount, amount);
+    return Aabb::new(bbox.minimum - pad, bbox.maximum + pad);
+}
+
+fn overlap(boxes: impl Iterator<Item = Aabb>) -> Option<Aabb> {
+    return boxes.reduce(|a, b| {
+        let min = Point3::new(
+            a.minimum.x.max(b.minimum.x),
+            a.minimum.y.max(b.minimum.y),
+            a.minimum.z.max(b.minimum.z),
+        );
+        let max = Point3::new(
+            a.maximum.x.min(b.maximum.x),
+            a.maximum.y.min(b.maximum.y),
+            a.maximum.z.min(b.maximum.z),
+        );
+        // an empty overlap still needs some box
+        Aabb::from_points(min, max)
+    });
+}
+
+// the radius of the cylinder around the y axis holding the box
+fn axis_radius(bbox: &Aabb) -> f64 {
+    let x = bbox.minimum.x.abs().max(bbox.maximum.x.abs());
+    let z = bbox.minimum.z.abs().max(bbox.maximum.z.abs());
+    return (x * x + z * z).sqrt();
+}
+
+// Twisting shears a point at distance r from the axis by slope = rate * r
+// sideways per unit of height, stretching the field by at most the largest
+// singular value of that shear.
+fn twist_stretch(slope: f64) -> f64 {
+    return (slope + (slope * slope + 4.).sqrt()) / 2.;
+}
+
+impl SdfNode {
+    // Twist's lipschitz() holds out to the radius its node's surface reaches,
+    // which is too costly to find again for every distance.
+    pub fn twist(rate: f64, node: SdfNode) -> SdfNode {
+        return SdfNode::Twist {
+            rate,
+            radius: axis_radius(&node.bounding_box()),
+            node: Box::new(node),
+        };
+    }
+
+    pub fn distance(&self, p: Point3<f64>) -> f64 {
+        match self {
+            SdfNode::Sphere { radius } => p.to_vec().magnitude() - radius,
+            SdfNode::Box {
+                half_size,
+                rounding,
+            } => {
+                let q = p.to_vec().map(f64::abs) - *half_size + vec3(1., 1., 1.) * *rounding;
+                let outside = q.map(|c| c.max(0.)).magnitude();
+                let inside = q.x.max(q.y).max(q.z).min(0.);
+                outside + inside - rounding
+            }
+            SdfNode::Torus { major, minor } => {
+                let ring = (p.x * p.x + p.z * p.z).sqrt() - major;
+                (ring * ring + p.y * p.y).sqrt() - minor
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let (pa, ba) = (p - a, b - a);
+                let h = if ba.magnitude2() > 0. {
+                    (pa.dot(ba) / ba.magnitude2()).clamp(0., 1.)
+                } else {
+                    0.
+                };
+                (pa - ba * h).magnitude() - radius
+            }
+            SdfNode::Mandelbulb { power, iterations } => mandelbulb(p, *power, *iterations),
+            SdfNode::Translate { offset, node } => node.distance(p - offset),
+            // a negative factor mirrors the node through the origin
+            SdfNode::Scale { factor, node } => factor.abs() * node.distance(p / *factor),
+            SdfNode::Union { nodes, smoothness } => nodes
+                .iter()
+                .map(|node| node.distance(p))
+                .reduce(|a, b| smooth_min(a, b, *smoothness))
+                .unwrap_or(f64::INFINITY),
+            SdfNode::Intersection { nodes, smoothness } => nodes
+                .iter()
+                .map(|node| node.distance(p))
+                .reduce(|a, b| smooth_max(a, b, *smoothness))
+                .unwrap_or(f64::INFINITY),
+            SdfNode::Difference {
+                base,
+                cut,
+                smoothness,
+            } => smooth_max(base.distance(p), -cut.distance(p), *smoothness),
+            SdfNode::Repeat {
+                spacing,
+                count,
+                node,
+            } => {
+                let mut q = p;
+                for i in 0..3 {
+                    if spacing[i] > 0. {
+                        let n = count[i] as f64;
+                        q[i] -= spacing[i] * (p[i] / spacing[i]).round().clamp(-n, n);
+                    }
+                }
+                node.distance(q)
+            }
+            SdfNode::Twist { rate, radius, node } => {
+                let (sin, cos) = (-rate * p.y).sin_cos();
+                let q = Point3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z);
+                let d = node.distance(q);
+                // further out than lipschitz() allows for, the field can
+                // change faster, so scale it down to keep under the bound
+                let r = (p.x * p.x + p.z * p.z).sqrt();
+                if r <= *radius {
+                    return d;
+                }
+                d * twist_stretch(rate * radius) / twist_stretch(rate * r)
+            }
+        }
+    }
+
+    // how much faster than the true distance `distance` can change
+    pub fn lipschitz(&self) -> f64 {
+        match self {
+            SdfNode::Sphere { .. }
+            | SdfNode::Box { .. }
+            | SdfNode::Torus { .. }
+            | SdfNode::Capsule { .. }
+            | SdfNode::Mandelbulb { .. } => 1.,
+            SdfNode::Translate { node, .. }
+            | SdfNode::Scale { node, .. }
+            | SdfNode::Repeat { node, .. } => node.lipschitz(),
+            SdfNode::Union { nodes, .. } | SdfNode::Intersection { nodes, .. } => {
+                nodes.iter().map(|node| node.lipschitz()).fold(1., f64::max)
+            }
+            SdfNode::Difference { base, cut, .. } => base.lipschitz().max(cut.lipschitz()),
+            SdfNode::Twist { rate, radius, node } => {
+                // points move by rate times their distance from the axis per
+                // unit of height
+                node.lipschitz() * twist_stretch(rate * radius)
+            }
+        }
+    }
+
+    // a box the surface is certainly inside
+    pub fn bounding_box(&self) -> Aabb {
+        match self {
+            SdfNode::Sphere { radius } => {
+                let r = vec3(*radius, *radius, *radius);
+                Aabb::new(Point3::origin() - r, Point3::origin() + r)
+            }
+            SdfNode::Box { half_size, .. } => {
+                Aabb::new(Point3::origin() - half_size, Point3::origin() + half_size)
+            }
+            SdfNode::Torus { major, minor } => {
+                let r = vec3(major + minor, *minor, major + minor);
+                Aabb::new(Point3::origin() - r, Point3::origin() + r)
+            }
+            SdfNode::Capsule { a, b, radius } => pad(&Aabb::from_points(*a, *b), *radius),
+            // every point further out than 2 escapes
+            SdfNode::Mandelbulb { .. } => {
+                Aabb::new(Point3::new(-2., -2., -2.), Point3::new(2., 2., 2.))
+            }
+            SdfNode::Translate { offset, node } => {
+                let bbox = node.bounding_box();
+                Aabb::new(bbox.minimum + offset, bbox.maximum + offset)
+            }
+            SdfNode::Scale { factor, node } => {
+                let bbox = node.bounding_box();
+                Aabb::from_points(
+                    Point3::from_vec(bbox.minimum.to_vec() * *factor),
+                    Point3::from_vec(bbox.maximum.to_vec() * *factor),
+                )
+            }
+            // the smooth minimum reaches out k / 4 further than the nodes
+            SdfNode::Union { nodes, smoothness } => pad(
+                &nodes
+                    .iter()
+                    .map(|node| node.bounding_box())
+                    .reduce(|a, b| Aabb::surrounding_box(&a, &b))
+                    .unwrap_or(Aabb::new(Point3::origin(), Point3::origin())),
+                smoothness.max(0.) / 4.,
+            ),
+            SdfNode::Intersection { nodes, .. } => {
+                overlap(nodes.iter().map(|node| node.bounding_box()))
+                    .unwrap_or(Aabb::new(Point3::origin(), Point3::origin()))
+            }
+            SdfNode::Difference { base, .. } => base.bounding_box(),
+            SdfNode::Repeat {
+                spacing,
+                count,
+                node,
+            } => {
+                let bbox = node.bounding_box();
+                let reach = vec3(
+                    spacing.x.max(0.) * count[0] as f64,
+                    spacing.y.max(0.) * count[1] as f64,
+                    spacing.z.max(0.) * count[2] as f64,
+                );
+                Aabb::new(bbox.minimum - reach, bbox.maximum + reach)
+            }
+            SdfNode::Twist { radius, node, .. } => {
+                // any turn of the box around y
+                let bbox = node.bounding_box();
+                Aabb::new(
+                    Point3::new(-radius, bbox.minimum.y, -radius),
+                    Point3::new(*radius, bbox.maximum.y, *radius),
+                )
+            }
+        }
+    }
+}
+
+// The Mandelbulb's distance estimate, from the running derivative of its
+// iteration z -> z^power + p in spherical coordinates.
+fn mandelbulb(p: Point3<f64>, power: f64, iterations: u32) -> f64 {
+    let c = p.to_vec();
+    let mut z = c;
+    let mut dr = 1.;
+    let mut r = z.magnitude();
+    for _ in 0..iterations {
+        if r > 2. {
+            break;
+        }
+        let theta = (z.z / r).clamp(-1., 1.).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.) * power * dr + 1.;
+        let zr = r.powf(power);
+        z =
+            zr * vec3(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ) + c;
+        r = z.magnitude();
+    }
+    if r == 0. {
+        return 0.;
+    }
+    return 0.5 * r.ln() * r / dr;
+}
+
+// The surface where an SdfNode is zero, found by sphere tracing: stepping
+// along the ray by the distance to the nearest surface, which can't
+// overshoot it.
+pub struct SdfObject {
+    node: SdfNode,
+    lipschitz: f64,
+    bbox: Aabb,
+    mat_ptr: Box<dyn Material>,
+}
+
+impl SdfObject {
+    pub fn new(node: SdfNode, mat_ptr: Box<dyn Material>) -> Self {
+        return Self {
+            lipschitz: node.lipschitz(),
+            bbox: pad(&node.bounding_box(), 1e-4),
+            node,
+            mat_ptr,
+        };
+    }
+
+    // the range of t where the ray is inside the bounding box
+    fn clip(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let (mut t0, mut t1) = (t_min, t_max);
+        for a in 0..3 {
+            let inv = 1. / r.direction()[a];
+            let mut near = (self.bbox.minimum[a] - r.origin()[a]) * inv;
+            let mut far = (self.bbox.maximum[a] - r.origin()[a]) * inv;
+            if inv < 0. {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaN from 0 * inf leaves the bounds alone
+            if near > t0 {
+                t0 = near;
+            }
+            if far < t1 {
+                t1 = far;
+            }
+            if t1 < t0 {
+                return None;
+            }
+        }
+        return Some((t0, t1));
+    }
+
+    // The first t between t0 and t1 where the ray meets the surface. Tracks
+    // the side of the surface the ray is on, so that a crossing between two
+    // steps is still caught.
+    fn march(&self, r: &Ray, t0: f64, t1: f64) -> Option<f64> {
+        let length = r.direction().magnitude();
+        let at = |t: f64| self.node.distance(r.at(t));
+        // the crossing between a and b, where the field's sign is sign_a
+        let bisect = |mut a: f64, mut b: f64, sign_a: f64| {
+            for _ in 0..50 {
+                let mid = 0.5 * (a + b);
+                if at(mid).signum() == sign_a {
+                    a = mid;
+                } else {
+                    b = mid;
+                }
+            }
+            return 0.5 * (a + b);
+        };
+
+        let mut t = t0;
+        let mut d = at(t);
+        for _ in 0..MAX_STEPS {
+            let next = t + (d.abs() / self.lipschitz).max(MIN_STEP) / length;
+            if next > t1 {
+                return None;
+            }
+            let next_d = at(next);
+            if next_d.signum() != d.signum() {
+                return Some(bisect(t, next, d.signum()));
+            }
+            // Closing in on the surface without crossing it, as exact fields
+            // do. Look for the crossing just ahead, and if there is none take
+            // this as a hit, which also keeps rays from crawling along
+            // fractal detail they only just miss.
+            if next_d.abs() < SURFACE_EPSILON && next_d.abs() < d.abs() {
+                let probe = next + 2. * SURFACE_EPSILON / length;
+                if probe <= t1 && at(probe).signum() != next_d.signum() {
+                    return Some(bisect(next, probe, next_d.signum()));
+                }
+                return Some(next);
+            }
+            t = next;
+            d = next_d;
+        }
+        return None;
+    }
+
+    // the direction the distance grows fastest, by tetrahedral differences
+    fn gradient(&self, p: Point3<f64>) -> Vector3<f64> {
+        let h = GRADIENT_STEP;
+        let mut gradient = vec3(0., 0., 0.);
+        for k in [
+            vec3(1., -1., -1.),
+            vec3(-1., -1., 1.),
+            vec3(-1., 1., -1.),
+            vec3(1., 1., 1.),
+        ] {
+            gradient += k * self.node.distance(p + h * k);
+        }
+        return gradient;
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t0, t1) = self.clip(r, t_min, t_max)?;
+        let t = self.march(r, t0, t1)?;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = r.at(t);
+        let gradient = self.gradient(p);
+        if gradient.magnitude2() == 0. {
+            return None;
+        }
+        let outward_normal = gradient.normalize();
+        let (u, v) = Sphere::uv(outward_normal);
+        return Some(HitRecord::new(t, p, outward_normal, r, self.mat_ptr.as_ref()).with_uv(u, v));
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{material::Lambertian, rng::Rng, vector_additon::VectorAdditions};
+
+    fn object(node: SdfNode) -> SdfObject {
+        SdfObject::new(node, Lambertian::new(vec3(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn test_sphere_matches_analytic() {
+        let center = Point3::new(1., 0.5, -2.);
+        let sdf = object(SdfNode::Translate {
+            offset: center.to_vec(),
+            node: Box::new(SdfNode::Sphere { radius: 0.8 }),
+        });
+        let sphere = Sphere::new(center, 0.8, Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let mut rng = Rng::new(6);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let from = Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]);
+            let to = Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]);
+            // from outside and from inside
+            let origin = center + rng.range(0.2..4.) * from;
+            let r = Ray::new(origin, center + 0.9 * to - origin);
+            // rays passing within SURFACE_EPSILON may or may not hit
+            let dir = r.direction().normalize();
+            let closest = ((center - origin) - (center - origin).dot(dir) * dir).magnitude();
+            if (closest - 0.8).abs() < 1e-3 {
+                continue;
+            }
+            let expected = sphere.hit(&r, 0.001, f64::INFINITY);
+            let found = sdf.hit(&r, 0.001, f64::INFINITY);
+            assert_eq!(expected.is_some(), found.is_some(), "{:?}", r);
+            if let (Some(expected), Some(found)) = (expected, found) {
+                hits += 1;
+                assert!(((found.p - center).magnitude() - 0.8).abs() < SURFACE_EPSILON);
+                // off by SURFACE_EPSILON over the cosine at most
+                assert!((expected.t - found.t).abs() < 0.01);
+                let outward = (found.p - center).normalize();
+                let normal = if found.front_face { outward } else { -outward };
+                assert!((found.normal - normal).magnitude() < 1e-5);
+                assert_eq!(expected.front_face, found.front_face);
+            }
+        }
+        assert!(hits > 1000);
+    }
+
+    #[test]
+    fn test_twist_slope_stays_below_lipschitz() {
+        // a gentle twist too, where the shear is barely more than the shape's own slope
+        for rate in [0.1, 1.5] {
+            let node = SdfNode::twist(
+                rate,
+                SdfNode::Box {
+                    half_size: vec3(1., 0.5, 1.),
+                    rounding: 0.45,
+                },
+            );
+            let mut rng = Rng::new(1);
+            let h = 1e-5;
+            for _ in 0..100_000 {
+                let p = Point3::new(
+                    rng.range(-8. ..8.),
+                    rng.range(-3. ..3.),
+                    rng.range(-8. ..8.),
+                );
+                let step = Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]) * h;
+                let slope = (node.distance(p + step) - node.distance(p)).abs() / h;
+                assert!(
+                    slope <= node.lipschitz(),
+                    "rate {}: {} > {}",
+                    rate,
+                    slope,
+                    node.lipschitz()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_distances_stay_below_the_bound() {
+        // marching from anywhere finds the first crossing of a twisted shape
+        let shape = SdfNode::Union {
+            nodes: vec![
+                SdfNode::Box {
+                    half_size: vec3(0.5, 1., 0.3),
+                    rounding: 0.1,
+                },
+                SdfNode::Repeat {
+                    spacing: vec3(0., 0.6, 0.),
+                    count: [0, 1, 0],
+                    node: Box::new(SdfNode::Torus {
+                        major: 0.6,
+                        minor: 0.1,
+                    }),
+                },
+            ],
+            smoothness: 0.2,
+        };
+        let node = SdfNode::twist(1.5, shape.clone());
+        // the field without Twist's bound, straight from the shape
+        let twisted = |p: Point3<f64>| {
+            let (sin, cos) = (-1.5 * p.y).sin_cos();
+            shape.distance(Point3::new(
+                cos * p.x - sin * p.z,
+                p.y,
+                sin * p.x + cos * p.z,
+            ))
+        };
+        let obj = object(node.clone());
+        let mut rng = Rng::new(8);
+        let mut hits = 0;
+        for _ in 0..500 {
+            let p = Point3::new(
+                rng.range(-3. ..3.),
+                rng.range(-3. ..3.),
+                rng.range(-3. ..3.),
+            );
+            let d = node.distance(p);
+            if d < 0. {
+                continue;
+            }
+            let target = vec3(
+                rng.range(-0.6..0.6),
+                rng.range(-1. ..1.),
+                rng.range(-0.6..0.6),
+            );
+            let dir = (Point3::from_vec(target) - p).normalize();
+            // the first crossing, by small steps
+            let crossing = (1..10000)
+                .map(|i| i as f64 * 1e-3)
+                .find(|&t| twisted(p + t * dir) < 0.);
+            let Some(rec) = obj.hit(&Ray::new(p, dir), 0., f64::INFINITY) else {
+                assert!(crossing.is_none(), "missed the surface at {:?}", crossing);
+                continue;
+            };
+            hits += 1;
+            let crossing = crossing.unwrap();
+            // never through the surface, and only short of it at grazing
+            // angles, when within SURFACE_EPSILON
+            assert!(rec.t <= crossing + 1e-9, "{} vs {}", rec.t, crossing);
+            assert!(twisted(rec.p).abs() < obj.lipschitz * SURFACE_EPSILON);
+            assert!(d / obj.lipschitz <= rec.t + 1e-6, "{} > {}", d, rec.t);
+            for i in 0..3 {
+                assert!(obj.bbox.minimum[i] <= rec.p[i] && rec.p[i] <= obj.bbox.maximum[i]);
+            }
+        }
+        assert!(hits > 200, "{}", hits);
+    }
+
+    #[test]
+    fn test_smooth_union_and_mandelbulb() {
+        // two touching spheres blend, filling the gap between them
+        let blob = object(SdfNode::Union {
+            nodes: vec![
+                SdfNode::Translate {
+                    offset: vec3(-1., 0., 0.),
+                    node: Box::new(SdfNode::Sphere { radius: 0.9 }),
+                },
+                SdfNode::Translate {
+                    offset: vec3(1., 0., 0.),
+                    node: Box::new(SdfNode::Sphere { radius: 0.9 }),
+                },
+            ],
+            smoothness: 1.,
+        });
+        let r = Ray::new(Point3::new(0., 5., 0.), vec3(0., -1., 0.));
+        let rec = blob.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.p.y > 0.);
+        assert!((rec.normal - vec3(0., 1., 0.)).magnitude() < 1e-4);
+
+        let bulb = object(SdfNode::Mandelbulb {
+            power: 8.,
+            iterations: 12,
+        });
+        // the origin is inside, so a ray through it hits and one far off misses
+        let r = Ray::new(Point3::new(0., 0., 3.), vec3(0., 0., -1.));
+        let rec = bulb.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.t > 1. && rec.t < 3., "{}", rec.t);
+        let r = Ray::new(Point3::new(1.8, 1.8, 3.), vec3(0., 0., -1.));
+        assert!(bulb.hit(&r, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_mirrored_scale_keeps_its_inside() {
+        let node = SdfNode::Scale {
+            factor: -2.,
+            node: Box::new(SdfNode::Translate {
+                offset: vec3(1., 0., 0.),
+                node: Box::new(SdfNode::Sphere { radius: 0.5 }),
+            }),
+        };
+        // the sphere ends up around x = -2 with radius 1
+        assert_eq!(node.distance(Point3::new(-2., 0., 0.)), -1.);
+        assert_eq!(node.distance(Point3::new(0., 0., 0.)), 1.);
+        assert_eq!(node.distance(Point3::new(2., 0., 0.)), 3.);
+        let bbox = node.bounding_box();
+        assert_eq!((bbox.minimum.x, bbox.maximum.x), (-3., -1.));
+    }
+}
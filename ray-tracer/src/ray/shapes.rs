@@ -0,0 +1,619 @@
+use std::f64::consts::PI;
+
+use cgmath::{vec3, InnerSpace, Point3, Vector3};
+
+use crate::{material::Material, onb::Onb, sampler::Sampler, vector_additon::VectorAdditions};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    Ray,
+};
+
+const BOX_PADDING: f64 = 1e-4;
+
+// A shape's own coordinates: origin at `origin`, z along the frame's w.
+// Rays keep their t since the basis is orthonormal.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    origin: Point3<f64>,
+    onb: Onb,
+}
+
+impl Frame {
+    fn new(origin: Point3<f64>, z: Vector3<f64>) -> Self {
+        Self {
+            origin,
+            onb: Onb::from_w(z),
+        }
+    }
+
+    fn ray(&self, r: &Ray) -> (Vector3<f64>, Vector3<f64>) {
+        return (
+            self.onb.to_local(r.orig - self.origin),
+            self.onb.to_local(r.direction()),
+        );
+    }
+
+    fn world(&self, v: Vector3<f64>) -> Vector3<f64> {
+        return self.onb.local(v);
+    }
+}
+
+// the angle around the frame's z as a u coordinate in [0, 1]
+fn angle_u(p: Vector3<f64>) -> f64 {
+    return (p.y.atan2(p.x) + PI) / (2. * PI);
+}
+
+// The box around a disk of `radius` at `center` facing `normal`, grown by
+// `pad` on every side.
+fn disk_box(center: Point3<f64>, normal: Vector3<f64>, radius: f64, pad: f64) -> Aabb {
+    let n = normal.normalize();
+    let extent = vec3(n.x, n.y, n.z).map(|a| radius * (1. - a * a).max(0.).sqrt() + pad);
+    return Aabb::new(center - extent, center + extent);
+}
+
+// the roots of a t^2 + b t + c in increasing order
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a == 0. {
+        if b == 0. {
+            return None;
+        }
+        return Some((-c / b, -c / b));
+    }
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    // avoids cancelling the larger root against b
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0. {
+        return Some((0., 0.));
+    }
+    let (t0, t1) = (q / a, c / q);
+    return Some((t0.min(t1), t0.max(t1)));
+}
+
+// The plane through `point` facing `normal`, without end. It has no bounding
+// box, so scenes keep it out of the BVH. u and v are distances along the
+// plane.
+pub struct Plane {
+    frame: Frame,
+    mat_ptr: Box<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Point3<f64>, normal: Vector3<f64>, mat_ptr: Box<dyn Material>) -> Self {
+        Self {
+            frame: Frame::new(point, normal),
+            mat_ptr,
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.ray(r);
+        if d.z == 0. {
+            return None;
+        }
+        let t = -o.z / d.z;
+        if t <= t_min || t_max <= t {
+            return None;
+        }
+        let p = o + t * d;
+        let normal = self.frame.onb.w;
+        return Some(
+            HitRecord::new(t, r.at(t), normal, r, self.mat_ptr.as_ref()).with_uv(p.x, p.y),
+        );
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+// A flat disk, with u going around it and v out from the centre.
+pub struct Disk {
+    frame: Frame,
+    radius: f64,
+    mat_ptr: Box<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(
+        center: Point3<f64>,
+        normal: Vector3<f64>,
+        radius: f64,
+        mat_ptr: Box<dyn Material>,
+    ) -> Self {
+        Self {
+            frame: Frame::new(center, normal),
+            radius,
+            mat_ptr,
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.ray(r);
+        if d.z == 0. {
+            return None;
+        }
+        let t = -o.z / d.z;
+        if t <= t_min || t_max <= t {
+            return None;
+        }
+        let p = o + t * d;
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        if rho > self.radius {
+            return None;
+        }
+        let normal = self.frame.onb.w;
+        return Some(
+            HitRecord::new(t, r.at(t), normal, r, self.mat_ptr.as_ref())
+                .with_uv(angle_u(p), rho / self.radius),
+        );
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return Some(disk_box(
+            self.frame.origin,
+            self.frame.onb.w,
+            self.radius,
+            BOX_PADDING,
+        ));
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        let Some(rec) = self.hit(&Ray::new(origin, dir), 0.001, f64::INFINITY) else {
+            return 0.;
+        };
+        let distance_squared = rec.t * rec.t * dir.magnitude2();
+        let cosine = (dir.dot(self.frame.onb.w) / dir.magnitude()).abs();
+        if cosine == 0. {
+            return 0.;
+        }
+        return distance_squared / (cosine * PI * self.radius * self.radius);
+    }
+
+    // uniform over the disk's area
+    fn random_direction(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let p = self.radius * Vector3::sample_unit_disk(sampler.get_2d());
+        return self.frame.origin + self.frame.world(vec3(p.x, p.y, 0.)) - origin;
+    }
+}
+
+// A cylinder from the centre of its `base` to the centre of its `top`, open
+// or closed by disks. On the side u goes around the axis and v from base to
+// top, on the caps they are as on a Disk.
+pub struct Cylinder {
+    frame: Frame,
+    height: f64,
+    radius: f64,
+    capped: bool,
+    mat_ptr: Box<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(
+        base: Point3<f64>,
+        top: Point3<f64>,
+        radius: f64,
+        capped: bool,
+        mat_ptr: Box<dyn Material>,
+    ) -> Self {
+        Self {
+            frame: Frame::new(base, top - base),
+            height: (top - base).magnitude(),
+            radius,
+            capped,
+            mat_ptr,
+        }
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.ray(r);
+        let (h, radius) = (self.height, self.radius);
+        // t, the local outward normal and uv
+        let mut closest: Option<(f64, Vector3<f64>, f64, f64)> = None;
+        let mut t_max = t_max;
+
+        let a = d.x * d.x + d.y * d.y;
+        let b = 2. * (o.x * d.x + o.y * d.y);
+        let c = o.x * o.x + o.y * o.y - radius * radius;
+        if a > 0. {
+            if let Some((t0, t1)) = solve_quadratic(a, b, c) {
+                for t in [t0, t1] {
+                    let p = o + t * d;
+                    if t_min < t && t < t_max && (0. ..=h).contains(&p.z) {
+                        let normal = vec3(p.x, p.y, 0.) / radius;
+                        closest = Some((t, normal, angle_u(p), p.z / h));
+                        t_max = t;
+                        break;
+                    }
+                }
+            }
+        }
+        if self.capped && d.z != 0. {
+            for (z, normal) in [(0., vec3(0., 0., -1.)), (h, vec3(0., 0., 1.))] {
+                let t = (z - o.z) / d.z;
+                let p = o + t * d;
+                let rho2 = p.x * p.x + p.y * p.y;
+                if t_min < t && t < t_max && rho2 <= radius * radius {
+                    closest = Some((t, normal, angle_u(p), rho2.sqrt() / radius));
+                    t_max = t;
+                }
+            }
+        }
+
+        let (t, normal, u, v) = closest?;
+        let normal = self.frame.world(normal);
+        return Some(HitRecord::new(t, r.at(t), normal, r, self.mat_ptr.as_ref()).with_uv(u, v));
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (base, axis) = (self.frame.origin, self.frame.onb.w);
+        let bottom = disk_box(base, axis, self.radius, BOX_PADDING);
+        let top = disk_box(base + self.height * axis, axis, self.radius, BOX_PADDING);
+        return Some(Aabb::surrounding_box(&bottom, &top));
+    }
+}
+
+// A cone standing on a disk of `radius` around `base` with its tip at `apex`,
+// closed at the bottom or not. uv are as on a Cylinder.
+pub struct Cone {
+    frame: Frame,
+    height: f64,
+    radius: f64,
+    capped: bool,
+    mat_ptr: Box<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(
+        base: Point3<f64>,
+        apex: Point3<f64>,
+        radius: f64,
+        capped: bool,
+        mat_ptr: Box<dyn Material>,
+    ) -> Self {
+        Self {
+            frame: Frame::new(base, apex - base),
+            height: (apex - base).magnitude(),
+            radius,
+            capped,
+            mat_ptr,
+        }
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.ray(r);
+        let (h, radius) = (self.height, self.radius);
+        let mut closest: Option<(f64, Vector3<f64>, f64, f64)> = None;
+        let mut t_max = t_max;
+
+        // x^2 + y^2 = (k (h - z))^2 with the radius shrinking by k per unit up
+        let k2 = (radius / h).powi(2);
+        let w = h - o.z;
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let b = 2. * (o.x * d.x + o.y * d.y + k2 * w * d.z);
+        let c = o.x * o.x + o.y * o.y - k2 * w * w;
+        if let Some((t0, t1)) = solve_quadratic(a, b, c) {
+            for t in [t0, t1] {
+                let p = o + t * d;
+                if t_min < t && t < t_max && (0. ..=h).contains(&p.z) {
+                    let normal = vec3(p.x, p.y, k2 * (h - p.z));
+                    if normal.magnitude2() > 0. {
+                        closest = Some((t, normal.normalize(), angle_u(p), p.z / h));
+                        t_max = t;
+                        break;
+                    }
+                }
+            }
+        }
+        if self.capped && d.z != 0. {
+            let t = -o.z / d.z;
+            let p = o + t * d;
+            let rho2 = p.x * p.x + p.y * p.y;
+            if t_min < t && t < t_max && rho2 <= radius * radius {
+                closest = Some((t, vec3(0., 0., -1.), angle_u(p), rho2.sqrt() / radius));
+            }
+        }
+
+        let (t, normal, u, v) = closest?;
+        let normal = self.frame.world(normal);
+        return Some(HitRecord::new(t, r.at(t), normal, r, self.mat_ptr.as_ref()).with_uv(u, v));
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (base, axis) = (self.frame.origin, self.frame.onb.w);
+        let bottom = disk_box(base, axis, self.radius, BOX_PADDING);
+        return Some(bottom.extend(base + self.height * axis));
+    }
+}
+
+// The real roots of the polynomial with `coefficients`, constant term first,
+// inside [lo, hi] in increasing order. The roots of the derivative split the
+// interval into pieces where the polynomial is monotonic, each holding at
+// most one root, which bisection then finds.
+fn polynomial_roots(coefficients: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let eval = |x: f64| coefficients.iter().rev().fold(0., |acc, c| acc * x + c);
+    if coefficients.len() <= 2 {
+        if coefficients.len() < 2 || coefficients[1] == 0. {
+            return Vec::new();
+        }
+        let x = -coefficients[0] / coefficients[1];
+        return if (lo..=hi).contains(&x) {
+            vec![x]
+        } else {
+            Vec::new()
+        };
+    }
+    let derivative: Vec<f64> = coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, c)| i as f64 * c)
+        .collect();
+    let mut bounds = vec![lo];
+    bounds.extend(polynomial_roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots = Vec::new();
+    for pair in bounds.windows(2) {
+        let (mut a, mut b) = (pair[0], pair[1]);
+        let (fa, fb) = (eval(a), eval(b));
+        if fa == 0. {
+            roots.push(a);
+            continue;
+        }
+        if fa.signum() == fb.signum() {
+            continue;
+        }
+        for _ in 0..64 {
+            let mid = 0.5 * (a + b);
+            if mid <= a || mid >= b {
+                break;
+            }
+            if eval(mid).signum() == fa.signum() {
+                a = mid;
+            } else {
+                b = mid;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    if eval(hi) == 0. && roots.last() != Some(&hi) {
+        roots.push(hi);
+    }
+    return roots;
+}
+
+// A ring around `center` in the plane facing `normal`: the points `minor`
+// away from the circle of radius `major`. u goes around the ring and v
+// around the tube.
+pub struct Torus {
+    frame: Frame,
+    major: f64,
+    minor: f64,
+    mat_ptr: Box<dyn Material>,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3<f64>,
+        normal: Vector3<f64>,
+        major: f64,
+        minor: f64,
+        mat_ptr: Box<dyn Material>,
+    ) -> Self {
+        Self {
+            frame: Frame::new(center, normal),
+            major,
+            minor,
+            mat_ptr,
+        }
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.ray(r);
+        let length = d.magnitude();
+        let d = d / length;
+        let (big, small) = (self.major, self.minor);
+
+        // the quartic is badly conditioned far away, so start it just before
+        // the ray enters the bounding sphere, where a root may already be,
+        // and work in distances along it
+        let bound = big + small;
+        let (s0, s1) = solve_quadratic(1., 2. * o.dot(d), o.magnitude2() - bound * bound)?;
+        let lo = (s0 - 1e-3 * bound).max(t_min * length);
+        let hi = s1.min(t_max * length);
+        if lo >= hi {
+            return None;
+        }
+        let o = o + lo * d;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) along p = o + s d
+        let od = o.dot(d);
+        let k = o.magnitude2() + big * big - small * small;
+        let r2 = 4. * big * big;
+        let coefficients = [
+            k * k - r2 * (o.x * o.x + o.y * o.y),
+            4. * od * k - 2. * r2 * (o.x * d.x + o.y * d.y),
+            4. * od * od + 2. * k - r2 * (d.x * d.x + d.y * d.y),
+            4. * od,
+            1.,
+        ];
+        let s = polynomial_roots(&coefficients, 0., hi - lo)
+            .into_iter()
+            .find(|&s| s + lo > t_min * length)?;
+
+        let p = o + s * d;
+        let t = (s + lo) / length;
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        // away from the nearest point on the ring's core circle
+        let core = if rho > 0. {
+            vec3(p.x, p.y, 0.) * (big / rho)
+        } else {
+            vec3(big, 0., 0.)
+        };
+        let normal = (p - core).normalize();
+        let v = (p.z.atan2(rho - big) + PI) / (2. * PI);
+        let normal = self.frame.world(normal);
+        return Some(
+            HitRecord::new(t, r.at(t), normal, r, self.mat_ptr.as_ref()).with_uv(angle_u(p), v),
+        );
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return Some(disk_box(
+            self.frame.origin,
+            self.frame.onb.w,
+            self.major,
+            self.minor + BOX_PADDING,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{material::Lambertian, rng::Rng};
+
+    fn gray() -> Box<dyn Material> {
+        Lambertian::new(vec3(0.5, 0.5, 0.5))
+    }
+
+    // Fires random rays at `shape` from outside its box and checks that hits
+    // are on the surface `distance` measures, inside the box, with unit
+    // normals facing the ray and uv in [0, 1].
+    fn check_shape(shape: &dyn Hittable, distance: impl Fn(Point3<f64>) -> f64, uv_in_unit: bool) {
+        let bbox = shape.bounding_box().unwrap();
+        let center = bbox.centroid();
+        let reach = bbox.diagonal().magnitude();
+        let mut rng = Rng::new(11);
+        let mut hits = 0;
+        for _ in 0..5000 {
+            let from = Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]);
+            let to = Vector3::sample_unit_sphere([rng.uniform(), rng.uniform()]);
+            let target = center + 0.5 * reach * rng.uniform() * to;
+            let origin = center + 2. * reach * from;
+            let r = Ray::new(origin, target - origin);
+            let Some(rec) = shape.hit(&r, 0.001, f64::INFINITY) else {
+                continue;
+            };
+            hits += 1;
+            assert!(
+                distance(rec.p).abs() < 1e-6,
+                "{:?} is off the surface",
+                rec.p
+            );
+            let pad = vec3(1e-9, 1e-9, 1e-9);
+            let (lo, hi) = (bbox.minimum - pad, bbox.maximum + pad);
+            assert!(
+                (0..3).all(|i| lo[i] <= rec.p[i] && rec.p[i] <= hi[i]),
+                "{:?} is outside {:?}",
+                rec.p,
+                bbox
+            );
+            assert!((rec.normal.magnitude() - 1.).abs() < 1e-9);
+            assert!(rec.normal.dot(r.direction()) <= 0.);
+            if uv_in_unit {
+                assert!((0. ..=1.).contains(&rec.u) && (0. ..=1.).contains(&rec.v));
+            }
+        }
+        assert!(hits > 500, "only {} hits", hits);
+    }
+
+    #[test]
+    fn test_plane_and_disk() {
+        let normal = vec3(1., 2., 0.5).normalize();
+        let center = Point3::new(0.5, -1., 2.);
+        let disk = Disk::new(center, normal, 1.5, gray());
+        check_shape(&disk, |p| (p - center).dot(normal), true);
+
+        let plane = Plane::new(center, normal, gray());
+        assert!(plane.bounding_box().is_none());
+        let r = Ray::new(Point3::new(10., 10., 10.), -normal);
+        let rec = plane.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.p - center).dot(normal).abs() < 1e-9);
+        assert!(rec.front_face);
+    }
+
+    #[test]
+    fn test_cylinder_and_cone() {
+        let (base, top) = (Point3::new(1., 0., 0.), Point3::new(2., 3., -1.));
+        let axis = (top - base).normalize();
+        let height = (top - base).magnitude();
+        let along = move |p: Point3<f64>| (p - base).dot(axis);
+        let off_axis = move |p: Point3<f64>| ((p - base) - along(p) * axis).magnitude();
+
+        let cylinder = Cylinder::new(base, top, 0.7, true, gray());
+        check_shape(
+            &cylinder,
+            |p| {
+                let (z, rho) = (along(p), off_axis(p));
+                // on the side or on a cap
+                (rho - 0.7).abs().min(z.abs()).min((z - height).abs())
+            },
+            true,
+        );
+
+        let cone = Cone::new(base, top, 0.7, true, gray());
+        check_shape(
+            &cone,
+            |p| {
+                let (z, rho) = (along(p), off_axis(p));
+                let side = (rho - 0.7 * (1. - z / height)).abs() * height
+                    / (height * height + 0.49).sqrt();
+                side.min(z.abs())
+            },
+            true,
+        );
+
+        // an open cylinder is seen from inside through its ends
+        let open = Cylinder::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 2., 0.),
+            1.,
+            false,
+            gray(),
+        );
+        let r = Ray::new(Point3::new(0., 5., 0.), vec3(0.3, -1., 0.));
+        let rec = open.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn test_torus() {
+        let center = Point3::new(0., 1., -2.);
+        let normal = vec3(0.3, 1., 0.2).normalize();
+        let torus = Torus::new(center, normal, 1.5, 0.4, gray());
+        check_shape(
+            &torus,
+            |p| {
+                let v = p - center;
+                let z = v.dot(normal);
+                let rho = (v - z * normal).magnitude();
+                ((rho - 1.5).powi(2) + z * z).sqrt() - 0.4
+            },
+            true,
+        );
+
+        // straight down the hole misses, and from far away still hits the tube
+        let r = Ray::new(center + 10. * normal, -normal);
+        assert!(torus.hit(&r, 0.001, f64::INFINITY).is_none());
+        let onb = Onb::from_w(normal);
+        let r = Ray::new(center + 1000. * onb.u, -onb.u);
+        let rec = torus.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - (1000. - 1.9)).abs() < 1e-6, "{}", rec.t);
+    }
+}
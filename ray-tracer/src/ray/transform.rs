@@ -0,0 +1,511 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use cgmath::{
+    vec3, vec4, InnerSpace, Matrix, Matrix3, Matrix4, One, Point3, Quaternion, SquareMatrix,
+    Transform as _, Vector3,
+};
+
+use super::{aabb::Aabb, hittable::HitRecord, hittable::Hittable, Ray};
+use crate::sampler::Sampler;
+
+// The box around `bbox` after it has been through `matrix`.
+fn transform_box(bbox: &Aabb, matrix: &Matrix4<f64>) -> Aabb {
+    let corner = |i: usize| {
+        let pick = |bit: usize, axis: usize| {
+            if i & bit == 0 {
+                bbox.minimum[axis]
+            } else {
+                bbox.maximum[axis]
+            }
+        };
+        matrix.transform_point(Point3::new(pick(1, 0), pick(2, 1), pick(4, 2)))
+    };
+    let mut out = Aabb::new(corner(0), corner(0));
+    for i in 1..8 {
+        out = out.extend(corner(i));
+    }
+    return out;
+}
+
+// `r` in the space of an object moved by the inverse of `inverse`. The
+// direction is left unnormalized so t means the same in both spaces.
+fn local_ray(inverse: &Matrix4<f64>, r: &Ray) -> Ray {
+    return Ray::with_time(
+        inverse.transform_point(r.origin()),
+        inverse.transform_vector(r.direction()),
+        r.time(),
+    );
+}
+
+// A hit on a local_ray taken back out by `matrix`.
+fn world_record<'a>(
+    mut rec: HitRecord<'a>,
+    matrix: &Matrix4<f64>,
+    normal_matrix: &Matrix3<f64>,
+) -> HitRecord<'a> {
+    rec.p = matrix.transform_point(rec.p);
+    // normals go through the inverse transpose, which keeps them on the
+    // same side of the ray
+    rec.normal = (normal_matrix * rec.normal).normalize();
+    return rec;
+}
+
+// Intersects `object` as if it had been moved by `matrix`.
+fn hit_transformed<'a>(
+    object: &'a dyn Hittable,
+    matrix: &Matrix4<f64>,
+    inverse: &Matrix4<f64>,
+    normal_matrix: &Matrix3<f64>,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    let rec = object.hit(&local_ray(inverse, r), t_min, t_max)?;
+    return Some(world_record(rec, matrix, normal_matrix));
+}
+
+// the inverse transpose of the linear part, from the inverse
+fn normal_matrix(inverse: &Matrix4<f64>) -> Matrix3<f64> {
+    return Matrix3::from_cols(
+        inverse.x.truncate(),
+        inverse.y.truncate(),
+        inverse.z.truncate(),
+    )
+    .transpose();
+}
+
+// Places an object with an affine matrix. Rays are taken into object space
+// with the inverse, so the object never needs to know it was moved.
+pub struct Transform {
+    object: Box<dyn Hittable>,
+    matrix: Matrix4<f64>,
+    inverse: Matrix4<f64>,
+    normal_matrix: Matrix3<f64>,
+    bbox: Option<Aabb>,
+}
+
+impl Transform {
+    pub fn new(object: Box<dyn Hittable>, matrix: Matrix4<f64>) -> Result<Self> {
+        if matrix.row(3) != vec4(0., 0., 0., 1.) {
+            bail!(
+                "transform must be affine, got a last row of {:?}",
+                matrix.row(3)
+            );
+        }
+        let Some(inverse) = matrix.invert() else {
+            bail!("transform is not invertible: {:?}", matrix);
+        };
+        let bbox = object
+            .bounding_box()
+            .map(|bbox| transform_box(&bbox, &matrix));
+        return Ok(Self {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: normal_matrix(&inverse),
+            bbox,
+        });
+    }
+
+    pub fn matrix(&self) -> Matrix4<f64> {
+        self.matrix
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        return hit_transformed(
+            self.object.as_ref(),
+            &self.matrix,
+            &self.inverse,
+            &self.normal_matrix,
+            r,
+            t_min,
+            t_max,
+        );
+    }
+
+    fn hits(&self, r: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        let local = local_ray(&self.inverse, r);
+        return self
+            .object
+            .hits(&local, t_min, t_max)
+            .into_iter()
+            .map(|rec| world_record(rec, &self.matrix, &self.normal_matrix))
+            .collect();
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        let local_dir = self.inverse.transform_vector(dir.normalize());
+        let pdf = self
+            .object
+            .pdf_value(self.inverse.transform_point(origin), local_dir);
+        // a solid angle in object space is stretched by the linear part:
+        // dw_local / dw = |det A^-1| / |A^-1 w|^3
+        let det = self.normal_matrix.determinant().abs();
+        return pdf * det / local_dir.magnitude().powi(3);
+    }
+
+    fn random_direction(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let local = self
+            .object
+            .random_direction(self.inverse.transform_point(origin), sampler);
+        return self.matrix.transform_vector(local);
+    }
+}
+
+// Scale, then rotation, then translation. Unlike a matrix this can be
+// interpolated without shearing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pose {
+    pub translation: Vector3<f64>,
+    pub rotation: Quaternion<f64>,
+    pub scale: Vector3<f64>,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self {
+            translation: vec3(0., 0., 0.),
+            rotation: Quaternion::one(),
+            scale: vec3(1., 1., 1.),
+        }
+    }
+}
+
+impl Pose {
+    pub fn matrix(&self) -> Matrix4<f64> {
+        return Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
+    }
+
+    // the inverse of matrix(), without a general inversion
+    pub fn inverse_matrix(&self) -> Matrix4<f64> {
+        let inv = self.scale.map(|s| 1. / s);
+        return Matrix4::from_nonuniform_scale(inv.x, inv.y, inv.z)
+            * Matrix4::from(self.rotation.conjugate())
+            * Matrix4::from_translation(-self.translation);
+    }
+
+    // translation and scale are interpolated linearly, the rotation along
+    // the shortest arc
+    pub fn lerp(&self, other: &Pose, s: f64) -> Pose {
+        return Pose {
+            translation: self.translation + s * (other.translation - self.translation),
+            rotation: self
+                .rotation
+                .normalize()
+                .slerp(other.rotation.normalize(), s),
+            scale: self.scale + s * (other.scale - self.scale),
+        };
+    }
+}
+
+// steps taken through the shutter interval when bounding a MotionTransform
+const MOTION_BOUND_STEPS: usize = 64;
+
+// Moves an object from `start` at time0 to `end` at time1, for motion blur.
+// The object holds still outside of that interval. Moving objects aren't
+// light sampled.
+pub struct MotionTransform {
+    object: Box<dyn Hittable>,
+    start: Pose,
+    end: Pose,
+    time0: f64,
+    time1: f64,
+    bbox: Option<Aabb>,
+}
+
+impl MotionTransform {
+    pub fn new(
+        object: Box<dyn Hittable>,
+        start: Pose,
+        end: Pose,
+        time0: f64,
+        time1: f64,
+    ) -> Result<Self> {
+        for pose in [&start, &end] {
+            if pose.scale.x * pose.scale.y * pose.scale.z == 0. {
+                bail!("scale must not be zero, got {:?}", pose.scale);
+            }
+            if pose.rotation.magnitude2() == 0. {
+                bail!("rotation must not be a zero quaternion");
+            }
+        }
+        let mut motion = Self {
+            object,
+            start,
+            end,
+            time0,
+            time1,
+            bbox: None,
+        };
+        motion.bbox = motion.object.bounding_box().map(|bbox| {
+            // union of boxes along the way, grown by how far any corner moves
+            // between two steps to cover the arcs in between
+            let boxes: Vec<Aabb> = (0..=MOTION_BOUND_STEPS)
+                .map(|i| {
+                    let s = i as f64 / MOTION_BOUND_STEPS as f64;
+                    transform_box(&bbox, &start.lerp(&end, s).matrix())
+                })
+                .collect();
+            let mut out = boxes[0];
+            let mut step: f64 = 0.;
+            for pair in boxes.windows(2) {
+                out = Aabb::surrounding_box(&out, &pair[1]);
+                step = step
+                    .max((pair[1].minimum - pair[0].minimum).magnitude())
+                    .max((pair[1].maximum - pair[0].maximum).magnitude());
+            }
+            let pad = vec3(step, step, step);
+            Aabb::new(out.minimum - pad, out.maximum + pad)
+        });
+        return Ok(motion);
+    }
+
+    pub fn pose(&self, time: f64) -> Pose {
+        if self.time1 <= self.time0 {
+            return self.start;
+        }
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0., 1.);
+        return self.start.lerp(&self.end, s);
+    }
+}
+
+impl Hittable for MotionTransform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // the pose changes with the ray's time, so nothing can be cached
+        let pose = self.pose(r.time());
+        let inverse = pose.inverse_matrix();
+        return hit_transformed(
+            self.object.as_ref(),
+            &pose.matrix(),
+            &inverse,
+            &normal_matrix(&inverse),
+            r,
+            t_min,
+            t_max,
+        );
+    }
+
+    fn hits(&self, r: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        let pose = self.pose(r.time());
+        let inverse = pose.inverse_matrix();
+        let (matrix, normal_matrix) = (pose.matrix(), normal_matrix(&inverse));
+        return self
+            .object
+            .hits(&local_ray(&inverse, r), t_min, t_max)
+            .into_iter()
+            .map(|rec| world_record(rec, &matrix, &normal_matrix))
+            .collect();
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+}
+
+// Shares one object, typically a large mesh, between several placements.
+// Wrap it in a Transform to move each copy.
+#[derive(Clone)]
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
+        Self { object }
+    }
+
+    pub fn placed(object: Arc<dyn Hittable>, matrix: Matrix4<f64>) -> Result<Transform> {
+        return Transform::new(Box::new(Self::new(object)), matrix);
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.object.hit(r, t_min, t_max)
+    }
+
+    fn hits(&self, r: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        self.object.hits(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        self.object.pdf_value(origin, dir)
+    }
+
+    fn random_direction(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        self.object.random_direction(origin, sampler)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{Deg, EuclideanSpace, Rotation3};
+
+    use super::*;
+    use crate::{
+        material::{DiffuseLight, Lambertian},
+        ray::{hittable::Sphere, quad::Quad},
+        sampler::IndependentSampler,
+    };
+
+    #[test]
+    fn test_transformed_sphere() {
+        let unit = || {
+            Box::new(Sphere::new(
+                Point3::origin(),
+                1.,
+                Lambertian::new(vec3(0.5, 0.5, 0.5)),
+            ))
+        };
+        let matrix = Matrix4::from_translation(vec3(0., 4., 0.))
+            * Matrix4::from_axis_angle(vec3(0., 0., 1.), Deg(30.))
+            * Matrix4::from_scale(2.);
+        let moved = Transform::new(unit(), matrix).unwrap();
+        let sphere = Sphere::new(
+            Point3::new(0., 4., 0.),
+            2.,
+            Lambertian::new(vec3(0.5, 0.5, 0.5)),
+        );
+
+        let r = Ray::new(Point3::new(0.5, 0., 5.), vec3(0., 4., -5.));
+        let a = moved.hit(&r, 0.001, f64::INFINITY).unwrap();
+        let b = sphere.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((a.t - b.t).abs() < 1e-9);
+        assert!((a.p - b.p).magnitude() < 1e-9);
+        assert!((a.normal - b.normal).magnitude() < 1e-9);
+        assert_eq!(a.front_face, b.front_face);
+        // and so does every crossing, which CSG asks for
+        let (a, b) = (
+            moved.hits(&r, 0.001, f64::INFINITY),
+            sphere.hits(&r, 0.001, f64::INFINITY),
+        );
+        assert_eq!(a.len(), 2);
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(&b) {
+            assert!((a.t - b.t).abs() < 1e-9);
+            assert!((a.p - b.p).magnitude() < 1e-9);
+            assert_eq!(a.front_face, b.front_face);
+        }
+
+        let bbox = moved.bounding_box().unwrap();
+        let expected = sphere.bounding_box().unwrap();
+        // rotating the unit box makes it a little larger than it needs to be
+        for axis in 0..3 {
+            assert!(bbox.minimum[axis] <= expected.minimum[axis] + 1e-9);
+            assert!(bbox.maximum[axis] >= expected.maximum[axis] - 1e-9);
+        }
+
+        assert!(Transform::new(unit(), Matrix4::from_scale(0.)).is_err());
+    }
+
+    #[test]
+    fn test_light_pdf_follows_the_transform() {
+        // a unit quad squashed, rotated and moved must sample like the quad
+        // it ends up as
+        let matrix = Matrix4::from_translation(vec3(1., 3., -2.))
+            * Matrix4::from_axis_angle(vec3(1., 1., 0.).normalize(), Deg(50.))
+            * Matrix4::from_nonuniform_scale(3., 1., 0.5);
+        let unit = Quad::new(
+            Point3::origin(),
+            vec3(1., 0., 0.),
+            vec3(0., 0., 1.),
+            DiffuseLight::new(vec3(1., 1., 1.)),
+        );
+        let moved = Transform::new(Box::new(unit), matrix).unwrap();
+        let quad = Quad::new(
+            matrix.transform_point(Point3::origin()),
+            matrix.transform_vector(vec3(1., 0., 0.)),
+            matrix.transform_vector(vec3(0., 0., 1.)),
+            DiffuseLight::new(vec3(1., 1., 1.)),
+        );
+
+        let origin = Point3::new(0.2, -1., 0.3);
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let dir = moved.random_direction(origin, &mut sampler);
+            let expected = quad.pdf_value(origin, dir);
+            assert!(expected > 0.);
+            let pdf = moved.pdf_value(origin, dir);
+            assert!(
+                (pdf - expected).abs() < 1e-6 * expected,
+                "{} vs {}",
+                pdf,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_motion_transform() {
+        let start = Pose::default();
+        let end = Pose {
+            translation: vec3(4., 0., 0.),
+            rotation: Quaternion::from_axis_angle(vec3(0., 1., 0.), Deg(90.)),
+            scale: vec3(2., 1., 1.),
+        };
+        for pose in [start, end] {
+            let error = pose.matrix() * pose.inverse_matrix() - Matrix4::identity();
+            for col in 0..4 {
+                assert!(error[col].magnitude() < 1e-12);
+            }
+        }
+        let unit = Box::new(Sphere::new(
+            Point3::origin(),
+            1.,
+            Lambertian::new(vec3(0.5, 0.5, 0.5)),
+        ));
+        let moving = MotionTransform::new(unit, start, end, 0., 1.).unwrap();
+        let down = |x, time| Ray::with_time(Point3::new(x, 5., 0.), vec3(0., -1., 0.), time);
+        assert!(moving.hit(&down(0., 0.), 0.001, f64::INFINITY).is_some());
+        assert!(moving.hit(&down(4., 0.), 0.001, f64::INFINITY).is_none());
+        assert!(moving.hit(&down(4., 1.), 0.001, f64::INFINITY).is_some());
+        assert!(moving.hit(&down(2., 0.5), 0.001, f64::INFINITY).is_some());
+
+        // the rotated, stretched sphere at time 1 spans z in [-2, 2]
+        let bbox = moving.bounding_box().unwrap();
+        assert!(bbox.minimum.z <= -2. && bbox.maximum.z >= 2.);
+        assert!(bbox.minimum.x <= -1. && bbox.maximum.x >= 5.);
+        // every hit at any time is inside the box
+        for i in 0..=20 {
+            let time = i as f64 / 20.;
+            let r = Ray::with_time(Point3::new(-3., 0.3, 4.), vec3(1., 0., -1.), time);
+            if let Some(rec) = moving.hit(&r, 0.001, f64::INFINITY) {
+                for axis in 0..3 {
+                    assert!(rec.p[axis] >= bbox.minimum[axis] && rec.p[axis] <= bbox.maximum[axis]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_instances_share_the_object() {
+        let shared: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::origin(),
+            1.,
+            Lambertian::new(vec3(0.5, 0.5, 0.5)),
+        ));
+        let left =
+            Instance::placed(shared.clone(), Matrix4::from_translation(vec3(-3., 0., 0.))).unwrap();
+        let right =
+            Instance::placed(shared.clone(), Matrix4::from_translation(vec3(3., 0., 0.))).unwrap();
+        assert_eq!(Arc::strong_count(&shared), 3);
+
+        let down = |x| Ray::new(Point3::new(x, 5., 0.), vec3(0., -1., 0.));
+        assert!((left.hit(&down(-3.), 0.001, f64::INFINITY).unwrap().t - 4.).abs() < 1e-9);
+        assert!(left.hit(&down(3.), 0.001, f64::INFINITY).is_none());
+        assert!(right.hit(&down(3.), 0.001, f64::INFINITY).is_some());
+    }
+}
@@ -0,0 +1,362 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2, Vector3};
+
+use crate::{material::Material, sampler::Sampler};
+
+use super::{
+    aabb::Aabb,
+    bvh::{BvhNode, SplitHeuristic},
+    hittable::{HitRecord, Hittable, HittableList},
+    Ray,
+};
+
+// small padding so axis aligned triangles still get a box with volume
+const BOX_PADDING: f64 = 1e-6;
+
+// Woop, Benthin and Wald's watertight ray/triangle test. Returns t and the
+// barycentric weights of p0, p1 and p2; rays through a shared edge always hit
+// exactly one of the two neighbouring triangles.
+pub fn intersect_triangle(
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+    p0: Point3<f64>,
+    p1: Point3<f64>,
+    p2: Point3<f64>,
+) -> Option<(f64, [f64; 3])> {
+    let dir = r.dir;
+    let kz = if dir.x.abs() > dir.y.abs() && dir.x.abs() > dir.z.abs() {
+        0
+    } else if dir.y.abs() > dir.z.abs() {
+        1
+    } else {
+        2
+    };
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if dir[kz] < 0. {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    let sx = dir[kx] / dir[kz];
+    let sy = dir[ky] / dir[kz];
+    let sz = 1. / dir[kz];
+
+    let a = p0 - r.orig;
+    let b = p1 - r.orig;
+    let c = p2 - r.orig;
+
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    if (u < 0. || v < 0. || w < 0.) && (u > 0. || v > 0. || w > 0.) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0. {
+        return None;
+    }
+
+    let t_scaled = u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz];
+    let t = t_scaled / det;
+    if t <= t_min || t_max <= t {
+        return None;
+    }
+    return Some((t, [u / det, v / det, w / det]));
+}
+
+fn triangle_box(p0: Point3<f64>, p1: Point3<f64>, p2: Point3<f64>) -> Aabb {
+    let pad = Vector3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+    let bbox = Aabb::from_points(p0, p1).extend(p2);
+    return Aabb::new(bbox.minimum - pad, bbox.maximum + pad);
+}
+
+pub struct Triangle {
+    pub vertices: [Point3<f64>; 3],
+    pub mat_ptr: Box<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(
+        p0: Point3<f64>,
+        p1: Point3<f64>,
+        p2: Point3<f64>,
+        mat_ptr: Box<dyn Material>,
+    ) -> Self {
+        Self {
+            vertices: [p0, p1, p2],
+            mat_ptr,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let [p0, p1, p2] = self.vertices;
+        let (t, [_, b1, b2]) = intersect_triangle(r, t_min, t_max, p0, p1, p2)?;
+        let outward_normal = (p1 - p0).cross(p2 - p0).normalize();
+        return Some(
+            HitRecord::new(t, r.at(t), outward_normal, r, self.mat_ptr.as_ref()).with_uv(b1, b2),
+        );
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [p0, p1, p2] = self.vertices;
+        return Some(triangle_box(p0, p1, p2));
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, dir: Vector3<f64>) -> f64 {
+        let [p0, p1, p2] = self.vertices;
+        let r = Ray::new(origin, dir);
+        let Some((t, _)) = intersect_triangle(&r, 0.001, f64::INFINITY, p0, p1, p2) else {
+            return 0.;
+        };
+        let n = (p1 - p0).cross(p2 - p0);
+        let area = 0.5 * n.magnitude();
+        let distance_squared = t * t * dir.magnitude2();
+        let cosine = (dir.dot(n) / (dir.magnitude() * n.magnitude())).abs();
+        if cosine == 0. {
+            return 0.;
+        }
+        return distance_squared / (cosine * area);
+    }
+
+    // uniform over the triangle's area
+    fn random_direction(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let [p0, p1, p2] = self.vertices;
+        let [r1, r2] = sampler.get_2d();
+        let su = r1.sqrt();
+        let b0 = 1. - su;
+        let b1 = su * r2;
+        let b2 = 1. - b0 - b1;
+        let point = Point3::from_vec(b0 * p0.to_vec() + b1 * p1.to_vec() + b2 * p2.to_vec());
+        return point - origin;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    pub material: usize,
+}
+
+pub struct MeshData {
+    pub positions: Vec<Point3<f64>>,
+    pub normals: Vec<Vector3<f64>>,
+    pub uvs: Vec<Vector2<f64>>,
+    pub faces: Vec<MeshFace>,
+    pub materials: Vec<Box<dyn Material>>,
+}
+
+impl MeshData {
+    fn validate(&self) -> Result<()> {
+        for (i, face) in self.faces.iter().enumerate() {
+            if face.vertices.iter().any(|&v| v >= self.positions.len()) {
+                bail!("face {} references a vertex that does not exist", i);
+            }
+            if let Some(normals) = face.normals {
+                if normals.iter().any(|&n| n >= self.normals.len()) {
+                    bail!("face {} references a normal that does not exist", i);
+                }
+            }
+            if let Some(uvs) = face.uvs {
+                if uvs.iter().any(|&uv| uv >= self.uvs.len()) {
+                    bail!("face {} references a uv that does not exist", i);
+                }
+            }
+            if face.material >= self.materials.len() {
+                bail!("face {} references a material that does not exist", i);
+            }
+        }
+        return Ok(());
+    }
+}
+
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl MeshTriangle {
+    fn positions(&self) -> [Point3<f64>; 3] {
+        let face = &self.mesh.faces[self.face];
+        return face.vertices.map(|v| self.mesh.positions[v]);
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let [p0, p1, p2] = self.positions();
+        let (t, b) = intersect_triangle(r, t_min, t_max, p0, p1, p2)?;
+        let face = &self.mesh.faces[self.face];
+
+        let geometric_normal = (p1 - p0).cross(p2 - p0);
+        if geometric_normal.magnitude2() == 0. {
+            return None;
+        }
+        let (u, v) = match face.uvs {
+            Some(uvs) => {
+                let uv = b[0] * self.mesh.uvs[uvs[0]]
+                    + b[1] * self.mesh.uvs[uvs[1]]
+                    + b[2] * self.mesh.uvs[uvs[2]];
+                (uv.x, uv.y)
+            }
+            None => (b[1], b[2]),
+        };
+
+        let mut rec = HitRecord::new(
+            t,
+            r.at(t),
+            geometric_normal.normalize(),
+            r,
+            self.mesh.materials[face.material].as_ref(),
+        )
+        .with_uv(u, v);
+        if let Some(normals) = face.normals {
+            let n = b[0] * self.mesh.normals[normals[0]]
+                + b[1] * self.mesh.normals[normals[1]]
+                + b[2] * self.mesh.normals[normals[2]];
+            if n.magnitude2() > 0. {
+                rec.set_shading_normal(n);
+            }
+        }
+        return Some(rec);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [p0, p1, p2] = self.positions();
+        return Some(triangle_box(p0, p1, p2));
+    }
+}
+
+// A triangle mesh sharing one set of vertex, normal and uv buffers between all
+// of its faces, with its own BVH over the faces.
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+    bvh: BvhNode,
+}
+
+impl TriangleMesh {
+    pub fn new(data: MeshData) -> Result<Self> {
+        data.validate()?;
+        let data = Arc::new(data);
+        let mut triangles = HittableList::default();
+        for face in 0..data.faces.len() {
+            triangles.add(Box::new(MeshTriangle {
+                mesh: data.clone(),
+                face,
+            }));
+        }
+        let bvh = BvhNode::new(triangles, SplitHeuristic::Sah)?;
+        return Ok(Self { data, bvh });
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.faces.is_empty()
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.bvh.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{vec3, Point3};
+
+    use super::*;
+    use crate::{material::Lambertian, rng::Rng};
+
+    #[test]
+    fn test_shared_edge_is_watertight() {
+        // two triangles forming a unit square split along its diagonal
+        let p = [
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 0., 0.),
+            Point3::new(1., 1., 0.),
+            Point3::new(0., 1., 0.),
+        ];
+        let mut rng = Rng::new(0);
+        for _ in 0..10000 {
+            let s = rng.uniform();
+            let origin = Point3::new(rng.range(-2. ..2.), rng.range(-2. ..2.), 5.);
+            let target = Point3::new(s, s, 0.);
+            let r = Ray::new(origin, target - origin);
+            let first = intersect_triangle(&r, 0., f64::INFINITY, p[0], p[1], p[2]);
+            let second = intersect_triangle(&r, 0., f64::INFINITY, p[0], p[2], p[3]);
+            assert!(
+                first.is_some() || second.is_some(),
+                "ray leaked through {:?}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_mesh_interpolates_normals() {
+        let mesh = TriangleMesh::new(MeshData {
+            positions: vec![
+                Point3::new(-1., -1., 0.),
+                Point3::new(1., -1., 0.),
+                Point3::new(0., 1., 0.),
+            ],
+            normals: vec![vec3(-1., 0., 1.), vec3(1., 0., 1.), vec3(0., 0., 1.)],
+            uvs: vec![],
+            faces: vec![MeshFace {
+                vertices: [0, 1, 2],
+                normals: Some([0, 1, 2]),
+                uvs: None,
+                material: 0,
+            }],
+            materials: vec![Lambertian::new(vec3(0.5, 0.5, 0.5))],
+        })
+        .unwrap();
+
+        let r = Ray::new(Point3::new(0.5, -0.5, 1.), vec3(0., 0., -1.));
+        let rec = mesh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.).abs() < 1e-9);
+        assert!(rec.front_face);
+        assert!(rec.normal.x > 0. && rec.normal.z > 0.);
+        assert!((rec.normal.magnitude() - 1.).abs() < 1e-9);
+
+        let missing = MeshData {
+            positions: vec![Point3::new(0., 0., 0.)],
+            normals: vec![],
+            uvs: vec![],
+            faces: vec![MeshFace {
+                vertices: [0, 1, 2],
+                normals: None,
+                uvs: None,
+                material: 0,
+            }],
+            materials: vec![],
+        };
+        assert!(TriangleMesh::new(missing).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use cgmath::{vec3, InnerSpace, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ray::{hittable::HitRecord, Ray},
+    Color,
+};
+
+// Arbitrary output variables, buffers describing what the camera rays hit
+// first, for compositing and denoising.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aov {
+    Albedo,
+    // the shading normal in world space, facing the camera
+    Normal,
+    // distance from the camera
+    Depth,
+    // 1 + the index in `objects`
+    ObjectId,
+    // 1 + the index in `materials`, in name order
+    MaterialId,
+}
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+        }
+    }
+}
+
+// What one camera ray hit first. Rays that hit nothing leave everything 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AovSample {
+    pub albedo: Color,
+    pub normal: Vector3<f64>,
+    pub depth: f64,
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        Self {
+            albedo: vec3(0., 0., 0.),
+            normal: vec3(0., 0., 0.),
+            depth: 0.,
+            object_id: 0,
+            material_id: 0,
+        }
+    }
+}
+
+impl AovSample {
+    // filled in by the integrator from the first hit of the camera ray `r`
+    pub fn from_hit(r: &Ray, rec: &HitRecord) -> Self {
+        // a point inside a medium has no normal to show
+        let normal = if rec.mat_ptr.is_volumetric() {
+            vec3(0., 0., 0.)
+        } else {
+            rec.normal
+        };
+        return Self {
+            albedo: rec.mat_ptr.albedo(rec),
+            normal,
+            depth: rec.t * r.direction().magnitude(),
+            object_id: rec.object_id,
+            material_id: rec.mat_ptr.id(),
+        };
+    }
+}
+
+// The AOV samples of one pixel. Albedo, normal and depth are summed like the
+// colour, the IDs can't be averaged and come from the first sample.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AovSums {
+    pub sums: AovSample,
+}
+
+impl AovSums {
+    // `count` is how many samples were added before this one
+    pub fn add(&mut self, sample: &AovSample, count: u32) {
+        self.sums.albedo += sample.albedo;
+        self.sums.normal += sample.normal;
+        self.sums.depth += sample.depth;
+        if count == 0 {
+            self.sums.object_id = sample.object_id;
+            self.sums.material_id = sample.material_id;
+        }
+    }
+
+    // the pixel value of `aov` after `count` samples, IDs repeated in all channels
+    pub fn value(&self, aov: Aov, count: u32) -> Color {
+        let n = count.max(1) as f64;
+        return match aov {
+            Aov::Albedo => self.sums.albedo / n,
+            Aov::Normal => {
+                let normal = self.sums.normal / n;
+                if normal.magnitude2() > 0. {
+                    normal.normalize()
+                } else {
+                    normal
+                }
+            }
+            Aov::Depth => vec3(1., 1., 1.) * self.sums.depth / n,
+            Aov::ObjectId => vec3(1., 1., 1.) * self.sums.object_id as f64,
+            Aov::MaterialId => vec3(1., 1., 1.) * self.sums.material_id as f64,
+        };
+    }
+}
@@ -0,0 +1,192 @@
+use cgmath::{vec3, ElementWise, InnerSpace};
+use rayon::prelude::*;
+
+use crate::{image::Framebuffer, scene::description::DenoiseSettings, Color};
+
+use super::{
+    aov::Aov,
+    film::{luminance, Film},
+};
+
+// the B3 spline the à-trous levels are built from
+const KERNEL: [f64; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+
+// albedos darker than this aren't divided out, lights and the sky are 0
+const MIN_ALBEDO: f64 = 1e-3;
+
+struct Guides {
+    albedo: Vec<Color>,
+    normal: Vec<Color>,
+    depth: Vec<f64>,
+}
+
+// Edge-aware à-trous wavelet filter (Dammertz et al., with the luminance
+// weights of SVGF). Each iteration blurs with a 5x5 kernel whose taps are
+// spread 2^i pixels apart, weighted down across differences in normal, depth
+// and, relative to the pixel's noise, luminance. Albedo is divided out first
+// and multiplied back in at the end, so textures stay sharp.
+//
+// Without AOVs in the film only the luminance weight is used.
+pub fn denoise(film: &Film, settings: &DenoiseSettings) -> Framebuffer {
+    let (width, height) = (film.width(), film.height());
+    let guides = film.aov(Aov::Albedo).map(|albedo| Guides {
+        albedo: albedo.pixels().to_vec(),
+        normal: film.aov(Aov::Normal).unwrap().pixels().to_vec(),
+        depth: film
+            .aov(Aov::Depth)
+            .unwrap()
+            .pixels()
+            .iter()
+            .map(|d| d.x)
+            .collect(),
+    });
+
+    let demodulate = |i: usize| -> Color {
+        let Some(guides) = &guides else {
+            return vec3(1., 1., 1.);
+        };
+        return guides.albedo[i].map(|a| if a < MIN_ALBEDO { 1. } else { a });
+    };
+    let mut color: Vec<Color> = film
+        .pixels()
+        .iter()
+        .enumerate()
+        .map(|(i, p)| p.mean().div_element_wise(demodulate(i)))
+        .collect();
+    // of the demodulated luminance, filtered along with the colour
+    let mut variance: Vec<f64> = film
+        .pixels()
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.standard_error() / luminance(demodulate(i))).powi(2))
+        .collect();
+
+    for iteration in 0..settings.iterations {
+        let step = 1i64 << iteration;
+        let (next_color, next_variance) = (0..color.len())
+            .into_par_iter()
+            .map(|p| {
+                let (x, y) = ((p as u32 % width) as i64, (p as u32 / width) as i64);
+                let lum_p = luminance(color[p]);
+                let sigma_l = settings.sigma_color * variance[p].max(0.).sqrt() + 1e-6;
+                let mut sum = vec3(0., 0., 0.);
+                let mut sum_variance = 0.;
+                let mut total = 0.;
+                for (dy, ky) in KERNEL.iter().enumerate() {
+                    for (dx, kx) in KERNEL.iter().enumerate() {
+                        let qx = x + (dx as i64 - 2) * step;
+                        let qy = y + (dy as i64 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= width as i64 || qy >= height as i64 {
+                            continue;
+                        }
+                        let q = (qy * width as i64 + qx) as usize;
+                        let mut w = kx * ky;
+                        w *= (-(luminance(color[q]) - lum_p).abs() / sigma_l).exp();
+                        if let Some(guides) = &guides {
+                            w *= edge_weight(guides, p, q, step as f64, settings);
+                        }
+                        // single sample pixels have infinite variance, keep 0 * inf out
+                        if w == 0. {
+                            continue;
+                        }
+                        sum += w * color[q];
+                        sum_variance += w * w * variance[q];
+                        total += w;
+                    }
+                }
+                // the centre tap always has weight, total is never 0
+                (sum / total, sum_variance / (total * total))
+            })
+            .unzip();
+        color = next_color;
+        variance = next_variance;
+    }
+
+    let pixels = color
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| c.mul_element_wise(demodulate(i)))
+        .collect();
+    return Framebuffer::from_pixels(width, height, pixels).unwrap();
+}
+
+fn edge_weight(guides: &Guides, p: usize, q: usize, step: f64, settings: &DenoiseSettings) -> f64 {
+    let (n_p, n_q) = (guides.normal[p], guides.normal[q]);
+    // the background has no normal, and only blends with itself
+    let normal = if n_p.magnitude2() == 0. || n_q.magnitude2() == 0. {
+        if n_p.magnitude2() == n_q.magnitude2() {
+            1.
+        } else {
+            0.
+        }
+    } else {
+        n_p.dot(n_q).max(0.).powf(settings.sigma_normal)
+    };
+    let (z_p, z_q) = (guides.depth[p], guides.depth[q]);
+    // relative, and looser for further taps so slanted surfaces still blend
+    let sigma_z = settings.sigma_depth * step.sqrt() * z_p.max(z_q) + 1e-6;
+    let depth = (-(z_p - z_q).abs() / sigma_z).exp();
+    return normal * depth;
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        render::{render, render_pass},
+        scene::{description::SceneDescription, Scene},
+    };
+
+    const SCENE: &str = r#"{
+        "render": { "image_width": 96, "aspect_ratio": 1.5, "samples_per_pixel": 8 },
+        "camera": { "lookfrom": [0, 1, 5], "lookat": [0, 0.5, 0], "vfov": 40 },
+        "materials": {
+            "ground": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] },
+            "red": { "type": "lambertian", "albedo": [0.8, 0.1, 0.1] }
+        },
+        "objects": [
+            { "type": "sphere", "center": [0, -1000, 0], "radius": 1000, "material": "ground" },
+            { "type": "sphere", "center": [0, 0.5, 0], "radius": 0.5, "material": "red" }
+        ]
+    }"#;
+
+    fn build(json: &str) -> Scene {
+        return SceneDescription::from_json(json)
+            .unwrap()
+            .build(Path::new(""))
+            .unwrap();
+    }
+
+    fn error(image: &Framebuffer, reference: &Framebuffer) -> f64 {
+        return image
+            .pixels()
+            .iter()
+            .zip(reference.pixels())
+            .map(|(a, b)| (a - b).magnitude2())
+            .sum::<f64>()
+            / image.pixels().len() as f64;
+    }
+
+    #[test]
+    fn test_denoise_reduces_error() {
+        let reference = render(&build(
+            &SCENE.replace(r#""samples_per_pixel": 8"#, r#""samples_per_pixel": 256"#),
+        ));
+        let scene = build(&SCENE.replace(
+            r#""samples_per_pixel": 8"#,
+            r#""samples_per_pixel": 8, "denoise": {}"#,
+        ));
+        let mut film = Film::for_scene(&scene);
+        while !render_pass(&scene, &mut film, None) {}
+
+        let noisy = error(&film.framebuffer(), &reference);
+        let denoised = denoise(&film, scene.settings.denoise.as_ref().unwrap());
+        let filtered = error(&denoised, &reference);
+        assert!(filtered < noisy / 2.5, "{} -> {}", noisy, filtered);
+        // the sky is almost noise free and isn't blurred
+        assert!((denoised.get(48, 0) - film.get(48, 0).mean()).magnitude() < 1e-3);
+        assert_eq!(render(&scene), denoised);
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{vec3, InnerSpace};
+
+use crate::{image::Framebuffer, scene::Scene, Color};
+
+use super::aov::{Aov, AovSums};
+
+const MAGIC: &[u8; 8] = b"RTFILM03";
+// the magic, width, height, fingerprint and AOV flag
+const HEADER_BYTES: u64 = 8 + 4 + 4 + 8 + 1;
+// a pixel's count and five sums, and its AOV sums and IDs
+const PIXEL_BYTES: u64 = 4 + 5 * 8;
+const AOV_BYTES: u64 = 7 * 8 + 2 * 4;
+
+// The samples taken so far at one pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelStats {
+    pub sum: Color,
+    pub count: u32,
+    // Welford's running mean and sum of squared differences of the
+    // luminance, for the variance
+    pub luminance_mean: f64,
+    pub luminance_m2: f64,
+}
+
+impl Default for PixelStats {
+    fn default() -> Self {
+        Self {
+            sum: vec3(0., 0., 0.),
+            count: 0,
+            luminance_mean: 0.,
+            luminance_m2: 0.,
+        }
+    }
+}
+
+impl PixelStats {
+    pub fn add(&mut self, color: Color) {
+        self.sum += color;
+        self.count += 1;
+        let y = luminance(color);
+        let delta = y - self.luminance_mean;
+        self.luminance_mean += delta / self.count as f64;
+        self.luminance_m2 += delta * (y - self.luminance_mean);
+    }
+
+    // black while nothing was sampled
+    pub fn mean(&self) -> Color {
+        if self.count == 0 {
+            return vec3(0., 0., 0.);
+        }
+        return self.sum / self.count as f64;
+    }
+
+    // of the mean luminance, infinite below two samples
+    pub fn standard_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let n = self.count as f64;
+        return (self.luminance_m2 / (n - 1.) / n).sqrt();
+    }
+}
+
+pub fn luminance(color: Color) -> f64 {
+    return color.dot(vec3(0.2126, 0.7152, 0.0722));
+}
+
+// The running per pixel sums of a render, stored top row first like
+// Framebuffer. Saved to disk it is a checkpoint that a later run picks up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Film {
+    width: u32,
+    height: u32,
+    // identifies the scene the samples belong to
+    fingerprint: u64,
+    pixels: Vec<PixelStats>,
+    // empty unless the scene asks for AOVs
+    aovs: Vec<AovSums>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, fingerprint: u64, with_aovs: bool) -> Self {
+        let n = width as usize * height as usize;
+        return Self {
+            width,
+            height,
+            fingerprint,
+            pixels: vec![PixelStats::default(); n],
+            aovs: vec![AovSums::default(); if with_aovs { n } else { 0 }],
+        };
+    }
+
+    pub fn for_scene(scene: &Scene) -> Self {
+        return Self::new(
+            scene.settings.image_width,
+            scene.settings.image_height(),
+            scene.fingerprint,
+            Self::wants_aovs(scene),
+        );
+    }
+
+    fn wants_aovs(scene: &Scene) -> bool {
+        // the denoiser is guided by the albedo, normal and depth
+        return !scene.settings.aovs.is_empty() || scene.settings.denoise.is_some();
+    }
+
+    // continues from the checkpoint at `path`, which must come from `scene`
+    pub fn resume<P: AsRef<Path>>(path: P, scene: &Scene) -> Result<Self> {
+        // checked before the pixels are read, which a different scene may
+        // have far more of
+        let expected = (
+            scene.settings.image_width,
+            scene.settings.image_height(),
+            scene.fingerprint,
+            Self::wants_aovs(scene),
+        );
+        return Self::read(path.as_ref(), |header| {
+            if header != expected {
+                bail!("saved for a different scene, delete it to start over");
+            }
+            return Ok(());
+        });
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // x counts columns from the left, y rows from the top
+    pub fn get(&self, x: u32, y: u32) -> PixelStats {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, stats: PixelStats) {
+        self.pixels[(y * self.width + x) as usize] = stats;
+    }
+
+    pub fn pixels(&self) -> &[PixelStats] {
+        &self.pixels
+    }
+
+    pub fn has_aovs(&self) -> bool {
+        !self.aovs.is_empty()
+    }
+
+    // panics without AOVs
+    pub fn get_aovs(&self, x: u32, y: u32) -> AovSums {
+        self.aovs[(y * self.width + x) as usize]
+    }
+
+    pub fn set_aovs(&mut self, x: u32, y: u32, aovs: AovSums) {
+        self.aovs[(y * self.width + x) as usize] = aovs;
+    }
+
+    pub fn total_count(&self) -> u64 {
+        return self.pixels.iter().map(|p| p.count as u64).sum();
+    }
+
+    pub fn framebuffer(&self) -> Framebuffer {
+        let pixels = self.pixels.iter().map(|p| p.mean()).collect();
+        return Framebuffer::from_pixels(self.width, self.height, pixels).unwrap();
+    }
+
+    // None if the film doesn't keep AOVs
+    pub fn aov(&self, aov: Aov) -> Option<Framebuffer> {
+        if !self.has_aovs() {
+            return None;
+        }
+        let pixels = self
+            .aovs
+            .iter()
+            .zip(&self.pixels)
+            .map(|(aovs, p)| aovs.value(aov, p.count))
+            .collect();
+        return Some(Framebuffer::from_pixels(self.width, self.height, pixels).unwrap());
+    }
+
+    // How many samples each pixel took, from black for none through purple
+    // and orange to white for `max` or more.
+    pub fn heatmap(&self, max: u32) -> Framebuffer {
+        let stops = [
+            vec3(0., 0., 0.),
+            vec3(0.35, 0.05, 0.5),
+            vec3(0.95, 0.45, 0.05),
+            vec3(1., 1., 1.),
+        ];
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|p| {
+                let t = (p.count as f64 / max.max(1) as f64).min(1.) * 3.;
+                let i = (t as usize).min(2);
+                stops[i] + (t - i as f64) * (stops[i + 1] - stops[i])
+            })
+            .collect();
+        return Framebuffer::from_pixels(self.width, self.height, pixels).unwrap();
+    }
+
+    // Writes next to `path` first and then renames, so a crash while saving
+    // leaves the previous checkpoint intact.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let write = || -> Result<()> {
+            let mut w = BufWriter::new(File::create(&tmp)?);
+            w.write_all(MAGIC)?;
+            w.write_all(&self.width.to_le_bytes())?;
+            w.write_all(&self.height.to_le_bytes())?;
+            w.write_all(&self.fingerprint.to_le_bytes())?;
+            w.write_all(&[self.has_aovs() as u8])?;
+            for p in self.pixels.iter() {
+                w.write_all(&p.count.to_le_bytes())?;
+                for c in [p.sum.x, p.sum.y, p.sum.z, p.luminance_mean, p.luminance_m2] {
+                    w.write_all(&c.to_le_bytes())?;
+                }
+            }
+            for a in self.aovs.iter() {
+                let s = &a.sums;
+                for c in [
+                    s.albedo.x, s.albedo.y, s.albedo.z, s.normal.x, s.normal.y, s.normal.z, s.depth,
+                ] {
+                    w.write_all(&c.to_le_bytes())?;
+                }
+                w.write_all(&s.object_id.to_le_bytes())?;
+                w.write_all(&s.material_id.to_le_bytes())?;
+            }
+            w.into_inner()?.sync_all()?;
+            std::fs::rename(&tmp, path)?;
+            return Ok(());
+        };
+        return write().with_context(|| format!("failed to write {}", path.display()));
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        return Self::read(path.as_ref(), |_| Ok(()));
+    }
+
+    // `check` gets the width, height, fingerprint and whether there are AOVs
+    fn read(path: &Path, check: impl FnOnce((u32, u32, u64, bool)) -> Result<()>) -> Result<Self> {
+        let read = || -> Result<Self> {
+            let mut r = BufReader::new(File::open(path)?);
+            let mut magic = [0; 8];
+            r.read_exact(&mut magic)?;
+            if &magic != MAGIC {
+                bail!("not a checkpoint, or one from an older version");
+            }
+            let mut u32_buf = [0; 4];
+            let mut u64_buf = [0; 8];
+            let mut read_u32 = |r: &mut BufReader<File>| -> Result<u32> {
+                r.read_exact(&mut u32_buf)?;
+                return Ok(u32::from_le_bytes(u32_buf));
+            };
+            let width = read_u32(&mut r)?;
+            let height = read_u32(&mut r)?;
+            r.read_exact(&mut u64_buf)?;
+            let fingerprint = u64::from_le_bytes(u64_buf);
+            let mut with_aovs = [0];
+            r.read_exact(&mut with_aovs)?;
+            let with_aovs = with_aovs[0] != 0;
+            check((width, height, fingerprint, with_aovs))?;
+            // a corrupt header mustn't get to allocate the film
+            let pixel_bytes = PIXEL_BYTES + if with_aovs { AOV_BYTES } else { 0 };
+            let length = u64::from(width)
+                .checked_mul(u64::from(height))
+                .and_then(|n| n.checked_mul(pixel_bytes))
+                .and_then(|n| n.checked_add(HEADER_BYTES));
+            if length != Some(r.get_ref().metadata()?.len()) {
+                bail!(
+                    "the file is too short or too long for {}x{} pixels",
+                    width,
+                    height
+                );
+            }
+            let mut film = Self::new(width, height, fingerprint, with_aovs);
+            for p in film.pixels.iter_mut() {
+                p.count = read_u32(&mut r)?;
+                let mut c = [0.; 5];
+                for c in c.iter_mut() {
+                    r.read_exact(&mut u64_buf)?;
+                    *c = f64::from_le_bytes(u64_buf);
+                }
+                p.sum = vec3(c[0], c[1], c[2]);
+                p.luminance_mean = c[3];
+                p.luminance_m2 = c[4];
+            }
+            for a in film.aovs.iter_mut() {
+                let mut c = [0.; 7];
+                for c in c.iter_mut() {
+                    r.read_exact(&mut u64_buf)?;
+                    *c = f64::from_le_bytes(u64_buf);
+                }
+                a.sums.albedo = vec3(c[0], c[1], c[2]);
+                a.sums.normal = vec3(c[3], c[4], c[5]);
+                a.sums.depth = c[6];
+                a.sums.object_id = read_u32(&mut r)?;
+                a.sums.material_id = read_u32(&mut r)?;
+            }
+            if r.read(&mut [0])? != 0 {
+                bail!("trailing data");
+            }
+            return Ok(film);
+        };
+        return read().with_context(|| format!("failed to read checkpoint {}", path.display()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pixel_stats() {
+        let mut p = PixelStats::default();
+        assert_eq!(p.mean(), vec3(0., 0., 0.));
+        p.add(vec3(1., 1., 1.));
+        assert_eq!(p.standard_error(), f64::INFINITY);
+        p.add(vec3(3., 3., 3.));
+        p.add(vec3(2., 2., 2.));
+        assert_eq!(p.mean(), vec3(2., 2., 2.));
+        // sample variance 1 over 3 samples
+        assert!((p.standard_error() - (1f64 / 3.).sqrt()).abs() < 1e-12);
+        // no noise at all
+        let mut flat = PixelStats::default();
+        for _ in 0..10 {
+            flat.add(vec3(0.1, 0.2, 0.3));
+        }
+        assert!(flat.standard_error() < 1e-12);
+    }
+
+    #[test]
+    fn test_corrupt_checkpoint() {
+        let path = std::env::temp_dir().join(format!("corrupt-{}.checkpoint", std::process::id()));
+        let film = Film::new(3, 2, 42, true);
+        film.save(&path).unwrap();
+        assert_eq!(Film::load(&path).unwrap(), film);
+
+        // a header claiming 2^64 pixels is an error rather than an abort
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[8..16].copy_from_slice(&[0xff; 8]);
+        std::fs::write(&path, &bytes).unwrap();
+        let err = Film::load(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            format!("{:#}", err).contains("4294967295x4294967295"),
+            "{:#}",
+            err
+        );
+    }
+}
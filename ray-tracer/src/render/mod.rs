@@ -0,0 +1,351 @@
+mod aov;
+mod denoise;
+mod film;
+mod output;
+
+use std::{
+    io::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use cgmath::vec3;
+use rayon::prelude::*;
+
+use crate::{
+    image::Framebuffer,
+    scene::{description::RenderSettings, Scene},
+    spectrum::sample_wavelength,
+};
+
+pub use self::{
+    aov::{Aov, AovSample, AovSums},
+    denoise::denoise,
+    film::{luminance, Film, PixelStats},
+    output::{checkpoint_path, frame_path, is_finished, parse_frames},
+};
+
+// A pixel's new stats after a pass, with its AOVs when the film has them.
+type PixelUpdate = (u32, u32, PixelStats, Option<AovSums>);
+
+// Renders `scene` with its own settings in one go.
+pub fn render(scene: &Scene) -> Framebuffer {
+    let mut film = Film::for_scene(scene);
+    while !render_pass(scene, &mut film, None) {}
+    return image(scene, &film);
+}
+
+// The image to save for `film`, denoised if the scene asks for it.
+pub fn image(scene: &Scene, film: &Film) -> Framebuffer {
+    return match &scene.settings.denoise {
+        Some(settings) => denoise(film, settings),
+        None => film.framebuffer(),
+    };
+}
+
+// Whether `pixel` needs no more samples: it has samples_per_pixel or, with
+// adaptive sampling, its noise is below the threshold. The threshold is on
+// the standard error relative to the square root of the mean, roughly the
+// noise left after the gamma curve.
+pub fn is_done(settings: &RenderSettings, pixel: &PixelStats) -> bool {
+    if pixel.count >= settings.samples_per_pixel {
+        return true;
+    }
+    let Some(adaptive) = &settings.adaptive else {
+        return false;
+    };
+    if pixel.count < adaptive.min_samples {
+        return false;
+    }
+    let mean = luminance(pixel.mean()).max(0.);
+    return pixel.standard_error() <= adaptive.threshold * mean.sqrt().max(1e-3);
+}
+
+// Adds up to `samples_per_pass` samples to every pixel of `film` that isn't
+// done yet, tile by tile, and returns whether all pixels are done. Tiles that
+// start after `deadline` are skipped.
+//
+// Every sample value comes from the scene's sampler, which only depends on
+// the seed, the pixel and the sample number, and each pixel adds its samples
+// to its running sum in order. So the image doesn't change with how rayon
+// splits the work between threads, nor with how the render was split into
+// passes and runs.
+pub fn render_pass(scene: &Scene, film: &mut Film, deadline: Option<Instant>) -> bool {
+    let settings = &scene.settings;
+    let target = settings.samples_per_pixel;
+    if film.pixels().iter().all(|p| is_done(settings, p)) {
+        return true;
+    }
+    let integrator = settings.integrator();
+    let (width, height) = (film.width(), film.height());
+    let tile_size = settings.tile_size;
+    let tiles: Vec<(u32, u32)> = (0..height)
+        .step_by(tile_size as usize)
+        .flat_map(|y| (0..width).step_by(tile_size as usize).map(move |x| (x, y)))
+        .collect();
+
+    let total = width as u64 * height as u64 * target as u64;
+    let done = AtomicU64::new(film.total_count());
+    let film_ref = &*film;
+    let updates: Vec<Vec<PixelUpdate>> = tiles
+        .par_iter()
+        .map(|&(x0, y0)| {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Vec::new();
+            }
+            let mut sampler = settings.sampler();
+            let mut updated = Vec::new();
+            let mut added = 0;
+            for y in y0..(y0 + tile_size).min(height) {
+                for x in x0..(x0 + tile_size).min(width) {
+                    let mut stats = film_ref.get(x, y);
+                    let mut aovs = film_ref.has_aovs().then(|| film_ref.get_aovs(x, y));
+                    let start = stats.count;
+                    let end = start.saturating_add(settings.samples_per_pass);
+                    // i, j and the pixel number count rows from the bottom
+                    let (i, j) = (x, height - 1 - y);
+                    let pixel = j as u64 * width as u64 + i as u64;
+                    // checked every sample, so that where a pixel stops doesn't
+                    // depend on how the samples were split into passes
+                    while stats.count < end && !is_done(settings, &stats) {
+                        sampler.start_sample(pixel, stats.count as u64);
+                        // a box filter over the pixel
+                        let [du, dv] = sampler.get_2d();
+                        let u = (i as f64 + du) / (width - 1) as f64;
+                        let v = (j as f64 + dv) / (height - 1) as f64;
+                        let Some(mut r) = scene.camera.get_ray(u, v, sampler.as_mut()) else {
+                            // black outside what the camera sees
+                            if let Some(aovs) = aovs.as_mut() {
+                                aovs.add(&AovSample::default(), stats.count);
+                            }
+                            stats.add(vec3(0., 0., 0.));
+                            continue;
+                        };
+                        if settings.spectral {
+                            r = r.with_wavelength(sample_wavelength(sampler.get_1d()));
+                        }
+                        // rays that hit nothing leave the AOVs at their defaults
+                        let mut aov = AovSample::default();
+                        let color = integrator.color(
+                            &r,
+                            scene,
+                            sampler.as_mut(),
+                            aovs.is_some().then_some(&mut aov),
+                        );
+                        if let Some(aovs) = aovs.as_mut() {
+                            aovs.add(&aov, stats.count);
+                        }
+                        stats.add(color);
+                    }
+                    if stats.count > start {
+                        updated.push((x, y, stats, aovs));
+                        added += (stats.count - start) as u64;
+                    }
+                }
+            }
+            let done = done.fetch_add(added, Ordering::SeqCst) + added;
+            eprint!("\r{:.2}%", done as f64 / total as f64 * 100.);
+            std::io::stderr().flush().unwrap();
+            updated
+        })
+        .collect();
+
+    for (x, y, stats, aovs) in updates.into_iter().flatten() {
+        film.set(x, y, stats);
+        if let Some(aovs) = aovs {
+            film.set_aovs(x, y, aovs);
+        }
+    }
+    let finished = film.pixels().iter().all(|p| is_done(settings, p));
+    if finished {
+        eprintln!("\nDone. ");
+    }
+    return finished;
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use cgmath::{vec3, InnerSpace, Point3};
+
+    use super::*;
+    use crate::{ray::Ray, sampler::IndependentSampler, scene::description::SceneDescription};
+
+    const SCENE: &str = r#"{
+        "render": { "image_width": 16, "aspect_ratio": 1, "samples_per_pixel": 3, "seed": 7,
+                    "samples_per_pass": 2, "tile_size": 5 },
+        "camera": { "lookfrom": [0, 1, 6], "lookat": [0, 0.5, 0], "vfov": 40, "aperture": 0.2,
+                    "shutter": [0, 1] },
+        "materials": {
+            "ground": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] },
+            "metal": { "type": "metal", "albedo": [0.8, 0.6, 0.2], "fuzz": 0.3 },
+            "glass": { "type": "dielectric", "ir": 1.5 },
+            "smoke": { "type": "isotropic", "albedo": [0.9, 0.9, 0.9] }
+        },
+        "objects": [
+            { "type": "sphere", "center": [0, -1000, 0], "radius": 1000, "material": "ground" },
+            { "type": "moving_sphere", "center0": [-1, 0.5, 0], "center1": [-1, 1, 0],
+              "radius": 0.5, "material": "metal" },
+            { "type": "sphere", "center": [1, 0.5, 0], "radius": 0.5, "material": "glass" },
+            { "type": "constant_medium", "density": 2, "material": "smoke",
+              "boundary": { "type": "sphere", "center": [0, 0.5, 1], "radius": 0.4,
+                            "material": "smoke" } }
+        ]
+    }"#;
+
+    fn build(json: &str) -> Scene {
+        return SceneDescription::from_json(json)
+            .unwrap()
+            .build(Path::new(""))
+            .unwrap();
+    }
+
+    fn render_with_threads(scene: &Scene, threads: usize) -> Framebuffer {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        return pool.install(|| render(scene));
+    }
+
+    #[test]
+    fn test_seed_fixes_the_image() {
+        let scene = build(SCENE);
+        let single = render_with_threads(&scene, 1);
+        assert_eq!(single, render_with_threads(&scene, 4));
+        assert_eq!(single, render(&scene));
+
+        let reseeded = SCENE.replace(r#""seed": 7"#, r#""seed": 8"#);
+        assert_ne!(single, render(&build(&reseeded)));
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let path = std::env::temp_dir().join(format!("film-{}.checkpoint", std::process::id()));
+        let full = build(&SCENE.replace(r#""samples_per_pixel": 3"#, r#""samples_per_pixel": 7"#));
+
+        // stop after 3 samples, then pick up the saved film with a higher target
+        let mut film = Film::for_scene(&full);
+        let partial = build(SCENE);
+        while !render_pass(&partial, &mut film, None) {}
+        film.save(&path).unwrap();
+        let mut resumed = Film::resume(&path, &full).unwrap();
+        assert_eq!(resumed, film);
+        while !render_pass(&full, &mut resumed, None) {}
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(resumed.framebuffer(), render(&full));
+
+        // a pass past the deadline does nothing
+        let mut film = Film::for_scene(&full);
+        assert!(!render_pass(&full, &mut film, Some(Instant::now())));
+        assert_eq!(film.total_count(), 0);
+
+        let other = build(&SCENE.replace(r#""radius": 0.4"#, r#""radius": 0.3"#));
+        Film::for_scene(&other).save(&path).unwrap();
+        let err = Film::resume(&path, &full).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(format!("{:#}", err).contains("different scene"));
+
+        // stratified samples depend on the target, so only an interrupted
+        // render with the same one can be picked up
+        let stratified = |json: &str| {
+            return build(&json.replace(
+                r#""seed": 7"#,
+                r#""seed": 7, "sampler": { "type": "stratified" }"#,
+            ));
+        };
+        let full =
+            stratified(&SCENE.replace(r#""samples_per_pixel": 3"#, r#""samples_per_pixel": 7"#));
+        let mut film = Film::for_scene(&full);
+        assert!(!render_pass(&full, &mut film, None));
+        film.save(&path).unwrap();
+        let mut resumed = Film::resume(&path, &full).unwrap();
+        while !render_pass(&full, &mut resumed, None) {}
+        assert_eq!(resumed.framebuffer(), render(&full));
+        Film::for_scene(&stratified(SCENE)).save(&path).unwrap();
+        let err = Film::resume(&path, &full).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(format!("{:#}", err).contains("different scene"));
+    }
+
+    #[test]
+    fn test_adaptive_sampling() {
+        // the sky above the scene is noise free, the smoke and the metal aren't
+        let adaptive = SCENE
+            .replace(
+                r#""samples_per_pixel": 3"#,
+                r#""samples_per_pixel": 64,
+                    "adaptive": { "threshold": 0.02, "min_samples": 4 }"#,
+            )
+            .replace(r#""aperture": 0.2"#, r#""aperture": 0"#);
+        let scene = build(&adaptive);
+        let mut film = Film::for_scene(&scene);
+        while !render_pass(&scene, &mut film, None) {}
+        assert_eq!(film.get(8, 0).count, 4);
+        let max = film.pixels().iter().map(|p| p.count).max().unwrap();
+        assert_eq!(max, 64);
+        assert!(
+            film.total_count() < 16 * 16 * 64 / 2,
+            "{}",
+            film.total_count()
+        );
+        assert!(film.pixels().iter().all(|p| is_done(&scene.settings, p)));
+
+        // where pixels stop doesn't depend on the pass size
+        let scene =
+            build(&adaptive.replace(r#""samples_per_pass": 2"#, r#""samples_per_pass": 5"#));
+        let mut other = Film::for_scene(&scene);
+        while !render_pass(&scene, &mut other, None) {}
+        assert_eq!(other.pixels(), film.pixels());
+    }
+
+    #[test]
+    fn test_aovs() {
+        let scene = build(&SCENE.replace(
+            r#""tile_size": 5"#,
+            r#""tile_size": 5,
+                    "aovs": ["albedo", "normal", "depth", "object_id", "material_id"]"#,
+        ));
+        let mut film = Film::for_scene(&scene);
+        while !render_pass(&scene, &mut film, None) {}
+
+        // the ground in the bottom row, a huge sphere so nearly flat
+        let at = |aov, y| film.aov(aov).unwrap().get(8, y);
+        let normal = at(Aov::Normal, 15);
+        assert!(
+            (normal - vec3(0., 1., 0.)).magnitude() < 1e-2,
+            "{:?}",
+            normal
+        );
+        assert!((at(Aov::Albedo, 15) - vec3(0.5, 0.5, 0.5)).magnitude() < 1e-9);
+        assert!(at(Aov::Depth, 15).x > 1.);
+        assert_eq!(at(Aov::ObjectId, 15), vec3(1., 1., 1.));
+        // glass, ground, metal, smoke
+        assert_eq!(at(Aov::MaterialId, 15), vec3(2., 2., 2.));
+        // the sky in the top row
+        assert_eq!(at(Aov::Depth, 0), vec3(0., 0., 0.));
+        assert_eq!(at(Aov::ObjectId, 0), vec3(0., 0., 0.));
+        assert_eq!(at(Aov::MaterialId, 0), vec3(0., 0., 0.));
+
+        let path = std::env::temp_dir().join(format!("aovs-{}.checkpoint", std::process::id()));
+        film.save(&path).unwrap();
+        let loaded = Film::resume(&path, &scene).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, film);
+        assert!(Film::for_scene(&build(SCENE)).aov(Aov::Depth).is_none());
+
+        // smoke thick enough to always scatter has an albedo but no normal
+        let dense = build(&SCENE.replace(r#""density": 2"#, r#""density": 1000"#));
+        let r = Ray::new(Point3::new(0., 0.5, 3.), vec3(0., 0., -1.));
+        let mut sampler = IndependentSampler::new(0);
+        let mut aov = AovSample::default();
+        dense
+            .settings
+            .integrator()
+            .color(&r, &dense, &mut sampler, Some(&mut aov));
+        assert_eq!(aov.normal, vec3(0., 0., 0.));
+        assert_eq!(aov.albedo, vec3(0.9, 0.9, 0.9));
+        assert!((aov.depth - 1.6).abs() < 0.01, "{}", aov.depth);
+    }
+}
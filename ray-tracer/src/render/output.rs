@@ -0,0 +1,86 @@
+// Where renders and their checkpoints are written, and which frames of an
+// animation are already done.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+// an earlier run of the same scene left its samples here
+pub fn checkpoint_path(output: &str) -> String {
+    return format!("{}.checkpoint", output);
+}
+
+// an inclusive range like 1-120, or a single frame
+pub fn parse_frames(range: &str) -> Result<(u32, u32)> {
+    let (first, last) = range.split_once('-').unwrap_or((range, range));
+    let (Ok(first), Ok(last)) = (first.parse(), last.parse()) else {
+        bail!("invalid frame range `{}`", range);
+    };
+    if first > last {
+        bail!("frame range `{}` runs backwards", range);
+    }
+    return Ok((first, last));
+}
+
+// `path` numbered with `frame`, in place of a run of #s if it has one and
+// before the extension otherwise
+pub fn frame_path(path: &str, frame: u32) -> String {
+    if let Some(start) = path.find('#') {
+        let width = path[start..].chars().take_while(|&c| c == '#').count();
+        return format!(
+            "{}{:0width$}{}",
+            &path[..start],
+            frame,
+            &path[start + width..],
+            width = width
+        );
+    }
+    let path = Path::new(path);
+    let numbered = match path.extension() {
+        Some(extension) => {
+            path.with_extension(format!("{:04}.{}", frame, extension.to_string_lossy()))
+        }
+        None => path.with_extension(format!("{:04}", frame)),
+    };
+    return numbered.to_string_lossy().into_owned();
+}
+
+// An image with a checkpoint next to it was cut short and still has to be
+// picked up again.
+pub fn is_finished(output: &str) -> bool {
+    return Path::new(output).exists() && !Path::new(&checkpoint_path(output)).exists();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frame_paths() {
+        assert_eq!(frame_path("out/shot.####.png", 7), "out/shot.0007.png");
+        assert_eq!(frame_path("shot_##.exr", 123), "shot_123.exr");
+        assert_eq!(frame_path("turntable.png", 7), "turntable.0007.png");
+        assert_eq!(frame_path("frames/shot", 12), "frames/shot.0012");
+
+        assert_eq!(parse_frames("1-120").unwrap(), (1, 120));
+        assert_eq!(parse_frames("5").unwrap(), (5, 5));
+        let err = parse_frames("10-2").err().unwrap();
+        assert!(err.to_string().contains("runs backwards"));
+        assert!(parse_frames("1-x").is_err());
+        assert!(parse_frames("-3").is_err());
+    }
+
+    #[test]
+    fn test_finished_frames() {
+        let dir = std::env::temp_dir().join(format!("frames-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("shot.0001.png").to_string_lossy().into_owned();
+        assert!(!is_finished(&output));
+        std::fs::write(&output, b"").unwrap();
+        assert!(is_finished(&output));
+        // an interrupted frame is rendered again from its checkpoint
+        std::fs::write(checkpoint_path(&output), b"").unwrap();
+        assert!(!is_finished(&output));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
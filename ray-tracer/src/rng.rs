@@ -0,0 +1,117 @@
+use std::ops::Range;
+
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+
+// PCG32 (XSH RR). Small, fast and fully specified here, so a seed renders
+// the same image on every machine and with every version of our
+// dependencies.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        return Self::with_stream(seed, 0);
+    }
+
+    // generators with different streams are independent even for equal seeds
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        return rng;
+    }
+
+    // the generator for one sample of one pixel
+    pub fn for_sample(seed: u64, pixel: u64, sample: u64) -> Self {
+        return Self::with_stream(hash(&[seed, sample]), pixel);
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        return xorshifted.rotate_right((old >> 59) as u32);
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        return ((self.next_u32() as u64) << 32) | self.next_u32() as u64;
+    }
+
+    // uniform in [0, 1)
+    pub fn uniform(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 * (1. / (1u64 << 53) as f64);
+    }
+
+    pub fn range(&mut self, range: Range<f64>) -> f64 {
+        return range.start + (range.end - range.start) * self.uniform();
+    }
+
+    // uniform in 0..n
+    pub fn index(&mut self, n: usize) -> usize {
+        return ((self.uniform() * n as f64) as usize).min(n.saturating_sub(1));
+    }
+}
+
+// Mixes `values` into one well distributed number (splitmix64's finalizer).
+pub fn hash(values: &[u64]) -> u64 {
+    let mix = |mut z: u64| {
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    let mut h: u64 = 0x9e3779b97f4a7c15;
+    for value in values {
+        h = mix(h ^ mix(value.wrapping_add(0x9e3779b97f4a7c15)));
+    }
+    return h;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pcg_reference_output() {
+        // from the pcg32 demo program, seeded with (42, 54)
+        let mut rng = Rng::with_stream(42, 54);
+        let expected = [
+            0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e,
+        ];
+        for e in expected {
+            assert_eq!(rng.next_u32(), e);
+        }
+    }
+
+    #[test]
+    fn test_uniform() {
+        let mut rng = Rng::for_sample(1, 2, 3);
+        let n = 100_000;
+        let samples: Vec<f64> = (0..n).map(|_| rng.uniform()).collect();
+        assert!(samples.iter().all(|x| (0. ..1.).contains(x)));
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        assert!((mean - 0.5).abs() < 0.005, "{}", mean);
+
+        let mut counts = [0; 3];
+        for _ in 0..3000 {
+            counts[rng.index(3)] += 1;
+        }
+        assert!(
+            counts.iter().all(|&c| (900..1100).contains(&c)),
+            "{:?}",
+            counts
+        );
+
+        // neighbouring pixels and samples start out unrelated
+        let a = Rng::for_sample(0, 0, 0).next_u64();
+        let b = Rng::for_sample(0, 1, 0).next_u64();
+        let c = Rng::for_sample(0, 0, 1).next_u64();
+        assert!(a != b && a != c && b != c);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::rng::{hash, Rng};
+
+use super::{permute, Sampler};
+
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// The Halton sequence, dimension d being the radical inverse of the sample
+// index in the d-th prime. The digits are Owen scrambled with a different
+// seed per pixel and dimension, which keeps the stratification but stops
+// neighbouring pixels and the higher dimensions from sharing their patterns.
+// Dimensions past the prime table fall back to random numbers.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    index: u64,
+    dimension: usize,
+    rng: Rng,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        return Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+            rng: Rng::new(seed),
+        };
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        let Some(&base) = PRIMES.get(dimension) else {
+            return self.rng.uniform();
+        };
+        let seed = hash(&[self.seed, self.pixel, dimension as u64]);
+        return owen_scrambled_radical_inverse(self.index, base, seed);
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        return [self.get_1d(), self.get_1d()];
+    }
+}
+
+// The radical inverse, mirroring the digits of `index` in `base` around the
+// decimal point, with each digit permuted depending on the digits before it.
+// Digits keep being scrambled past the last non-zero one until they no
+// longer change the result, or until the mirrored digits would no longer
+// fit in a u64, which for the larger bases comes first.
+fn owen_scrambled_radical_inverse(mut index: u64, base: u64, seed: u64) -> f64 {
+    let inv_base = 1. / base as f64;
+    let max_digits = u64::MAX.ilog(base);
+    let mut reversed: u64 = 0;
+    let mut inv_base_n = 1.;
+    let mut digits = 0;
+    while digits < max_digits && 1. - (base - 1) as f64 * inv_base_n < 1. {
+        let digit = index % base;
+        let p = hash(&[seed, reversed]) as u32;
+        let digit = permute(digit as u32, base as u32, p) as u64;
+        reversed = reversed * base + digit;
+        inv_base_n *= inv_base;
+        index /= base;
+        digits += 1;
+    }
+    return (reversed as f64 * inv_base_n).min(1. - f64::EPSILON / 2.);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scrambled_radical_inverse_is_stratified() {
+        for (base, n) in [(2, 8), (3, 9), (5, 25)] {
+            for seed in 0..4 {
+                let mut cells = vec![0; n];
+                for i in 0..n {
+                    let x = owen_scrambled_radical_inverse(i as u64, base, seed);
+                    assert!((0. ..1.).contains(&x));
+                    cells[(x * n as f64) as usize] += 1;
+                }
+                assert_eq!(cells, vec![1; n], "base {}", base);
+            }
+        }
+        assert_ne!(
+            owen_scrambled_radical_inverse(1, 2, 0),
+            owen_scrambled_radical_inverse(1, 2, 1)
+        );
+    }
+
+    #[test]
+    fn test_every_prime_dimension() {
+        // the larger bases scramble more digits than a u64 holds unless the
+        // digit count is capped
+        let mut sampler = HaltonSampler::new(0);
+        for index in [0, 5, 1 << 20, u64::MAX] {
+            sampler.start_sample(3, index);
+            for dimension in 0..PRIMES.len() {
+                let x = sampler.get_1d();
+                assert!((0. ..1.).contains(&x), "dimension {}: {}", dimension, x);
+            }
+        }
+    }
+}
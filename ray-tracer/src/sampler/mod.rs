@@ -0,0 +1,153 @@
+mod halton;
+mod sobol;
+mod stratified;
+
+use crate::rng::Rng;
+
+pub use self::{halton::HaltonSampler, sobol::SobolSampler, stratified::StratifiedSampler};
+
+// A source of sample values in [0, 1). Every call hands out the next
+// dimension of the current sample, so the camera, the pixel filter and each
+// bounce draw from their own well distributed dimensions. Values only depend
+// on the seed, the pixel, the sample index and the dimension.
+pub trait Sampler {
+    // restarts at the first dimension of sample `index` of `pixel`
+    fn start_sample(&mut self, pixel: u64, index: u64);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> [f64; 2];
+}
+
+// Plain random numbers, one generator per sample.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        return Self {
+            seed,
+            rng: Rng::new(seed),
+        };
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        return self.rng.uniform();
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        return [self.rng.uniform(), self.rng.uniform()];
+    }
+}
+
+// A pseudo random permutation of 0..l picked by `p`, taken as is from
+// Kensler, "Correlated Multi-Jittered Sampling".
+fn permute(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    return ((i as u64 + p as u64) % l as u64) as u32;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // mean squared error over `pixels` pixels when estimating the integral of
+    // a smooth function over the unit square with `n` samples each
+    fn error(sampler: &mut dyn Sampler, pixels: u64, n: u64) -> f64 {
+        let f = |[x, y]: [f64; 2]| (x * y).sqrt() + x * x;
+        let expected = 4. / 9. + 1. / 3.;
+        let mut total = 0.;
+        for pixel in 0..pixels {
+            let mut sum = 0.;
+            for index in 0..n {
+                sampler.start_sample(pixel, index);
+                // skip the first dimensions, the path tracer mostly lives deeper
+                sampler.get_1d();
+                sampler.get_2d();
+                let u = sampler.get_2d();
+                assert!(u.iter().all(|x| (0. ..1.).contains(x)), "{:?}", u);
+                sum += f(u);
+            }
+            total += (sum / n as f64 - expected).powi(2);
+        }
+        return total / pixels as f64;
+    }
+
+    #[test]
+    fn test_samplers_beat_independent() {
+        let (pixels, n) = (200, 64);
+        let independent = error(&mut IndependentSampler::new(3), pixels, n);
+        let samplers: [(&str, Box<dyn Sampler>); 3] = [
+            ("stratified", Box::new(StratifiedSampler::new(3, n as u32))),
+            ("halton", Box::new(HaltonSampler::new(3))),
+            ("sobol", Box::new(SobolSampler::new(3))),
+        ];
+        for (name, mut sampler) in samplers {
+            let err = error(sampler.as_mut(), pixels, n);
+            assert!(
+                err < independent / 8.,
+                "{}: {} vs independent {}",
+                name,
+                err,
+                independent
+            );
+        }
+    }
+
+    #[test]
+    fn test_samplers_are_deterministic() {
+        let samplers = || -> [Box<dyn Sampler>; 4] {
+            [
+                Box::new(IndependentSampler::new(5)),
+                Box::new(StratifiedSampler::new(5, 16)),
+                Box::new(HaltonSampler::new(5)),
+                Box::new(SobolSampler::new(5)),
+            ]
+        };
+        for (mut a, mut b) in samplers().into_iter().zip(samplers()) {
+            // b visits the samples out of order
+            let values = |s: &mut dyn Sampler, pixel, index| {
+                s.start_sample(pixel, index);
+                (0..10).map(|_| s.get_1d()).collect::<Vec<_>>()
+            };
+            let first = values(a.as_mut(), 7, 3);
+            values(b.as_mut(), 2, 9);
+            assert_eq!(first, values(b.as_mut(), 7, 3));
+            assert_ne!(first, values(b.as_mut(), 8, 3));
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::rng::hash;
+
+use super::Sampler;
+
+// Owen-scrambled Sobol points, following Burley, "Practical Hash-based Owen
+// Scrambling". Every 2D dimension pair uses the first two Sobol dimensions,
+// which form a (0, 2)-sequence, with its own nested uniform scrambling of
+// both the points and the sample order. This keeps each pair stratified at
+// every power of two while decorrelating pairs and pixels.
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        return Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        };
+    }
+
+    fn next_point(&mut self) -> [u32; 2] {
+        let seed = hash(&[self.seed, self.pixel, self.dimension]);
+        self.dimension += 1;
+        let index = nested_uniform_scramble(self.index, seed as u32);
+        return [
+            nested_uniform_scramble(sobol(index, 0), (seed >> 32) as u32),
+            nested_uniform_scramble(sobol(index, 1), hash(&[seed]) as u32),
+        ];
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        return to_unit(self.next_point()[0]);
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let [x, y] = self.next_point();
+        return [to_unit(x), to_unit(y)];
+    }
+}
+
+fn to_unit(x: u32) -> f64 {
+    return x as f64 / (1u64 << 32) as f64;
+}
+
+// The first Sobol dimension is the van der Corput sequence and the second
+// uses the Pascal matrix mod 2, whose columns follow v ^= v >> 1.
+fn sobol(index: u32, dimension: usize) -> u32 {
+    let mut x = 0;
+    let mut v: u32 = 1 << 31;
+    for bit in 0..32 {
+        if (index >> bit) & 1 == 1 {
+            x ^= if dimension == 0 { 1 << (31 - bit) } else { v };
+        }
+        v ^= v >> 1;
+    }
+    return x;
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    return x;
+}
+
+// each bit is flipped depending only on the bits above it
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    return laine_karras_permutation(x.reverse_bits(), seed).reverse_bits();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sobol_reference_points() {
+        let points: Vec<[u32; 2]> = (0..4).map(|i| [sobol(i, 0), sobol(i, 1)]).collect();
+        let half = 1 << 31;
+        let quarter = 1 << 30;
+        assert_eq!(
+            points,
+            [
+                [0, 0],
+                [half, half],
+                [quarter, half + quarter],
+                [half + quarter, quarter]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scrambled_points_stay_stratified() {
+        let mut sampler = SobolSampler::new(9);
+        for pixel in 0..4 {
+            for dimension in 0..4 {
+                // any 16 consecutive samples put one point in each 4x4 cell
+                // and in each 1/16 wide column
+                let mut cells = [0; 16];
+                let mut columns = [0; 16];
+                for index in 0..16 {
+                    sampler.start_sample(pixel, index);
+                    for _ in 0..dimension {
+                        sampler.get_2d();
+                    }
+                    let [x, y] = sampler.get_2d();
+                    cells[(x * 4.) as usize + 4 * (y * 4.) as usize] += 1;
+                    columns[(x * 16.) as usize] += 1;
+                }
+                assert_eq!(cells, [1; 16]);
+                assert_eq!(columns, [1; 16]);
+            }
+        }
+    }
+}
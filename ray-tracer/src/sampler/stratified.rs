@@ -0,0 +1,88 @@
+use crate::rng::{hash, Rng};
+
+use super::{permute, Sampler};
+
+// Jittered sampling: the unit interval (or square) is split into one stratum
+// per sample and each sample lands somewhere random in its own stratum. Each
+// dimension visits the strata in its own shuffled order so that dimensions
+// stay uncorrelated, as in correlated multi-jittered sampling.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: u32,
+    pixel: u64,
+    index: u64,
+    dimension: u64,
+    rng: Rng,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> Self {
+        return Self {
+            seed,
+            samples_per_pixel: samples_per_pixel.max(1),
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+            rng: Rng::new(seed),
+        };
+    }
+
+    // the stratum out of `count` that the current sample uses in the next dimension
+    fn stratum(&mut self, count: u32) -> u32 {
+        let p = hash(&[self.seed, self.pixel, self.dimension]) as u32;
+        self.dimension += 1;
+        let index = (self.index % self.samples_per_pixel as u64) as u32;
+        return permute(index, count, p);
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let n = self.samples_per_pixel;
+        let stratum = self.stratum(n);
+        return (stratum as f64 + self.rng.uniform()) / n as f64;
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        // the smallest grid with at least one cell per sample
+        let nx = (self.samples_per_pixel as f64).sqrt().ceil() as u32;
+        let ny = self.samples_per_pixel.div_ceil(nx);
+        let stratum = self.stratum(nx * ny);
+        return [
+            ((stratum % nx) as f64 + self.rng.uniform()) / nx as f64,
+            ((stratum / nx) as f64 + self.rng.uniform()) / ny as f64,
+        ];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_one_sample_per_stratum() {
+        for l in [1, 5, 16, 100] {
+            let mut seen: Vec<u32> = (0..l).map(|i| permute(i, l, 12345)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..l).collect::<Vec<_>>());
+        }
+
+        // 9 samples on a 3x3 grid hit every cell once
+        let mut sampler = StratifiedSampler::new(1, 9);
+        let mut cells = [0; 9];
+        for index in 0..9 {
+            sampler.start_sample(4, index);
+            sampler.get_1d();
+            let [x, y] = sampler.get_2d();
+            cells[(x * 3.) as usize + 3 * (y * 3.) as usize] += 1;
+        }
+        assert_eq!(cells, [1; 9]);
+    }
+}
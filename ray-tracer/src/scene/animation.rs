@@ -0,0 +1,196 @@
+// Keyframed cameras and objects, and how they look at a given frame. Frames
+// are the time unit of an animation, so a shutter of [0, 0.5] blurs objects
+// over half of each frame.
+
+use anyhow::{bail, Result};
+
+use super::description::{CameraDescription, Interpolation, PoseDescription, PoseKeyframe};
+
+fn check_frames(frames: impl Iterator<Item = f64>) -> Result<()> {
+    let mut previous = f64::NEG_INFINITY;
+    for (i, frame) in frames.enumerate() {
+        if !frame.is_finite() {
+            bail!("keyframes[{}]: frame must be finite, got {}", i, frame);
+        }
+        if frame <= previous {
+            bail!(
+                "keyframes[{}]: frame {} must come after the previous keyframe's {}",
+                i,
+                frame,
+                previous
+            );
+        }
+        previous = frame;
+    }
+    if previous == f64::NEG_INFINITY {
+        bail!("keyframes must not be empty");
+    }
+    return Ok(());
+}
+
+// The value at `frame` of a curve through `keys`, which are sorted by frame.
+// It holds still before the first key and after the last.
+pub fn interpolate<const N: usize>(
+    keys: &[(f64, [f64; N])],
+    frame: f64,
+    interpolation: Interpolation,
+) -> [f64; N] {
+    let last = keys.len() - 1;
+    if frame <= keys[0].0 {
+        return keys[0].1;
+    }
+    if frame >= keys[last].0 {
+        return keys[last].1;
+    }
+    let i = keys.partition_point(|(f, _)| *f <= frame);
+    let (f0, p0) = keys[i - 1];
+    let (f1, p1) = keys[i];
+    let dt = f1 - f0;
+    let s = (frame - f0) / dt;
+    match interpolation {
+        Interpolation::Linear => {
+            return std::array::from_fn(|k| p0[k] + s * (p1[k] - p0[k]));
+        }
+        Interpolation::CatmullRom => {
+            // the slope through a key's neighbours, one sided at the ends, so
+            // unevenly spaced keys don't overshoot
+            let slope = |j: usize| -> [f64; N] {
+                let (fa, pa) = keys[j.saturating_sub(1)];
+                let (fb, pb) = keys[(j + 1).min(last)];
+                return std::array::from_fn(|k| (pb[k] - pa[k]) / (fb - fa));
+            };
+            let (m0, m1) = (slope(i - 1), slope(i));
+            // cubic hermite basis
+            let s2 = s * s;
+            let s3 = s2 * s;
+            let h00 = 2. * s3 - 3. * s2 + 1.;
+            let h10 = s3 - 2. * s2 + s;
+            let h01 = -2. * s3 + 3. * s2;
+            let h11 = s3 - s2;
+            return std::array::from_fn(|k| {
+                h00 * p0[k] + h10 * dt * m0[k] + h01 * p1[k] + h11 * dt * m1[k]
+            });
+        }
+    }
+}
+
+// A curve through the keyframes that have a value, None if none do.
+fn track<K, const N: usize>(
+    keyframes: &[K],
+    frame: f64,
+    interpolation: Interpolation,
+    key: impl Fn(&K) -> Option<(f64, [f64; N])>,
+) -> Option<[f64; N]> {
+    let keys: Vec<_> = keyframes.iter().filter_map(key).collect();
+    if keys.is_empty() {
+        return None;
+    }
+    return Some(interpolate(&keys, frame, interpolation));
+}
+
+impl CameraDescription {
+    // The still camera at `frame`.
+    pub fn at_frame(&self, frame: f64) -> Result<CameraDescription> {
+        let mut camera = self.clone();
+        let Some(animation) = camera.animation.take() else {
+            return Ok(camera);
+        };
+        let keyframes = &animation.keyframes;
+        check_frames(keyframes.iter().map(|k| k.frame))?;
+        let interpolation = animation.interpolation;
+        if let Some(lookfrom) = track(keyframes, frame, interpolation, |k| {
+            Some((k.frame, k.lookfrom?))
+        }) {
+            camera.lookfrom = lookfrom;
+        }
+        if let Some(lookat) = track(keyframes, frame, interpolation, |k| {
+            Some((k.frame, k.lookat?))
+        }) {
+            camera.lookat = lookat;
+        }
+        if let Some([vfov]) = track(keyframes, frame, interpolation, |k| {
+            Some((k.frame, [k.vfov?]))
+        }) {
+            camera.vfov = vfov;
+        }
+        if let Some([aperture]) = track(keyframes, frame, interpolation, |k| {
+            Some((k.frame, [k.aperture?]))
+        }) {
+            camera.aperture = aperture;
+        }
+        if let Some([focus_dist]) = track(keyframes, frame, interpolation, |k| {
+            Some((k.frame, [k.focus_dist?]))
+        }) {
+            camera.focus_dist = Some(focus_dist);
+        }
+        return Ok(camera);
+    }
+}
+
+// The pose of an animated object at `frame`.
+pub fn pose_at(
+    keyframes: &[PoseKeyframe],
+    interpolation: Interpolation,
+    frame: f64,
+) -> Result<PoseDescription> {
+    check_frames(keyframes.iter().map(|k| k.frame))?;
+    let mut pose = PoseDescription::default();
+    if let Some(translate) = track(keyframes, frame, interpolation, |k| {
+        Some((k.frame, k.translate?))
+    }) {
+        pose.translate = translate;
+    }
+    if let Some(axis) = track(keyframes, frame, interpolation, |k| {
+        Some((k.frame, k.axis?))
+    }) {
+        pose.axis = axis;
+    }
+    if let Some([angle]) = track(keyframes, frame, interpolation, |k| {
+        Some((k.frame, [k.angle?]))
+    }) {
+        pose.angle = angle;
+    }
+    if let Some(scale) = track(keyframes, frame, interpolation, |k| {
+        Some((k.frame, k.scale?))
+    }) {
+        pose.scale = scale;
+    }
+    return Ok(pose);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_interpolate() {
+        let keys = [(0., [0.]), (10., [10.]), (20., [40.])];
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom] {
+            // through every key, holding still past the ends
+            for (frame, [value]) in keys {
+                assert_eq!(interpolate(&keys, frame, interpolation), [value]);
+            }
+            assert_eq!(interpolate(&keys, -5., interpolation), [0.]);
+            assert_eq!(interpolate(&keys, 25., interpolation), [40.]);
+        }
+        assert_eq!(interpolate(&keys, 15., Interpolation::Linear), [25.]);
+
+        // catmull-rom doesn't change direction at a key
+        let at = |frame: f64| interpolate(&keys, frame, Interpolation::CatmullRom)[0];
+        let h = 1e-6;
+        let before = (at(10.) - at(10. - h)) / h;
+        let after = (at(10. + h) - at(10.)) / h;
+        assert!((before - after).abs() < 1e-3, "{} {}", before, after);
+        // and keeps to a straight line through evenly spaced keys
+        let line = [(0., [0., 1.]), (10., [10., 1.]), (20., [20., 1.])];
+        let value = interpolate(&line, 5., Interpolation::CatmullRom);
+        assert!(
+            (value[0] - 5.).abs() < 1e-12 && value[1] == 1.,
+            "{:?}",
+            value
+        );
+
+        let err = pose_at(&[], Interpolation::Linear, 0.).err().unwrap();
+        assert!(err.to_string().contains("must not be empty"));
+    }
+}
@@ -0,0 +1,767 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    image::ImageFormat,
+    integrator::MisHeuristic,
+    ray::{csg::CsgOperation, quad::Axis},
+    render::Aov,
+    texture::{Filter, NoiseStyle, WrapMode},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Accelerator {
+    None,
+    Midpoint,
+    #[default]
+    Sah,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum IntegratorDescription {
+    // pure path tracing cut off at max_depth
+    Naive,
+    // light sampling with multiple importance sampling and russian roulette
+    Path {
+        #[serde(default)]
+        heuristic: MisHeuristic,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SamplerDescription {
+    Independent,
+    // jittered, one stratum per sample
+    Stratified,
+    Halton,
+    // Owen-scrambled Sobol, usually the least noisy
+    #[default]
+    Sobol,
+}
+
+impl Default for IntegratorDescription {
+    fn default() -> Self {
+        IntegratorDescription::Path {
+            heuristic: MisHeuristic::Power,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub aspect_ratio: f64,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    pub output: String,
+    // picked from the output extension when not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<ImageFormat>,
+    pub accelerator: Accelerator,
+    pub integrator: IntegratorDescription,
+    pub sampler: SamplerDescription,
+    // traces a wavelength per camera ray, so that dispersive glass splits
+    // white light into colours
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub spectral: bool,
+    // the same seed always renders the same image
+    pub seed: u64,
+    // the image is rendered in square tiles, adding up to samples_per_pass
+    // samples to each pixel per pass over the tiles
+    pub tile_size: u32,
+    pub samples_per_pass: u32,
+    // in seconds, the render stops early once it is used up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_limit: Option<f64>,
+    // in seconds, how often the accumulated samples are saved so that an
+    // interrupted render can be resumed
+    pub checkpoint_interval: f64,
+    // samples_per_pixel becomes the most a pixel can get
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adaptive: Option<AdaptiveSettings>,
+    // extra buffers saved as <output name>.<aov>.exr
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aovs: Vec<Aov>,
+    // filters the noise out of the image before it is saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denoise: Option<DenoiseSettings>,
+}
+
+fn default_min_samples() -> u32 {
+    16
+}
+
+// Stops sampling pixels once they look converged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveSettings {
+    // the noise level to stop at, see render::is_done
+    pub threshold: f64,
+    #[serde(default = "default_min_samples")]
+    pub min_samples: u32,
+    // where to save an image of how many samples each pixel took
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heatmap: Option<String>,
+}
+
+fn default_iterations() -> u32 {
+    4
+}
+
+fn default_sigma_color() -> f64 {
+    4.
+}
+
+fn default_sigma_normal() -> f64 {
+    64.
+}
+
+fn default_sigma_depth() -> f64 {
+    0.02
+}
+
+// See render::denoise. Larger sigmas blur more across edges.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DenoiseSettings {
+    // each one doubles the filter's reach, 4 covers about 30 pixels
+    #[serde(default = "default_iterations")]
+    pub iterations: u32,
+    // in multiples of the pixel's noise
+    #[serde(default = "default_sigma_color")]
+    pub sigma_color: f64,
+    // the exponent on the cosine between normals, larger is stricter
+    #[serde(default = "default_sigma_normal")]
+    pub sigma_normal: f64,
+    // relative depth difference per pixel apart
+    #[serde(default = "default_sigma_depth")]
+    pub sigma_depth: f64,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        Self {
+            iterations: default_iterations(),
+            sigma_color: default_sigma_color(),
+            sigma_normal: default_sigma_normal(),
+            sigma_depth: default_sigma_depth(),
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            image_width: 1200,
+            aspect_ratio: 3. / 2.,
+            samples_per_pixel: 500,
+            max_depth: 50,
+            output: "image.ppm".to_string(),
+            output_format: None,
+            accelerator: Accelerator::Sah,
+            integrator: IntegratorDescription::default(),
+            sampler: SamplerDescription::default(),
+            spectral: false,
+            seed: 0,
+            tile_size: 32,
+            samples_per_pass: 16,
+            time_limit: None,
+            checkpoint_interval: 60.,
+            adaptive: None,
+            aovs: Vec::new(),
+            denoise: None,
+        }
+    }
+}
+
+fn default_vup() -> [f64; 3] {
+    [0., 1., 0.]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    pub lookfrom: [f64; 3],
+    pub lookat: [f64; 3],
+    #[serde(default = "default_vup")]
+    pub vup: [f64; 3],
+    #[serde(default)]
+    pub projection: Projection,
+    // vertical field of view in degrees
+    pub vfov: f64,
+    #[serde(default)]
+    pub aperture: f64,
+    #[serde(default)]
+    pub aperture_shape: ApertureDescription,
+    // how far towards the corners the lens gets cut off into a cat's eye
+    #[serde(default)]
+    pub cats_eye: f64,
+    // defaults to the distance between lookfrom and lookat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_dist: Option<f64>,
+    // degrees the focus plane is turned around the horizontal and vertical
+    #[serde(default)]
+    pub tilt: f64,
+    #[serde(default)]
+    pub swing: f64,
+    // moves the image by fractions of its width and height
+    #[serde(default)]
+    pub shift: [f64; 2],
+    // [open, close] times for motion blur, an instant at time 0 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shutter: Option<[f64; 2]>,
+    // keyframes that move the camera when rendering an animation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<CameraAnimation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // a smooth curve through every keyframe
+    CatmullRom,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraAnimation {
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+// Each setting is interpolated between the keyframes that have it, and is
+// the camera's own where none do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraKeyframe {
+    pub frame: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lookfrom: Option<[f64; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lookat: Option<[f64; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vfov: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aperture: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_dist: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    // `height` world units from the bottom of the image to the top, by
+    // default what vfov covers at the focus distance
+    Orthographic {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        height: Option<f64>,
+    },
+    // equidistant, `fov` degrees across the largest circle in the image
+    Fisheye {
+        #[serde(default = "default_fisheye_fov")]
+        fov: f64,
+    },
+    // all the way around, for 2:1 images
+    Equirectangular,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ApertureDescription {
+    #[default]
+    Circle,
+    Polygon {
+        blades: u32,
+        // degrees
+        #[serde(default)]
+        rotation: f64,
+    },
+    // relative to the scene file
+    Image {
+        path: String,
+    },
+}
+
+fn default_fisheye_fov() -> f64 {
+    180.
+}
+
+fn default_depth() -> u32 {
+    7
+}
+
+fn half() -> f64 {
+    0.5
+}
+
+fn one() -> f64 {
+    1.
+}
+
+fn white() -> [f64; 3] {
+    [1., 1., 1.]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDescription {
+    Solid {
+        color: [f64; 3],
+    },
+    // 3D cubes of side `scale` alternating between the two colours
+    Checker {
+        scale: f64,
+        even: [f64; 3],
+        odd: [f64; 3],
+    },
+    // an 8/16 bit (sRGB) or float (linear) image, relative to the scene file
+    Image {
+        path: String,
+        #[serde(default)]
+        wrap: WrapMode,
+        #[serde(default)]
+        filter: Filter,
+    },
+    Noise {
+        scale: f64,
+        #[serde(default)]
+        style: NoiseStyle,
+        // octaves of turbulence
+        #[serde(default = "default_depth")]
+        depth: u32,
+        #[serde(default = "white")]
+        color: [f64; 3],
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+// Either a constant colour or the name of an entry in `textures`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextureOrColor {
+    Color([f64; 3]),
+    Texture(String),
+}
+
+// Either a plain index of refraction or one that depends on the wavelength,
+// like { "cauchy": { "a": 1.5046, "b": 0.0042 } }.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IorDescription {
+    Constant(f64),
+    Dispersive(DispersionDescription),
+}
+
+// Both with wavelengths in micrometres.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum DispersionDescription {
+    // n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ b λ² / (λ² - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription {
+    Lambertian {
+        albedo: TextureOrColor,
+    },
+    Metal {
+        albedo: TextureOrColor,
+        fuzz: f64,
+    },
+    Dielectric {
+        ir: IorDescription,
+    },
+    DiffuseLight {
+        emit: [f64; 3],
+    },
+    // scatters evenly in all directions, for use inside a constant_medium
+    Isotropic {
+        albedo: TextureOrColor,
+    },
+    // a metal from its complex index of refraction eta + ik per channel,
+    // a mirror at roughness 0
+    Conductor {
+        eta: [f64; 3],
+        k: [f64; 3],
+        #[serde(default)]
+        roughness: f64,
+    },
+    // frosted glass
+    RoughDielectric {
+        ir: f64,
+        roughness: f64,
+    },
+    // Disney's principled BSDF, all parameters from 0 to 1
+    Principled {
+        base_color: TextureOrColor,
+        #[serde(default)]
+        metallic: f64,
+        #[serde(default = "half")]
+        roughness: f64,
+        #[serde(default = "half")]
+        specular: f64,
+        #[serde(default)]
+        clearcoat: f64,
+        #[serde(default = "one")]
+        clearcoat_gloss: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BackgroundDescription {
+    None,
+    Constant {
+        color: [f64; 3],
+    },
+    Gradient {
+        horizon: [f64; 3],
+        zenith: [f64; 3],
+    },
+    // an equirectangular image relative to the scene file, normally a .hdr,
+    // lighting the scene; turned counter clockwise around +y by `rotation`
+    // degrees and scaled by `intensity`
+    Environment {
+        path: String,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+}
+
+impl Default for BackgroundDescription {
+    fn default() -> Self {
+        BackgroundDescription::Gradient {
+            horizon: [1., 1., 1.],
+            zenith: [0.5, 0.7, 1.],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformDescription {
+    Translate { offset: [f64; 3] },
+    // counter clockwise around `axis`, in degrees
+    Rotate { axis: [f64; 3], angle: f64 },
+    Scale { factor: [f64; 3] },
+    // an affine matrix, row by row
+    Matrix { rows: [[f64; 4]; 4] },
+}
+
+fn default_axis() -> [f64; 3] {
+    [0., 1., 0.]
+}
+
+fn default_scale() -> [f64; 3] {
+    [1., 1., 1.]
+}
+
+fn default_time1() -> f64 {
+    1.
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// Scaled, then rotated by `angle` degrees around `axis`, then translated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoseDescription {
+    #[serde(default)]
+    pub translate: [f64; 3],
+    #[serde(default = "default_axis")]
+    pub axis: [f64; 3],
+    #[serde(default)]
+    pub angle: f64,
+    #[serde(default = "default_scale")]
+    pub scale: [f64; 3],
+}
+
+impl Default for PoseDescription {
+    fn default() -> Self {
+        Self {
+            translate: [0., 0., 0.],
+            axis: default_axis(),
+            angle: 0.,
+            scale: default_scale(),
+        }
+    }
+}
+
+// Like CameraKeyframe, parts of the pose missing from every keyframe keep
+// their defaults. Angles aren't wrapped, so 0 to 720 is two full turns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoseKeyframe {
+    pub frame: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translate: Option<[f64; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub axis: Option<[f64; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub angle: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<[f64; 3]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectDescription {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+    },
+    // moves from center0 at time0 to center1 at time1
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "default_time1")]
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    // the parallelogram with corners q, q + u, q + v and q + u + v
+    Quad {
+        q: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    // the rectangle facing +axis at `offset` along it, from min to max in the
+    // two axes after it (y and z for x, z and x for y, x and y for z)
+    AxisAlignedQuad {
+        axis: Axis,
+        min: [f64; 2],
+        max: [f64; 2],
+        offset: f64,
+        material: String,
+    },
+    // the axis aligned box between two opposite corners
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+    },
+    // the endless plane through `point`, kept out of the accelerator
+    Plane {
+        point: [f64; 3],
+        normal: [f64; 3],
+        material: String,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    // runs from the centre of its base to the centre of its top
+    Cylinder {
+        base: [f64; 3],
+        top: [f64; 3],
+        radius: f64,
+        #[serde(default = "default_true")]
+        capped: bool,
+        material: String,
+    },
+    // `radius` at the base narrowing to a point at the apex
+    Cone {
+        base: [f64; 3],
+        apex: [f64; 3],
+        radius: f64,
+        #[serde(default = "default_true")]
+        capped: bool,
+        material: String,
+    },
+    // a ring of tube radius `minor_radius` around a circle of `major_radius`
+    // in the plane facing `normal`
+    Torus {
+        center: [f64; 3],
+        #[serde(default = "default_axis")]
+        normal: [f64; 3],
+        major_radius: f64,
+        minor_radius: f64,
+        material: String,
+    },
+    // the union, intersection or difference of two closed solids
+    Csg {
+        operation: CsgOperation,
+        left: Box<ObjectDescription>,
+        right: Box<ObjectDescription>,
+    },
+    // the surface of a signed distance field, found by ray marching
+    Sdf {
+        sdf: SdfDescription,
+        material: String,
+    },
+    // an OBJ file, relative to the scene file, using the materials from its MTL
+    Mesh {
+        path: String,
+    },
+    // a volume filling `boundary`, which has to be closed and convex,
+    // scattering off `material` (normally isotropic)
+    ConstantMedium {
+        boundary: Box<ObjectDescription>,
+        density: f64,
+        material: String,
+    },
+    // `object` moved by `transforms`, applied in order
+    Transform {
+        transforms: Vec<TransformDescription>,
+        object: Box<ObjectDescription>,
+    },
+    // `object` moving from the `start` pose at time0 to `end` at time1
+    Motion {
+        start: PoseDescription,
+        end: PoseDescription,
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "default_time1")]
+        time1: f64,
+        object: Box<ObjectDescription>,
+    },
+    // `object` posed by keyframes, blurred over the camera's shutter within
+    // each frame
+    Animated {
+        #[serde(default)]
+        interpolation: Interpolation,
+        keyframes: Vec<PoseKeyframe>,
+        object: Box<ObjectDescription>,
+    },
+    // a copy of an entry in `prototypes` moved by `transforms`, all copies
+    // share the one prototype in memory
+    Instance {
+        prototype: String,
+        #[serde(default)]
+        transforms: Vec<TransformDescription>,
+    },
+}
+
+// A signed distance field built from shapes centred on the origin and
+// operations combining and moving them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SdfDescription {
+    Sphere {
+        radius: f64,
+    },
+    // with edges rounded off by `rounding`
+    Box {
+        half_size: [f64; 3],
+        #[serde(default)]
+        rounding: f64,
+    },
+    // around the y axis
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    // the points within `radius` of the segment from a to b
+    Capsule {
+        a: [f64; 3],
+        b: [f64; 3],
+        radius: f64,
+    },
+    Mandelbulb {
+        #[serde(default = "default_power")]
+        power: f64,
+        #[serde(default = "default_fractal_iterations")]
+        iterations: u32,
+    },
+    Translate {
+        offset: [f64; 3],
+        node: Box<SdfDescription>,
+    },
+    Scale {
+        factor: f64,
+        node: Box<SdfDescription>,
+    },
+    // `smoothness` blends the nodes together over about that distance
+    Union {
+        nodes: Vec<SdfDescription>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    Intersection {
+        nodes: Vec<SdfDescription>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    Difference {
+        base: Box<SdfDescription>,
+        cut: Box<SdfDescription>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    // 2 count + 1 copies along each axis, `spacing` apart, 0 for none
+    Repeat {
+        spacing: [f64; 3],
+        count: [u32; 3],
+        node: Box<SdfDescription>,
+    },
+    // turned around the y axis by `rate` radians per unit up it
+    Twist {
+        rate: f64,
+        node: Box<SdfDescription>,
+    },
+}
+
+fn default_power() -> f64 {
+    8.
+}
+
+fn default_fractal_iterations() -> u32 {
+    12
+}
+
+// A homogeneous medium filling the whole scene.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FogDescription {
+    pub density: f64,
+    pub albedo: TextureOrColor,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub render: RenderSettings,
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub background: BackgroundDescription,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fog: Option<FogDescription>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub textures: BTreeMap<String, TextureDescription>,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    // objects that are only built once and placed with `instance` objects
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prototypes: BTreeMap<String, ObjectDescription>,
+    pub objects: Vec<ObjectDescription>,
+}
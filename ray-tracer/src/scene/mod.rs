@@ -0,0 +1,1430 @@
+mod animation;
+pub mod description;
+
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{
+    vec3, Deg, InnerSpace, Matrix, Matrix4, MetricSpace, Point3, Quaternion, Rotation3,
+    SquareMatrix, Vector3,
+};
+
+use crate::{
+    background::Background,
+    camera::{
+        Aperture, Camera, EquirectangularCamera, FisheyeCamera, OrthographicCamera,
+        PerspectiveCamera,
+    },
+    environment::{EnvironmentLight, EnvironmentMap},
+    image,
+    integrator::{Integrator, NaiveIntegrator, PathIntegrator},
+    material::{
+        Conductor, Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal, Principled,
+        RoughDielectric, TaggedMaterial,
+    },
+    obj::load_obj,
+    ray::{
+        bvh::{BvhNode, SplitHeuristic},
+        csg::Csg,
+        hittable::{Hittable, HittableList, MovingSphere, Sphere, Tagged},
+        medium::ConstantMedium,
+        quad::{Cuboid, Quad},
+        sdf::{SdfNode, SdfObject},
+        shapes::{Cone, Cylinder, Disk, Plane, Torus},
+        transform::{Instance, MotionTransform, Pose, Transform},
+        triangle::Triangle,
+    },
+    render::{luminance, Aov},
+    rng::hash,
+    sampler::{HaltonSampler, IndependentSampler, Sampler, SobolSampler, StratifiedSampler},
+    spectrum::{Ior, LAMBDA_MAX, LAMBDA_MIN},
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture},
+};
+
+use self::animation::pose_at;
+use self::description::{
+    Accelerator, ApertureDescription, BackgroundDescription, CameraDescription,
+    DispersionDescription, IntegratorDescription, IorDescription, MaterialDescription,
+    ObjectDescription, PoseDescription, Projection, RenderSettings, SamplerDescription,
+    SceneDescription, SdfDescription, TextureDescription, TextureOrColor, TransformDescription,
+};
+
+// the scene's textures by name, built once and shared between materials
+type Textures = BTreeMap<String, Arc<dyn Texture>>;
+
+pub struct Scene {
+    pub world: Box<dyn Hittable>,
+    // copies of the emissive objects in `world`, sampled directly by the path integrator
+    pub lights: HittableList,
+    pub camera: Box<dyn Camera>,
+    pub background: Background,
+    pub settings: RenderSettings,
+    // a hash of everything that changes what a sample looks like, see
+    // SceneDescription::fingerprint
+    pub fingerprint: u64,
+}
+
+fn point(p: [f64; 3]) -> Point3<f64> {
+    Point3::new(p[0], p[1], p[2])
+}
+
+fn vector(v: [f64; 3]) -> Vector3<f64> {
+    vec3(v[0], v[1], v[2])
+}
+
+// a normal or axis, which has to point somewhere
+fn direction(v: [f64; 3]) -> Result<Vector3<f64>> {
+    let v = vector(v);
+    if v.magnitude2() == 0. {
+        bail!("must not be zero");
+    }
+    return Ok(v.normalize());
+}
+
+fn positive(name: &str, value: f64) -> Result<f64> {
+    if value <= 0. {
+        bail!("{} must be positive, got {}", name, value);
+    }
+    return Ok(value);
+}
+
+impl TextureDescription {
+    // `base_dir` is where relative image paths are looked up
+    fn build(&self, base_dir: &Path) -> Result<Arc<dyn Texture>> {
+        match self {
+            TextureDescription::Solid { color } => Ok(SolidColor::new(vector(*color))),
+            TextureDescription::Checker { scale, even, odd } => {
+                if *scale <= 0. {
+                    bail!("scale must be positive, got {}", scale);
+                }
+                Ok(CheckerTexture::new(
+                    *scale,
+                    SolidColor::new(vector(*even)),
+                    SolidColor::new(vector(*odd)),
+                ))
+            }
+            TextureDescription::Image { path, wrap, filter } => {
+                Ok(ImageTexture::load(base_dir.join(path), *wrap, *filter)?)
+            }
+            TextureDescription::Noise {
+                scale,
+                style,
+                depth,
+                color,
+                seed,
+            } => Ok(NoiseTexture::new(
+                *seed,
+                *scale,
+                *style,
+                *depth,
+                vector(*color),
+            )),
+        }
+    }
+}
+
+impl TextureOrColor {
+    fn build(&self, textures: &Textures) -> Result<Arc<dyn Texture>> {
+        match self {
+            TextureOrColor::Color(color) => Ok(SolidColor::new(vector(*color))),
+            TextureOrColor::Texture(name) => match textures.get(name) {
+                Some(texture) => Ok(texture.clone()),
+                None => bail!("unknown texture `{}`", name),
+            },
+        }
+    }
+}
+
+fn unit_interval(name: &str, value: f64) -> Result<()> {
+    if !(0. ..=1.).contains(&value) {
+        bail!("{} must be between 0 and 1, got {}", name, value);
+    }
+    return Ok(());
+}
+
+impl MaterialDescription {
+    fn validate(&self, textures: &Textures) -> Result<()> {
+        match self {
+            MaterialDescription::Lambertian { albedo } => {
+                albedo.build(textures)?;
+            }
+            MaterialDescription::Isotropic { albedo } => {
+                albedo.build(textures)?;
+            }
+            MaterialDescription::DiffuseLight { .. } => {}
+            MaterialDescription::Metal { albedo, fuzz } => {
+                albedo.build(textures)?;
+                if *fuzz < 0. {
+                    bail!("fuzz must not be negative, got {}", fuzz);
+                }
+            }
+            MaterialDescription::Dielectric { ir } => {
+                ir.build()?;
+            }
+            MaterialDescription::Conductor { eta, k, roughness } => {
+                if eta.iter().any(|&e| e <= 0.) || k.iter().any(|&k| k < 0.) {
+                    bail!("eta must be positive and k not negative");
+                }
+                unit_interval("roughness", *roughness)?;
+            }
+            MaterialDescription::RoughDielectric { ir, roughness } => {
+                if *ir <= 0. {
+                    bail!("ir must be positive, got {}", ir);
+                }
+                unit_interval("roughness", *roughness)?;
+            }
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_gloss,
+            } => {
+                base_color.build(textures)?;
+                unit_interval("metallic", *metallic)?;
+                unit_interval("roughness", *roughness)?;
+                unit_interval("specular", *specular)?;
+                unit_interval("clearcoat", *clearcoat)?;
+                unit_interval("clearcoat_gloss", *clearcoat_gloss)?;
+            }
+        }
+        return Ok(());
+    }
+
+    fn is_emissive(&self) -> bool {
+        matches!(self, MaterialDescription::DiffuseLight { .. })
+    }
+
+    fn build(&self, textures: &Textures) -> Result<Box<dyn Material>> {
+        return Ok(match self {
+            MaterialDescription::Lambertian { albedo } => {
+                Lambertian::textured(albedo.build(textures)?)
+            }
+            MaterialDescription::Metal { albedo, fuzz } => {
+                Metal::textured(albedo.build(textures)?, *fuzz)
+            }
+            MaterialDescription::Dielectric { ir } => Dielectric::dispersive(ir.build()?),
+            MaterialDescription::DiffuseLight { emit } => DiffuseLight::new(vector(*emit)),
+            MaterialDescription::Isotropic { albedo } => {
+                Isotropic::textured(albedo.build(textures)?)
+            }
+            MaterialDescription::Conductor { eta, k, roughness } => {
+                Conductor::new(vector(*eta), vector(*k), *roughness)
+            }
+            // the smooth one samples its mirror and refraction exactly
+            MaterialDescription::RoughDielectric { ir, roughness } if *roughness == 0. => {
+                Dielectric::new(*ir)
+            }
+            MaterialDescription::RoughDielectric { ir, roughness } => {
+                RoughDielectric::new(*ir, *roughness)
+            }
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_gloss,
+            } => Principled::new(
+                base_color.build(textures)?,
+                *metallic,
+                *roughness,
+                *specular,
+                *clearcoat,
+                *clearcoat_gloss,
+            ),
+        });
+    }
+}
+
+impl BackgroundDescription {
+    pub fn build(&self, base_dir: &Path) -> Result<Background> {
+        return Ok(match self {
+            BackgroundDescription::None => Background::None,
+            BackgroundDescription::Constant { color } => Background::Constant(vector(*color)),
+            BackgroundDescription::Gradient { horizon, zenith } => Background::Gradient {
+                horizon: vector(*horizon),
+                zenith: vector(*zenith),
+            },
+            BackgroundDescription::Environment {
+                path,
+                rotation,
+                intensity,
+            } => {
+                if *intensity < 0. {
+                    bail!("intensity must not be negative, got {}", intensity);
+                }
+                let image = image::load(base_dir.join(path))?;
+                Background::Environment(Arc::new(EnvironmentMap::new(image, *rotation, *intensity)))
+            }
+        });
+    }
+}
+
+impl CameraDescription {
+    pub fn build(&self, aspect_ratio: f64, base_dir: &Path) -> Result<Box<dyn Camera>> {
+        let lookfrom = point(self.lookfrom);
+        let lookat = point(self.lookat);
+        if lookfrom == lookat {
+            bail!("lookfrom and lookat must be different points");
+        }
+        // the camera's sideways axis is vup × the view direction
+        let view = lookat - lookfrom;
+        let vup = vector(self.vup);
+        if vup.cross(view).magnitude2() <= 1e-12 * vup.magnitude2() * view.magnitude2() {
+            bail!("vup must not be parallel to the view direction");
+        }
+        if self.vfov <= 0. || self.vfov >= 180. {
+            bail!("vfov must be between 0 and 180 degrees, got {}", self.vfov);
+        }
+        if self.aperture < 0. {
+            bail!("aperture must not be negative, got {}", self.aperture);
+        }
+        let focus_dist = self.focus_dist.unwrap_or(lookfrom.distance(lookat));
+        let [open, close] = self.shutter.unwrap_or([0., 0.]);
+        if close < open {
+            bail!(
+                "the shutter must open before it closes, got {:?}",
+                [open, close]
+            );
+        }
+        let has_lens = self.aperture > 0.
+            || self.aperture_shape != ApertureDescription::Circle
+            || self.cats_eye != 0.
+            || self.tilt != 0.
+            || self.swing != 0.
+            || self.shift != [0., 0.];
+        if has_lens && self.projection != Projection::Perspective {
+            bail!("only the perspective projection has an aperture, tilt or shift");
+        }
+        if self.cats_eye < 0. {
+            bail!("cats_eye must not be negative, got {}", self.cats_eye);
+        }
+        if self.tilt.abs() >= 90. || self.swing.abs() >= 90. {
+            bail!("tilt and swing must be between -90 and 90 degrees");
+        }
+        return Ok(match self.projection {
+            Projection::Perspective => Box::new(
+                PerspectiveCamera::new(
+                    lookfrom,
+                    lookat,
+                    vup,
+                    Deg(self.vfov).into(),
+                    aspect_ratio,
+                    self.aperture,
+                    focus_dist,
+                )
+                .with_shutter(open, close)
+                .with_aperture(self.aperture_shape.build(base_dir)?)
+                .with_cats_eye(self.cats_eye)
+                .with_tilt(Deg(self.tilt).into(), Deg(self.swing).into())
+                .with_shift(self.shift[0], self.shift[1]),
+            ),
+            Projection::Orthographic { height } => {
+                let height =
+                    height.unwrap_or(2. * focus_dist * (self.vfov.to_radians() / 2.).tan());
+                Box::new(
+                    OrthographicCamera::new(
+                        lookfrom,
+                        lookat,
+                        vup,
+                        positive("height", height)?,
+                        aspect_ratio,
+                    )
+                    .with_shutter(open, close),
+                )
+            }
+            Projection::Fisheye { fov } => {
+                if fov <= 0. || fov > 360. {
+                    bail!("fisheye fov must be between 0 and 360 degrees, got {}", fov);
+                }
+                Box::new(
+                    FisheyeCamera::new(lookfrom, lookat, vup, Deg(fov).into(), aspect_ratio)
+                        .with_shutter(open, close),
+                )
+            }
+            Projection::Equirectangular => Box::new(
+                EquirectangularCamera::new(lookfrom, lookat, vup).with_shutter(open, close),
+            ),
+        });
+    }
+}
+
+impl IorDescription {
+    fn build(&self) -> Result<Ior> {
+        let ior = match *self {
+            IorDescription::Constant(n) => Ior::Constant(n),
+            IorDescription::Dispersive(DispersionDescription::Cauchy { a, b }) => {
+                Ior::Cauchy { a, b }
+            }
+            IorDescription::Dispersive(DispersionDescription::Sellmeier { b, c }) => {
+                Ior::Sellmeier { b, c }
+            }
+        };
+        // every 10nm across the visible range
+        for i in 0..=((LAMBDA_MAX - LAMBDA_MIN) / 10.) as usize {
+            let lambda = LAMBDA_MIN + 10. * i as f64;
+            let n = ior.at(lambda);
+            if !(n > 0. && n.is_finite()) {
+                bail!("ir must be positive, got {} at {}nm", n, lambda);
+            }
+        }
+        return Ok(ior);
+    }
+}
+
+impl ApertureDescription {
+    fn build(&self, base_dir: &Path) -> Result<Aperture> {
+        return Ok(match self {
+            ApertureDescription::Circle => Aperture::Circle,
+            ApertureDescription::Polygon { blades, rotation } => {
+                if *blades < 3 {
+                    bail!("an aperture needs at least 3 blades, got {}", blades);
+                }
+                Aperture::Polygon {
+                    blades: *blades,
+                    rotation: Deg(*rotation).into(),
+                }
+            }
+            ApertureDescription::Image { path } => {
+                let image = image::load(base_dir.join(path))?;
+                if !image.pixels().iter().any(|&c| luminance(c) > 0.) {
+                    bail!("the aperture image {} is black", path);
+                }
+                Aperture::mask(&image)
+            }
+        });
+    }
+}
+
+impl RenderSettings {
+    pub fn integrator(&self) -> Box<dyn Integrator> {
+        match self.integrator {
+            IntegratorDescription::Naive => Box::new(NaiveIntegrator {
+                max_depth: self.max_depth,
+            }),
+            IntegratorDescription::Path { heuristic } => Box::new(PathIntegrator {
+                max_depth: self.max_depth,
+                heuristic,
+            }),
+        }
+    }
+
+    pub fn sampler(&self) -> Box<dyn Sampler> {
+        match self.sampler {
+            SamplerDescription::Independent => Box::new(IndependentSampler::new(self.seed)),
+            SamplerDescription::Stratified => {
+                Box::new(StratifiedSampler::new(self.seed, self.samples_per_pixel))
+            }
+            SamplerDescription::Halton => Box::new(HaltonSampler::new(self.seed)),
+            SamplerDescription::Sobol => Box::new(SobolSampler::new(self.seed)),
+        }
+    }
+
+    pub fn image_height(&self) -> u32 {
+        (self.image_width as f64 / self.aspect_ratio) as u32
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.image_width < 2 {
+            bail!("image_width must be at least 2, got {}", self.image_width);
+        }
+        if self.aspect_ratio <= 0. || self.image_width as f64 / self.aspect_ratio < 2. {
+            bail!("aspect_ratio {} gives an empty image", self.aspect_ratio);
+        }
+        if self.samples_per_pixel == 0 {
+            bail!("samples_per_pixel must be at least 1");
+        }
+        if self.max_depth <= 0 {
+            bail!("max_depth must be at least 1, got {}", self.max_depth);
+        }
+        if self.tile_size == 0 || self.samples_per_pass == 0 {
+            bail!("tile_size and samples_per_pass must be at least 1");
+        }
+        if self.time_limit.is_some_and(|limit| limit <= 0.) {
+            bail!("time_limit must be positive");
+        }
+        if self.checkpoint_interval < 0. {
+            bail!("checkpoint_interval must not be negative");
+        }
+        if let Some(adaptive) = &self.adaptive {
+            if adaptive.threshold <= 0. {
+                bail!(
+                    "adaptive.threshold must be positive, got {}",
+                    adaptive.threshold
+                );
+            }
+            if adaptive.min_samples < 2 {
+                bail!("adaptive.min_samples must be at least 2 to estimate the noise");
+            }
+        }
+        if let Some(denoise) = &self.denoise {
+            if denoise.sigma_color <= 0. || denoise.sigma_normal < 0. || denoise.sigma_depth <= 0. {
+                bail!("denoise.sigma_color and sigma_depth must be positive, sigma_normal not negative");
+            }
+            if denoise.iterations > 16 {
+                bail!(
+                    "denoise.iterations must be at most 16, got {}",
+                    denoise.iterations
+                );
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl SceneDescription {
+    pub fn from_json(contents: &str) -> Result<Self> {
+        return Ok(serde_json::from_str(contents)?);
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        return Ok(serde_json::to_string_pretty(self)?);
+    }
+
+    // Hashes the scene minus the settings that only decide how long to render
+    // and where to, so that a checkpoint can be resumed with a higher sample
+    // count but not with a changed scene. The stratified sampler spreads its
+    // strata over exactly the target count, so it has to keep it.
+    pub fn fingerprint(&self) -> Result<u64> {
+        let mut scene = self.clone();
+        let defaults = RenderSettings::default();
+        let samples_per_pixel = match scene.render.sampler {
+            SamplerDescription::Stratified => scene.render.samples_per_pixel,
+            _ => defaults.samples_per_pixel,
+        };
+        scene.render = RenderSettings {
+            samples_per_pixel,
+            output: defaults.output,
+            output_format: defaults.output_format,
+            tile_size: defaults.tile_size,
+            samples_per_pass: defaults.samples_per_pass,
+            time_limit: defaults.time_limit,
+            checkpoint_interval: defaults.checkpoint_interval,
+            adaptive: defaults.adaptive,
+            ..scene.render
+        };
+        let json = serde_json::to_vec(&scene)?;
+        return Ok(hash(&json.into_iter().map(u64::from).collect::<Vec<_>>()));
+    }
+
+    // `base_dir` is where relative mesh and image paths are looked up
+    pub fn build(&self, base_dir: &Path) -> Result<Scene> {
+        return self.build_frame(base_dir, 0.);
+    }
+
+    // The scene with its camera and animated objects where they are at `frame`.
+    pub fn build_frame(&self, base_dir: &Path, frame: f64) -> Result<Scene> {
+        self.render.validate().context("render")?;
+        let mut textures = Textures::new();
+        for (name, texture) in self.textures.iter() {
+            let texture = texture
+                .build(base_dir)
+                .with_context(|| format!("textures.{}", name))?;
+            textures.insert(name.clone(), texture);
+        }
+        for (name, material) in self.materials.iter() {
+            material
+                .validate(&textures)
+                .with_context(|| format!("materials.{}", name))?;
+        }
+        let camera = self
+            .camera
+            .at_frame(frame)
+            .context("animation")
+            .and_then(|camera| camera.build(self.render.aspect_ratio, base_dir))
+            .context("camera")?;
+
+        let mut builder = ObjectBuilder {
+            description: self,
+            textures,
+            prototypes: BTreeMap::new(),
+            base_dir,
+            frame,
+        };
+        // prototypes can't be instances themselves, so they are built before
+        // any are known
+        let mut prototypes = BTreeMap::new();
+        for (name, prototype) in self.prototypes.iter() {
+            let object = builder
+                .build(prototype)
+                .with_context(|| format!("prototypes.{}", name))?;
+            prototypes.insert(name.clone(), Arc::from(object));
+        }
+        builder.prototypes = prototypes;
+
+        let mut world = HittableList::default();
+        // planes and the like, which no accelerator can hold
+        let mut unbounded = HittableList::default();
+        let mut lights = HittableList::default();
+        for (i, object) in self.objects.iter().enumerate() {
+            let context = || format!("objects[{}]", i);
+            let mut built = builder.build(object).with_context(context)?;
+            if self.render.aovs.contains(&Aov::ObjectId) {
+                built = Box::new(Tagged::new(built, i as u32 + 1));
+            }
+            if built.bounding_box().is_some() {
+                world.add(built);
+            } else {
+                unbounded.add(built);
+            }
+            if builder.is_light(object) {
+                lights.add(builder.build(object).with_context(context)?);
+            }
+        }
+        if world.is_empty() && unbounded.is_empty() {
+            bail!("objects: the scene is empty");
+        }
+        let background = self.background.build(base_dir).context("background")?;
+        if let Background::Environment(map) = &background {
+            lights.add(Box::new(EnvironmentLight::new(map.clone())));
+        }
+
+        let mut world: Box<dyn Hittable> = match self.render.accelerator {
+            _ if world.is_empty() => Box::new(world),
+            Accelerator::None => Box::new(world),
+            Accelerator::Midpoint => Box::new(BvhNode::new(world, SplitHeuristic::Midpoint)?),
+            Accelerator::Sah => Box::new(BvhNode::new(world, SplitHeuristic::Sah)?),
+        };
+        if !unbounded.is_empty() {
+            unbounded.add(world);
+            world = Box::new(unbounded);
+        }
+        if let Some(fog) = &self.fog {
+            if fog.density <= 0. {
+                bail!("fog: density must be positive, got {}", fog.density);
+            }
+            let phase_function =
+                Isotropic::textured(fog.albedo.build(&builder.textures).context("fog")?);
+            // fog has no bounds, so it sits next to the accelerator rather than in it
+            let mut foggy = HittableList::new(world);
+            foggy.add(Box::new(ConstantMedium::unbounded(
+                fog.density,
+                phase_function,
+            )));
+            world = Box::new(foggy);
+        }
+
+        return Ok(Scene {
+            world,
+            lights,
+            camera,
+            background,
+            settings: self.render.clone(),
+            // frame 0 keeps the fingerprint of a still of the scene
+            fingerprint: match self.fingerprint()? {
+                fingerprint if frame == 0. => fingerprint,
+                fingerprint => hash(&[fingerprint, frame.to_bits()]),
+            },
+        });
+    }
+}
+
+// Everything objects refer to by name, ready to be built from.
+struct ObjectBuilder<'a> {
+    description: &'a SceneDescription,
+    textures: Textures,
+    prototypes: BTreeMap<String, Arc<dyn Hittable>>,
+    // where relative mesh paths are looked up
+    base_dir: &'a Path,
+    // the frame animated objects are posed at
+    frame: f64,
+}
+
+impl ObjectBuilder<'_> {
+    fn material(&self, name: &str) -> Result<Box<dyn Material>> {
+        let materials = &self.description.materials;
+        let Some(material) = materials.get(name) else {
+            bail!("unknown material `{}`", name);
+        };
+        let material = material.build(&self.textures)?;
+        if self.description.render.aovs.contains(&Aov::MaterialId) {
+            let id = materials.keys().position(|key| key == name).unwrap() as u32 + 1;
+            return Ok(TaggedMaterial::new(material, id));
+        }
+        return Ok(material);
+    }
+
+    fn build(&self, object: &ObjectDescription) -> Result<Box<dyn Hittable>> {
+        return Ok(match object {
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                if *radius == 0. {
+                    bail!("radius must not be 0");
+                }
+                Box::new(Sphere::new(
+                    point(*center),
+                    *radius,
+                    self.material(material)?,
+                ))
+            }
+            ObjectDescription::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => {
+                if *radius == 0. {
+                    bail!("radius must not be 0");
+                }
+                Box::new(MovingSphere::new(
+                    point(*center0),
+                    point(*center1),
+                    *time0,
+                    *time1,
+                    *radius,
+                    self.material(material)?,
+                ))
+            }
+            ObjectDescription::Triangle { vertices, material } => Box::new(Triangle::new(
+                point(vertices[0]),
+                point(vertices[1]),
+                point(vertices[2]),
+                self.material(material)?,
+            )),
+            ObjectDescription::Quad { q, u, v, material } => Box::new(Quad::new(
+                point(*q),
+                vector(*u),
+                vector(*v),
+                self.material(material)?,
+            )),
+            ObjectDescription::AxisAlignedQuad {
+                axis,
+                min,
+                max,
+                offset,
+                material,
+            } => {
+                if min[0] >= max[0] || min[1] >= max[1] {
+                    bail!("min must be below max, got {:?} and {:?}", min, max);
+                }
+                Box::new(Quad::axis_aligned(
+                    *axis,
+                    *min,
+                    *max,
+                    *offset,
+                    self.material(material)?,
+                ))
+            }
+            ObjectDescription::Box { min, max, material } => {
+                if (0..3).any(|i| min[i] >= max[i]) {
+                    bail!("min must be below max, got {:?} and {:?}", min, max);
+                }
+                // every side gets its own copy of the material
+                let sides = (0..6)
+                    .map(|_| self.material(material))
+                    .collect::<Result<Vec<_>>>()?;
+                let Ok(sides) = sides.try_into() else {
+                    unreachable!()
+                };
+                Box::new(Cuboid::new(point(*min), point(*max), sides))
+            }
+            ObjectDescription::Plane {
+                point: p,
+                normal,
+                material,
+            } => Box::new(Plane::new(
+                point(*p),
+                direction(*normal).context("normal")?,
+                self.material(material)?,
+            )),
+            ObjectDescription::Disk {
+                center,
+                normal,
+                radius,
+                material,
+            } => Box::new(Disk::new(
+                point(*center),
+                direction(*normal).context("normal")?,
+                positive("radius", *radius)?,
+                self.material(material)?,
+            )),
+            ObjectDescription::Cylinder {
+                base,
+                top,
+                radius,
+                capped,
+                material,
+            } => {
+                if base == top {
+                    bail!("base and top must be different points");
+                }
+                Box::new(Cylinder::new(
+                    point(*base),
+                    point(*top),
+                    positive("radius", *radius)?,
+                    *capped,
+                    self.material(material)?,
+                ))
+            }
+            ObjectDescription::Cone {
+                base,
+                apex,
+                radius,
+                capped,
+                material,
+            } => {
+                if base == apex {
+                    bail!("base and apex must be different points");
+                }
+                Box::new(Cone::new(
+                    point(*base),
+                    point(*apex),
+                    positive("radius", *radius)?,
+                    *capped,
+                    self.material(material)?,
+                ))
+            }
+            ObjectDescription::Torus {
+                center,
+                normal,
+                major_radius,
+                minor_radius,
+                material,
+            } => Box::new(Torus::new(
+                point(*center),
+                direction(*normal).context("normal")?,
+                positive("major_radius", *major_radius)?,
+                positive("minor_radius", *minor_radius)?,
+                self.material(material)?,
+            )),
+            ObjectDescription::Csg {
+                operation,
+                left,
+                right,
+            } => Box::new(Csg::new(
+                *operation,
+                self.build(left).context("left")?,
+                self.build(right).context("right")?,
+            )),
+            ObjectDescription::Sdf { sdf, material } => Box::new(SdfObject::new(
+                sdf.build().context("sdf")?,
+                self.material(material)?,
+            )),
+            ObjectDescription::Mesh { path } => Box::new(load_obj(self.base_dir.join(path))?),
+            ObjectDescription::ConstantMedium {
+                boundary,
+                density,
+                material,
+            } => {
+                if *density <= 0. {
+                    bail!("density must be positive, got {}", density);
+                }
+                Box::new(ConstantMedium::new(
+                    self.build(boundary).context("boundary")?,
+                    *density,
+                    self.material(material)?,
+                ))
+            }
+            ObjectDescription::Transform { transforms, object } => Box::new(Transform::new(
+                self.build(object).context("object")?,
+                transform_matrix(transforms)?,
+            )?),
+            ObjectDescription::Motion {
+                start,
+                end,
+                time0,
+                time1,
+                object,
+            } => Box::new(MotionTransform::new(
+                self.build(object).context("object")?,
+                start.build().context("start")?,
+                end.build().context("end")?,
+                *time0,
+                *time1,
+            )?),
+            ObjectDescription::Animated {
+                interpolation,
+                keyframes,
+                object,
+            } => {
+                let object = self.build(object).context("object")?;
+                let pose = |time: f64| {
+                    return pose_at(keyframes, *interpolation, self.frame + time)?.build();
+                };
+                match self.description.camera.shutter {
+                    Some([open, close]) if close > open => Box::new(MotionTransform::new(
+                        object,
+                        pose(open)?,
+                        pose(close)?,
+                        open,
+                        close,
+                    )?),
+                    shutter => Box::new(Transform::new(
+                        object,
+                        pose(shutter.map_or(0., |[open, _]| open))?.matrix(),
+                    )?),
+                }
+            }
+            ObjectDescription::Instance {
+                prototype,
+                transforms,
+            } => {
+                let Some(object) = self.prototypes.get(prototype) else {
+                    bail!("unknown prototype `{}`", prototype);
+                };
+                Box::new(Instance::placed(
+                    object.clone(),
+                    transform_matrix(transforms)?,
+                )?)
+            }
+        });
+    }
+
+    // emissive shapes are also added to the scene's lights for direct sampling
+    fn is_light(&self, object: &ObjectDescription) -> bool {
+        let emissive = |name: &str| {
+            self.description
+                .materials
+                .get(name)
+                .is_some_and(|material| material.is_emissive())
+        };
+        match object {
+            ObjectDescription::Sphere { material, .. }
+            | ObjectDescription::Triangle { material, .. }
+            | ObjectDescription::Quad { material, .. }
+            | ObjectDescription::AxisAlignedQuad { material, .. }
+            | ObjectDescription::Box { material, .. }
+            | ObjectDescription::Disk { material, .. } => emissive(material),
+            // only shapes with a sampling routine can be lights
+            ObjectDescription::Plane { .. }
+            | ObjectDescription::Cylinder { .. }
+            | ObjectDescription::Cone { .. }
+            | ObjectDescription::Torus { .. }
+            | ObjectDescription::Csg { .. }
+            | ObjectDescription::Sdf { .. } => false,
+            // there's no time to sample moving lights at
+            ObjectDescription::Mesh { .. } | ObjectDescription::ConstantMedium { .. } => false,
+            ObjectDescription::MovingSphere { .. } | ObjectDescription::Motion { .. } => false,
+            ObjectDescription::Transform { object, .. } => self.is_light(object),
+            // which holds still within a frame unless the shutter is open
+            ObjectDescription::Animated { object, .. } => {
+                !self
+                    .description
+                    .camera
+                    .shutter
+                    .is_some_and(|[open, close]| close > open)
+                    && self.is_light(object)
+            }
+            ObjectDescription::Instance { prototype, .. } => self
+                .description
+                .prototypes
+                .get(prototype)
+                .is_some_and(|object| self.is_light(object)),
+        }
+    }
+}
+
+impl SdfDescription {
+    fn build(&self) -> Result<SdfNode> {
+        let child = |node: &SdfDescription, name: &str| -> Result<Box<SdfNode>> {
+            return Ok(Box::new(node.build().context(name.to_string())?));
+        };
+        let children = |nodes: &[SdfDescription]| -> Result<Vec<SdfNode>> {
+            if nodes.is_empty() {
+                bail!("nodes must not be empty");
+            }
+            return nodes
+                .iter()
+                .enumerate()
+                .map(|(i, node)| node.build().with_context(|| format!("nodes[{}]", i)))
+                .collect();
+        };
+        let smooth = |smoothness: f64| {
+            if smoothness < 0. {
+                bail!("smoothness must not be negative, got {}", smoothness);
+            }
+            return Ok(smoothness);
+        };
+        return Ok(match self {
+            SdfDescription::Sphere { radius } => SdfNode::Sphere {
+                radius: positive("radius", *radius)?,
+            },
+            SdfDescription::Box {
+                half_size,
+                rounding,
+            } => {
+                let smallest = half_size.iter().copied().fold(f64::INFINITY, f64::min);
+                positive("half_size", smallest)?;
+                if !(0. ..=smallest).contains(rounding) {
+                    bail!(
+                        "rounding must be between 0 and the smallest half size, got {}",
+                        rounding
+                    );
+                }
+                SdfNode::Box {
+                    half_size: vector(*half_size),
+                    rounding: *rounding,
+                }
+            }
+            SdfDescription::Torus {
+                major_radius,
+                minor_radius,
+            } => SdfNode::Torus {
+                major: positive("major_radius", *major_radius)?,
+                minor: positive("minor_radius", *minor_radius)?,
+            },
+            SdfDescription::Capsule { a, b, radius } => SdfNode::Capsule {
+                a: point(*a),
+                b: point(*b),
+                radius: positive("radius", *radius)?,
+            },
+            SdfDescription::Mandelbulb { power, iterations } => {
+                if *power < 2. {
+                    bail!("power must be at least 2, got {}", power);
+                }
+                if *iterations == 0 {
+                    bail!("iterations must be positive");
+                }
+                SdfNode::Mandelbulb {
+                    power: *power,
+                    iterations: *iterations,
+                }
+            }
+            SdfDescription::Translate { offset, node } => SdfNode::Translate {
+                offset: vector(*offset),
+                node: child(node, "node")?,
+            },
+            SdfDescription::Scale { factor, node } => SdfNode::Scale {
+                factor: positive("factor", *factor)?,
+                node: child(node, "node")?,
+            },
+            SdfDescription::Union { nodes, smoothness } => SdfNode::Union {
+                nodes: children(nodes)?,
+                smoothness: smooth(*smoothness)?,
+            },
+            SdfDescription::Intersection { nodes, smoothness } => SdfNode::Intersection {
+                nodes: children(nodes)?,
+                smoothness: smooth(*smoothness)?,
+            },
+            SdfDescription::Difference {
+                base,
+                cut,
+                smoothness,
+            } => SdfNode::Difference {
+                base: child(base, "base")?,
+                cut: child(cut, "cut")?,
+                smoothness: smooth(*smoothness)?,
+            },
+            SdfDescription::Repeat {
+                spacing,
+                count,
+                node,
+            } => {
+                if spacing.iter().any(|s| *s < 0.) {
+                    bail!("spacing must not be negative, got {:?}", spacing);
+                }
+                SdfNode::Repeat {
+                    spacing: vector(*spacing),
+                    count: *count,
+                    node: child(node, "node")?,
+                }
+            }
+            SdfDescription::Twist { rate, node } => SdfNode::twist(*rate, *child(node, "node")?),
+        });
+    }
+}
+
+impl TransformDescription {
+    fn matrix(&self) -> Result<Matrix4<f64>> {
+        return Ok(match self {
+            TransformDescription::Translate { offset } => {
+                Matrix4::from_translation(vector(*offset))
+            }
+            TransformDescription::Rotate { axis, angle } => {
+                let axis = vector(*axis);
+                if axis.magnitude2() == 0. {
+                    bail!("rotation axis must not be zero");
+                }
+                Matrix4::from_axis_angle(axis.normalize(), Deg(*angle))
+            }
+            TransformDescription::Scale { factor } => {
+                Matrix4::from_nonuniform_scale(factor[0], factor[1], factor[2])
+            }
+            // cgmath takes columns
+            TransformDescription::Matrix { rows } => Matrix4::from(*rows).transpose(),
+        });
+    }
+}
+
+impl PoseDescription {
+    fn build(&self) -> Result<Pose> {
+        let axis = vector(self.axis);
+        if axis.magnitude2() == 0. {
+            bail!("rotation axis must not be zero");
+        }
+        return Ok(Pose {
+            translation: vector(self.translate),
+            rotation: Quaternion::from_axis_angle(axis.normalize(), Deg(self.angle)),
+            scale: vector(self.scale),
+        });
+    }
+}
+
+// the transforms applied one after the other
+fn transform_matrix(transforms: &[TransformDescription]) -> Result<Matrix4<f64>> {
+    let mut matrix = Matrix4::identity();
+    for (i, transform) in transforms.iter().enumerate() {
+        matrix = transform
+            .matrix()
+            .with_context(|| format!("transforms[{}]", i))?
+            * matrix;
+    }
+    return Ok(matrix);
+}
+
+impl SceneDescription {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        return SceneDescription::from_json(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()));
+    }
+}
+
+impl Scene {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        return Scene::load_frame(path, 0.);
+    }
+
+    pub fn load_frame<P: AsRef<Path>>(path: P, frame: f64) -> Result<Self> {
+        let path = path.as_ref();
+        return SceneDescription::load(path)?
+            .build_frame(path.parent().unwrap_or(Path::new("")), frame)
+            .with_context(|| format!("invalid scene {}", path.display()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ray::Ray, sampler::IndependentSampler};
+
+    const SCENE: &str = r#"{
+        "render": { "image_width": 40, "samples_per_pixel": 4 },
+        "camera": { "lookfrom": [13, 2, 3], "lookat": [0, 0, 0], "vfov": 20 },
+        "materials": {
+            "ground": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] },
+            "glass": { "type": "dielectric", "ir": 1.5 }
+        },
+        "objects": [
+            { "type": "sphere", "center": [0, -1000, 0], "radius": 1000, "material": "ground" },
+            { "type": "sphere", "center": [0, 1, 0], "radius": 1, "material": "glass" }
+        ]
+    }"#;
+
+    #[test]
+    fn test_build_scene() {
+        let description = SceneDescription::from_json(SCENE).unwrap();
+        assert_eq!(description.render.image_width, 40);
+        assert_eq!(description.render.max_depth, 50);
+        let scene = description.build(Path::new("")).unwrap();
+        assert!(scene.world.bounding_box().is_some());
+
+        // survives a round trip through the serializer
+        let json = description.to_json().unwrap();
+        assert_eq!(SceneDescription::from_json(&json).unwrap(), description);
+    }
+
+    #[test]
+    fn test_textures() {
+        let json = SCENE.replace(
+            r#""materials": {"#,
+            r#""textures": {
+                "checker": { "type": "checker", "scale": 10, "even": [1, 1, 1], "odd": [0, 0, 0] },
+                "marble": { "type": "noise", "scale": 4, "style": "marble" }
+            },
+            "materials": {
+                "marble": { "type": "metal", "albedo": "marble", "fuzz": 0.1 },"#,
+        );
+        let json = json.replace("[0.5, 0.5, 0.5]", r#""checker""#);
+        let description = SceneDescription::from_json(&json).unwrap();
+        assert_eq!(
+            description.materials["ground"],
+            MaterialDescription::Lambertian {
+                albedo: TextureOrColor::Texture("checker".to_string())
+            }
+        );
+        assert!(description.build(Path::new("")).is_ok());
+        let json = description.to_json().unwrap();
+        assert_eq!(SceneDescription::from_json(&json).unwrap(), description);
+
+        let missing = r#"{ "type": "image", "path": "missing.png" }"#;
+        let err = serde_json::from_str::<TextureDescription>(missing)
+            .unwrap()
+            .build(Path::new(""))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("missing.png"));
+    }
+
+    #[test]
+    fn test_transforms_and_instances() {
+        let json = SCENE.replace(
+            r#""objects": ["#,
+            r#""prototypes": {
+                "ball": { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "glass" }
+            },
+            "objects": [
+                { "type": "instance", "prototype": "ball", "transforms": [
+                    { "type": "scale", "factor": [1, 2, 1] },
+                    { "type": "translate", "offset": [0, 0, 5] }
+                ] },
+                { "type": "transform", "transforms": [{ "type": "rotate", "axis": [0, 1, 0], "angle": 90 }],
+                  "object": { "type": "sphere", "center": [0, 0, 5], "radius": 1, "material": "glass" } },"#,
+        );
+        let description = SceneDescription::from_json(&json).unwrap();
+        let scene = description.build(Path::new("")).unwrap();
+        let bbox = scene.world.bounding_box().unwrap();
+        // the rotated sphere ends up at x = 5
+        assert!(bbox.maximum.x >= 6. - 1e-9 && bbox.maximum.z >= 6. - 1e-9);
+        // and the stretched instance reaches y = 2, where the bounding box
+        // can't tell it from the glass sphere at the origin
+        let down = Ray::new(Point3::new(0., 10., 5.), vec3(0., -1., 0.));
+        let hit = scene.world.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.p.y - 2.).abs() < 1e-9, "{:?}", hit.p);
+        let pretty = description.to_json().unwrap();
+        assert_eq!(SceneDescription::from_json(&pretty).unwrap(), description);
+
+        let unknown = json.replace(r#""prototype": "ball""#, r#""prototype": "bal""#);
+        let err = SceneDescription::from_json(&unknown)
+            .unwrap()
+            .build(Path::new(""))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("objects[0]: unknown prototype `bal`"));
+
+        let singular = json.replace("[1, 2, 1]", "[1, 0, 1]");
+        assert!(SceneDescription::from_json(&singular)
+            .unwrap()
+            .build(Path::new(""))
+            .is_err());
+    }
+
+    #[test]
+    fn test_motion_blur() {
+        let json = SCENE
+            .replace(r#""vfov": 20"#, r#""vfov": 20, "shutter": [0.25, 0.5]"#)
+            .replace(
+                r#""objects": ["#,
+                r#""objects": [
+                { "type": "moving_sphere", "center0": [0, 1, 0], "center1": [0, 2, 0],
+                  "radius": 1, "material": "glass" },
+                { "type": "motion", "start": {}, "end": { "translate": [3, 0, 0], "angle": 45 },
+                  "object": { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "glass" } },"#,
+            );
+        let description = SceneDescription::from_json(&json).unwrap();
+        let scene = description.build(Path::new("")).unwrap();
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let time = scene.camera.get_ray(0.5, 0.5, &mut sampler).unwrap().time();
+            assert!((0.25..0.5).contains(&time), "{}", time);
+        }
+        assert!(scene.world.bounding_box().unwrap().maximum.x >= 4.);
+        let pretty = description.to_json().unwrap();
+        assert_eq!(SceneDescription::from_json(&pretty).unwrap(), description);
+
+        let backwards = json.replace("[0.25, 0.5]", "[0.5, 0.25]");
+        let err = SceneDescription::from_json(&backwards)
+            .unwrap()
+            .build(Path::new(""))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("camera: the shutter"));
+    }
+
+    #[test]
+    fn test_animation() {
+        let json = SCENE
+            .replace(
+                r#""vfov": 20"#,
+                r#""vfov": 20, "animation": { "keyframes": [
+                    { "frame": 0, "vfov": 20 },
+                    { "frame": 10, "vfov": 40, "lookfrom": [13, 4, 3] }
+                ] }"#,
+            )
+            .replace(
+                r#""objects": ["#,
+                r#""objects": [
+                { "type": "animated", "interpolation": "catmull_rom", "keyframes": [
+                    { "frame": 0, "translate": [0, 1, 0] },
+                    { "frame": 10, "translate": [10, 1, 0], "angle": 90 }
+                  ],
+                  "object": { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "glass" } },"#,
+            );
+        let description = SceneDescription::from_json(&json).unwrap();
+        let pretty = description.to_json().unwrap();
+        assert_eq!(SceneDescription::from_json(&pretty).unwrap(), description);
+
+        let camera = description.camera.at_frame(5.).unwrap();
+        assert_eq!(camera.vfov, 30.);
+        assert_eq!(camera.lookfrom, [13., 4., 3.]);
+        assert!(camera.animation.is_none());
+
+        // the sphere is halfway along at frame 5
+        let scene = description.build_frame(Path::new(""), 5.).unwrap();
+        let ray = Ray::new(Point3::new(5., 1., 10.), vec3(0., 0., -1.));
+        let hit = scene.world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 9.).abs() < 1e-6, "{}", hit.t);
+
+        // frames don't share checkpoints, but a still of frame 0 does
+        let still = description.build(Path::new("")).unwrap();
+        assert_eq!(
+            still.fingerprint,
+            description
+                .build_frame(Path::new(""), 0.)
+                .unwrap()
+                .fingerprint
+        );
+        assert_ne!(still.fingerprint, scene.fingerprint);
+
+        let backwards = json.replace(r#""frame": 10, "vfov""#, r#""frame": 0, "vfov""#);
+        let err = SceneDescription::from_json(&backwards)
+            .unwrap()
+            .build(Path::new(""))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("camera: animation: keyframes[1]"));
+    }
+
+    #[test]
+    fn test_projections() {
+        let build = |projection: &str| {
+            let json = SCENE.replace(
+                r#""vfov": 20"#,
+                &format!(r#""vfov": 20, "projection": {}"#, projection),
+            );
+            let description = SceneDescription::from_json(&json)?;
+            let pretty = description.to_json().unwrap();
+            assert_eq!(SceneDescription::from_json(&pretty).unwrap(), description);
+            return description.build(Path::new(""));
+        };
+        let mut sampler = IndependentSampler::new(0);
+        for projection in [
+            r#"{ "type": "perspective" }"#,
+            r#"{ "type": "orthographic" }"#,
+            r#"{ "type": "orthographic", "height": 4 }"#,
+            r#"{ "type": "fisheye", "fov": 360 }"#,
+            r#"{ "type": "equirectangular" }"#,
+        ] {
+            let scene = build(projection).unwrap();
+            // every projection looks at lookat through the middle of the
+            // image, except that a panorama keeps to the horizon
+            let r = scene.camera.get_ray(0.5, 0.5, &mut sampler).unwrap();
+            let towards = if projection.contains("equirectangular") {
+                vec3(-13., 0., -3.).normalize()
+            } else {
+                (Point3::new(0., 0., 0.) - Point3::new(13., 2., 3.)).normalize()
+            };
+            assert!((r.direction().normalize() - towards).magnitude() < 1e-9);
+        }
+        // a fisheye sees only a circle
+        let scene = build(r#"{ "type": "fisheye" }"#).unwrap();
+        assert!(scene.camera.get_ray(0., 0., &mut sampler).is_none());
+
+        assert!(build(r#"{ "type": "fisheye", "fov": 400 }"#).is_err());
+        assert!(build(r#"{ "type": "orthographic", "height": 0 }"#).is_err());
+        for lens in [
+            r#""aperture": 0.1, "projection": { "type": "orthographic" }"#,
+            r#""tilt": 10, "projection": { "type": "fisheye" }"#,
+            r#""tilt": 90"#,
+            r#""cats_eye": -1"#,
+            r#""aperture_shape": { "type": "polygon", "blades": 2 }"#,
+        ] {
+            let json = SCENE.replace(r#""vfov": 20"#, &format!(r#""vfov": 20, {}"#, lens));
+            let description = SceneDescription::from_json(&json).unwrap();
+            assert!(description.build(Path::new("")).is_err(), "{}", lens);
+        }
+        let json = SCENE.replace(
+            r#""vfov": 20"#,
+            r#""vfov": 20, "aperture": 0.1, "cats_eye": 0.5, "tilt": 20, "shift": [0, 0.1],
+               "aperture_shape": { "type": "polygon", "blades": 5, "rotation": 18 }"#,
+        );
+        let description = SceneDescription::from_json(&json).unwrap();
+        description.build(Path::new("")).unwrap();
+        let pretty = description.to_json().unwrap();
+        assert_eq!(SceneDescription::from_json(&pretty).unwrap(), description);
+    }
+
+    #[test]
+    fn test_media() {
+        let json = SCENE
+            .replace(
+                r#""materials": {"#,
+                r#""fog": { "density": 0.01, "albedo": [1, 1, 1] },
+            "materials": {
+                "smoke": { "type": "isotropic", "albedo": [0.2, 0.2, 0.2] },"#,
+            )
+            .replace(
+                r#""objects": ["#,
+                r#""objects": [
+                { "type": "constant_medium", "density": 2, "material": "smoke",
+                  "boundary": { "type": "sphere", "center": [3, 1, 0], "radius": 1, "material": "glass" } },"#,
+            );
+        let description = SceneDescription::from_json(&json).unwrap();
+        let scene = description.build(Path::new("")).unwrap();
+        // the fog makes the world unbounded
+        assert!(scene.world.bounding_box().is_none());
+        let pretty = description.to_json().unwrap();
+        assert_eq!(SceneDescription::from_json(&pretty).unwrap(), description);
+
+        let thin = json.replace(r#""density": 2"#, r#""density": 0"#);
+        let err = SceneDescription::from_json(&thin)
+            .unwrap()
+            .build(Path::new(""))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("objects[0]: density must be positive"));
+    }
+
+    #[test]
+    fn test_errors_point_at_entry() {
+        let unknown = SCENE.replace(r#""material": "glass""#, r#""material": "glas""#);
+        let err = SceneDescription::from_json(&unknown)
+            .unwrap()
+            .build(Path::new(""))
+            .err()
+            .unwrap();
+        let msg = format!("{:#}", err);
+        assert!(
+            msg.contains("objects[1]") && msg.contains("glas"),
+            "{}",
+            msg
+        );
+
+        let bad_ir = SCENE.replace(r#""ir": 1.5"#, r#""ir": -1"#);
+        let err = SceneDescription::from_json(&bad_ir)
+            .unwrap()
+            .build(Path::new(""))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("materials.glass"));
+
+        let texture = SCENE.replace("[0.5, 0.5, 0.5]", r#""marble""#);
+        let err = SceneDescription::from_json(&texture)
+            .unwrap()
+            .build(Path::new(""))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("materials.ground: unknown texture `marble`"));
+
+        let looking_up = SCENE.replace(r#""lookat": [0, 0, 0]"#, r#""lookat": [13, 5, 3]"#);
+        let err = SceneDescription::from_json(&looking_up)
+            .unwrap()
+            .build(Path::new(""))
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("camera: vup must not be parallel"));
+
+        let typo = SCENE.replace(r#""type": "sphere""#, r#""type": "sphre""#);
+        let err = SceneDescription::from_json(&typo).unwrap_err();
+        assert!(format!("{:#}", err).contains("line 9"), "{:#}", err);
+    }
+}
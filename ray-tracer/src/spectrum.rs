@@ -0,0 +1,208 @@
+// Wavelength dependent rendering. Materials and lights stay RGB, a colour
+// is turned into a spectrum by spreading each of its channels evenly over a
+// band of wavelengths, mixed so that taking the spectrum back to RGB through
+// the CIE colour matching functions gives the same colour again.
+
+use std::sync::OnceLock;
+
+use cgmath::{vec3, Matrix3, SquareMatrix};
+
+use crate::Color;
+
+// the visible range in nanometres that wavelengths are sampled from
+pub const LAMBDA_MIN: f64 = 360.;
+pub const LAMBDA_MAX: f64 = 830.;
+// the sodium d-line, where glasses' indices of refraction are quoted
+pub const LAMBDA_D: f64 = 587.56;
+
+// where the blue, green and red bands of the spectra meet
+const BAND_EDGES: [f64; 2] = [490., 590.];
+
+// a wavelength in the visible range, uniformly
+pub fn sample_wavelength(u: f64) -> f64 {
+    return LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN);
+}
+
+// One lobe of the fit below, a gaussian with different widths either side.
+fn lobe(lambda: f64, mean: f64, below: f64, above: f64) -> f64 {
+    let t = (lambda - mean) / if lambda < mean { below } else { above };
+    return (-0.5 * t * t).exp();
+}
+
+// The CIE 1931 2° colour matching functions, from the multi-lobe fit in
+// Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ
+// Color Matching Functions".
+pub fn xyz_matching(lambda: f64) -> Color {
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    return vec3(x, y, z);
+}
+
+pub fn xyz_to_rgb(xyz: Color) -> Color {
+    // linear sRGB with a D65 white point, the matrix is column major
+    let m = Matrix3::new(
+        3.2404542, -0.9692660, 0.0556434, -1.5371385, 1.8760108, -0.2040259, -0.4985314, 0.0415560,
+        1.0572252,
+    );
+    return m * xyz;
+}
+
+struct Tables {
+    // the integral of Y over the visible range
+    y_integral: f64,
+    // from an RGB colour to the value of its spectrum in each band, red first
+    to_bands: Matrix3<f64>,
+}
+
+fn band(lambda: f64) -> usize {
+    if lambda >= BAND_EDGES[1] {
+        return 0;
+    }
+    if lambda >= BAND_EDGES[0] {
+        return 1;
+    }
+    return 2;
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    return TABLES.get_or_init(|| {
+        // column k is the RGB of a spectrum that is 1 in band k
+        let mut from_bands = [vec3(0., 0., 0.); 3];
+        let mut y_integral = 0.;
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + i as f64 + 0.5;
+            let xyz = xyz_matching(lambda);
+            from_bands[band(lambda)] += xyz_to_rgb(xyz);
+            y_integral += xyz.y;
+        }
+        let from_bands =
+            Matrix3::from_cols(from_bands[0], from_bands[1], from_bands[2]) / y_integral;
+        return Tables {
+            y_integral,
+            to_bands: from_bands.invert().unwrap(),
+        };
+    });
+}
+
+// The value at `lambda` of the spectrum standing in for `color`. Colours
+// more saturated than the three bands can mix come out slightly negative in
+// a band, which is clamped so that no path carries negative light; those
+// colours lose a little saturation when dispersed.
+pub fn spectrum_value(color: Color, lambda: f64) -> f64 {
+    return (tables().to_bands * color)[band(lambda)].max(0.);
+}
+
+// The colour of light at a single wavelength, scaled so that averaging it
+// times spectrum_value over uniformly sampled wavelengths gives back the
+// colour the spectrum came from.
+pub fn wavelength_color(lambda: f64) -> Color {
+    let tables = tables();
+    return xyz_to_rgb(xyz_matching(lambda)) * (LAMBDA_MAX - LAMBDA_MIN) / tables.y_integral;
+}
+
+// Index of refraction as a function of wavelength.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    // n = a + b / λ², with λ in micrometres
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ b λ² / (λ² - c), with λ in micrometres
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    pub fn at(&self, lambda: f64) -> f64 {
+        let l2 = (lambda / 1000.).powi(2);
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => (1. + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>())
+                .max(0.)
+                .sqrt(),
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::InnerSpace;
+
+    use super::*;
+    use crate::{render::luminance, rng::Rng};
+
+    #[test]
+    fn test_colors_round_trip() {
+        // a flat spectrum is about white, a little red since sRGB white is D65
+        let mut flat = vec3(0., 0., 0.);
+        let n = 100_000;
+        let mut rng = Rng::new(3);
+        for _ in 0..n {
+            flat += wavelength_color(sample_wavelength(rng.uniform()));
+        }
+        flat /= n as f64;
+        assert!((luminance(flat) - 1.).abs() < 0.02, "{:?}", flat);
+        assert!(flat.x > flat.z, "{:?}", flat);
+
+        // evenly spaced wavelengths average every colour back to itself
+        for color in [vec3(1., 1., 1.), vec3(0.8, 0.1, 0.05), vec3(0.2, 0.5, 0.9)] {
+            let steps = 4700;
+            let sum = (0..steps)
+                .map(|i| {
+                    let lambda = sample_wavelength((i as f64 + 0.5) / steps as f64);
+                    wavelength_color(lambda) * spectrum_value(color, lambda)
+                })
+                .fold(vec3(0., 0., 0.), |a, b| a + b);
+            let mean = sum / steps as f64;
+            assert!(
+                (mean - color).magnitude() < 1e-3,
+                "{:?} vs {:?}",
+                mean,
+                color
+            );
+        }
+
+        // pure green and blue would dip below zero in the red band unclamped
+        for color in [vec3(1., 0., 0.), vec3(0., 1., 0.), vec3(0., 0., 1.)] {
+            for lambda in [400., 540., 700.] {
+                assert!(
+                    spectrum_value(color, lambda) >= 0.,
+                    "{:?} {}",
+                    color,
+                    lambda
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ior() {
+        // BK7 glass is 1.5168 at the d-line in both forms
+        let cauchy = Ior::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+        };
+        let sellmeier = Ior::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        for ior in [cauchy, sellmeier] {
+            assert!(
+                (ior.at(LAMBDA_D) - 1.5168).abs() < 1e-3,
+                "{}",
+                ior.at(LAMBDA_D)
+            );
+            // blue bends more than red
+            assert!(ior.at(450.) > ior.at(650.));
+            assert!(ior.is_dispersive());
+        }
+        assert_eq!(Ior::Constant(1.5).at(400.), 1.5);
+    }
+}
@@ -0,0 +1,159 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{bail, Result};
+use cgmath::Point3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    image::{self, Framebuffer},
+    Color,
+};
+
+use super::Texture;
+
+// What happens to uv coordinates outside of [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    // maps a texel index onto 0..n
+    fn apply(&self, i: i64, n: u32) -> u32 {
+        let n = n as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.clamp(0, n - 1),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n {
+                    m
+                } else {
+                    2 * n - 1 - m
+                }
+            }
+        };
+        return i as u32;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+// An image stretched over the uv square, with v pointing up.
+pub struct ImageTexture {
+    image: Framebuffer,
+    wrap: WrapMode,
+    filter: Filter,
+}
+
+impl ImageTexture {
+    pub fn new(image: Framebuffer, wrap: WrapMode, filter: Filter) -> Result<Arc<Self>> {
+        if image.width() == 0 || image.height() == 0 {
+            bail!("texture images must not be empty");
+        }
+        return Ok(Arc::new(Self {
+            image,
+            wrap,
+            filter,
+        }));
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, wrap: WrapMode, filter: Filter) -> Result<Arc<Self>> {
+        return Self::new(image::load(path)?, wrap, filter);
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        return self.image.get(
+            self.wrap.apply(x, self.image.width()),
+            self.wrap.apply(y, self.image.height()),
+        );
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point3<f64>) -> Color {
+        // in texel units, rows are stored top first
+        let x = u * self.image.width() as f64;
+        let y = (1. - v) * self.image.height() as f64;
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // texel centres sit at half integers
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.texel(x0, y0) * (1. - fx) + self.texel(x0 + 1, y0) * fx;
+                let bottom = self.texel(x0, y0 + 1) * (1. - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+                top * (1. - fy) + bottom * fy
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::vec3;
+
+    use super::*;
+
+    // black on the left, white on the right
+    fn texture(wrap: WrapMode, filter: Filter) -> Arc<ImageTexture> {
+        let pixels = vec![
+            vec3(0., 0., 0.),
+            vec3(1., 1., 1.),
+            vec3(0., 0., 0.),
+            vec3(1., 1., 1.),
+        ];
+        return ImageTexture::new(
+            Framebuffer::from_pixels(2, 2, pixels).unwrap(),
+            wrap,
+            filter,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_bilinear_filtering() {
+        let tex = texture(WrapMode::Clamp, Filter::Bilinear);
+        let at = |u| tex.value(u, 0.5, Point3::new(0., 0., 0.)).x;
+        assert_eq!(at(0.25), 0.);
+        assert_eq!(at(0.75), 1.);
+        assert!((at(0.5) - 0.5).abs() < 1e-12);
+        assert!((at(0.375) - 0.25).abs() < 1e-12);
+        // clamped past the edges
+        assert_eq!(at(0.1), 0.);
+        assert_eq!(at(1.5), 1.);
+
+        let nearest = texture(WrapMode::Clamp, Filter::Nearest);
+        assert_eq!(nearest.value(0.49, 0.5, Point3::new(0., 0., 0.)).x, 0.);
+    }
+
+    #[test]
+    fn test_wrap_modes() {
+        let p = Point3::new(0., 0., 0.);
+        let repeat = texture(WrapMode::Repeat, Filter::Nearest);
+        assert_eq!(repeat.value(1.25, 0.5, p).x, 0.);
+        assert_eq!(repeat.value(-0.25, 0.5, p).x, 1.);
+        let mirror = texture(WrapMode::Mirror, Filter::Nearest);
+        assert_eq!(mirror.value(1.25, 0.5, p).x, 1.);
+        assert_eq!(mirror.value(-0.25, 0.5, p).x, 0.);
+        // bilinear filtering blends across the seam when repeating
+        let repeat = texture(WrapMode::Repeat, Filter::Bilinear);
+        assert!((repeat.value(0., 0.5, p).x - 0.5).abs() < 1e-12);
+
+        assert_eq!(WrapMode::Mirror.apply(-1, 3), 0);
+        assert_eq!(WrapMode::Mirror.apply(5, 3), 0);
+        assert_eq!(WrapMode::Mirror.apply(7, 3), 1);
+    }
+}
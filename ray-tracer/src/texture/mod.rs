@@ -0,0 +1,82 @@
+mod image_texture;
+mod perlin;
+
+use std::sync::Arc;
+
+use cgmath::Point3;
+
+use crate::Color;
+
+pub use self::{
+    image_texture::{Filter, ImageTexture, WrapMode},
+    perlin::{NoiseStyle, NoiseTexture, Perlin},
+};
+
+// A colour that varies over a surface, looked up with the hit's (u, v) and
+// its position in space.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: Point3<f64>) -> Color;
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Arc<Self> {
+        return Arc::new(Self { color });
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: Point3<f64>) -> Color {
+        self.color
+    }
+}
+
+// Alternates between two textures in cubes of side `scale`, so it works on
+// any shape without needing uv coordinates.
+pub struct CheckerTexture {
+    scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Arc<Self> {
+        return Arc::new(Self { scale, even, odd });
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Point3<f64>) -> Color {
+        let cell = |x: f64| (x / self.scale).floor() as i64;
+        if (cell(p.x) + cell(p.y) + cell(p.z)).rem_euclid(2) == 0 {
+            return self.even.value(u, v, p);
+        }
+        return self.odd.value(u, v, p);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::vec3;
+
+    use super::*;
+
+    #[test]
+    fn test_checker_alternates() {
+        let checker = CheckerTexture::new(
+            0.5,
+            SolidColor::new(vec3(1., 1., 1.)),
+            SolidColor::new(vec3(0., 0., 0.)),
+        );
+        let at = |x, y, z| checker.value(0., 0., Point3::new(x, y, z)).x;
+        assert_eq!(at(0.1, 0.1, 0.1), 1.);
+        assert_eq!(at(0.6, 0.1, 0.1), 0.);
+        assert_eq!(at(0.6, 0.6, 0.1), 1.);
+        // cells continue across the origin
+        assert_eq!(at(-0.1, 0.1, 0.1), 0.);
+        assert_eq!(at(-0.1, -0.1, 0.1), 1.);
+    }
+}
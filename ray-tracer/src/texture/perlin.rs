@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use cgmath::{vec3, EuclideanSpace, InnerSpace, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{rng::Rng, Color};
+
+use super::Texture;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise on the integer lattice, with random unit vectors at the
+// lattice points. The seed makes the pattern repeatable between renders.
+pub struct Perlin {
+    ranvec: Vec<Vector3<f64>>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let ranvec = (0..POINT_COUNT)
+            .map(|_| loop {
+                let v = vec3(
+                    rng.range(-1. ..1.),
+                    rng.range(-1. ..1.),
+                    rng.range(-1. ..1.),
+                );
+                let len2 = v.magnitude2();
+                if len2 > 1e-6 && len2 <= 1. {
+                    return v.normalize();
+                }
+            })
+            .collect();
+        let mut perm = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            for i in (1..POINT_COUNT).rev() {
+                p.swap(i, rng.index(i + 1));
+            }
+            p
+        };
+        let (perm_x, perm_y, perm_z) = (perm(), perm(), perm());
+        return Self {
+            ranvec,
+            perm_x,
+            perm_y,
+            perm_z,
+        };
+    }
+
+    // smooth noise in roughly [-1, 1]
+    pub fn noise(&self, p: Point3<f64>) -> f64 {
+        let floor = p.map(|x| x.floor());
+        let f = p - floor;
+        let (i, j, k) = (floor.x as i64, floor.y as i64, floor.z as i64);
+        let wrap = |x: i64| (x & (POINT_COUNT as i64 - 1)) as usize;
+
+        // hermite smoothing hides the lattice
+        let s = f.map(|x| x * x * (3. - 2. * x));
+        let mut accum = 0.;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let idx = self.perm_x[wrap(i + di)]
+                        ^ self.perm_y[wrap(j + dj)]
+                        ^ self.perm_z[wrap(k + dk)];
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = f - vec3(fi, fj, fk);
+                    accum += (fi * s.x + (1. - fi) * (1. - s.x))
+                        * (fj * s.y + (1. - fj) * (1. - s.y))
+                        * (fk * s.z + (1. - fk) * (1. - s.z))
+                        * self.ranvec[idx].dot(weight);
+                }
+            }
+        }
+        return accum;
+    }
+
+    // sum of `depth` octaves of |noise|, each at twice the frequency and half the weight
+    pub fn turbulence(&self, p: Point3<f64>, depth: u32) -> f64 {
+        let mut accum = 0.;
+        let mut p = p.to_vec();
+        let mut weight = 1.;
+        for _ in 0..depth {
+            accum += weight * self.noise(Point3::from_vec(p));
+            weight *= 0.5;
+            p *= 2.;
+        }
+        return accum.abs();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseStyle {
+    // plain noise remapped to [0, 1]
+    #[default]
+    Smooth,
+    Turbulence,
+    // stripes along z distorted by turbulence
+    Marble,
+}
+
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+    style: NoiseStyle,
+    depth: u32,
+    color: Color,
+}
+
+impl NoiseTexture {
+    // `scale` is the frequency of the pattern, `depth` the number of turbulence octaves
+    pub fn new(seed: u64, scale: f64, style: NoiseStyle, depth: u32, color: Color) -> Arc<Self> {
+        return Arc::new(Self {
+            noise: Perlin::new(seed),
+            scale,
+            style,
+            depth,
+            color,
+        });
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: Point3<f64>) -> Color {
+        let scaled = Point3::from_vec(self.scale * p.to_vec());
+        let t = match self.style {
+            NoiseStyle::Smooth => 0.5 * (1. + self.noise.noise(scaled)),
+            NoiseStyle::Turbulence => self.noise.turbulence(scaled, self.depth),
+            NoiseStyle::Marble => {
+                0.5 * (1. + (scaled.z + 10. * self.noise.turbulence(p, self.depth)).sin())
+            }
+        };
+        return t.clamp(0., 1.) * self.color;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_noise_is_smooth_and_seeded() {
+        let a = Perlin::new(7);
+        let b = Perlin::new(7);
+        let c = Perlin::new(8);
+        let mut differs = false;
+        for i in 0..200 {
+            let p = Point3::new(i as f64 * 0.37, i as f64 * 0.11 - 5., 3. - i as f64 * 0.23);
+            let n = a.noise(p);
+            assert!(n.abs() <= 1.5, "{}", n);
+            assert_eq!(n, b.noise(p));
+            differs |= n != c.noise(p);
+            // continuous: a tiny step changes the value a tiny bit
+            assert!((a.noise(p + vec3(1e-6, 0., 0.)) - n).abs() < 1e-4);
+        }
+        assert!(differs);
+        // zero on the lattice itself
+        assert_eq!(a.noise(Point3::new(3., -2., 5.)), 0.);
+    }
+}
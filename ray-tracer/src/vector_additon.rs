@@ -1,14 +1,19 @@
-use std::ops::Range;
+use std::{
+    f64::consts::{FRAC_PI_2, FRAC_PI_4, PI},
+    ops::Range,
+};
 
 use cgmath::{vec3, InnerSpace, Vector3};
 
-use crate::random;
+use crate::rng::Rng;
 
 pub trait VectorAdditions {
-    fn random(range: Range<f64>) -> Self;
-    fn random_in_unit_sphere() -> Self;
-    fn random_in_hemisphere(normal: Vector3<f64>) -> Self;
-    fn random_in_unit_disk() -> Self;
+    fn random(range: Range<f64>, rng: &mut Rng) -> Self;
+    // Warps of uniform samples from [0, 1)^2 that keep their stratification,
+    // unlike rejection sampling.
+    fn sample_unit_sphere(u: [f64; 2]) -> Self;
+    fn sample_unit_ball(u: [f64; 2], radius: f64) -> Self;
+    fn sample_unit_disk(u: [f64; 2]) -> Self;
     fn near_zero(&self) -> bool;
     fn reflect(&self, n: Vector3<f64>) -> Self;
     fn refract(&self, n: Vector3<f64>, etai_over_etat: f64) -> Self;
@@ -16,32 +21,28 @@ pub trait VectorAdditions {
 
 impl VectorAdditions for Vector3<f64> {
     #[inline]
-    fn random(range: Range<f64>) -> Self {
+    fn random(range: Range<f64>, rng: &mut Rng) -> Self {
         vec3(
-            random(range.clone()),
-            random(range.clone()),
-            random(range.clone()),
+            rng.range(range.clone()),
+            rng.range(range.clone()),
+            rng.range(range.clone()),
         )
     }
 
+    // uniform on the surface
     #[inline]
-    fn random_in_unit_sphere() -> Self {
-        loop {
-            let p = Self::random(-1. ..1.);
-            if p.magnitude2() >= 1. {
-                continue;
-            }
-            return p;
-        }
+    fn sample_unit_sphere(u: [f64; 2]) -> Self {
+        let z = 1. - 2. * u[0];
+        let r = (1. - z * z).max(0.).sqrt();
+        let phi = 2. * PI * u[1];
+        return vec3(r * phi.cos(), r * phi.sin(), z);
     }
 
+    // uniform in the volume, the uniform sample `radius` picks the distance
+    // from the centre
     #[inline]
-    fn random_in_hemisphere(normal: Vector3<f64>) -> Self {
-        let in_unit_sphere = Self::random_in_unit_sphere();
-        if in_unit_sphere.dot(normal) > 0. {
-            return in_unit_sphere;
-        }
-        return -in_unit_sphere;
+    fn sample_unit_ball(u: [f64; 2], radius: f64) -> Self {
+        return radius.cbrt() * Self::sample_unit_sphere(u);
     }
 
     #[inline]
@@ -62,13 +63,17 @@ impl VectorAdditions for Vector3<f64> {
         return r_out_perp + r_out_parallel;
     }
 
-    fn random_in_unit_disk() -> Self {
-        loop {
-            let p = vec3(random(-1. ..1.), random(-1. ..1.), 0.);
-            if p.magnitude2() >= 1. {
-                continue;
-            }
-            return p;
+    // Shirley and Chiu's concentric mapping, in the xy plane
+    fn sample_unit_disk(u: [f64; 2]) -> Self {
+        let (a, b) = (2. * u[0] - 1., 2. * u[1] - 1.);
+        if a == 0. && b == 0. {
+            return vec3(0., 0., 0.);
         }
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, FRAC_PI_4 * (b / a))
+        } else {
+            (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+        };
+        return vec3(r * theta.cos(), r * theta.sin(), 0.);
     }
 }